@@ -0,0 +1,467 @@
+//! Output volume of the default audio device
+//!
+//! On Linux this talks to the sound server through libpulse, which PipeWire
+//! serves as well via pipewire-pulse. libpulse is linked, so it must be
+//! installed even where no sound server runs; then `amixer` on the Master
+//! control is used instead. macOS goes through AppleScript's volume
+//! settings and Windows through the Core Audio endpoint of the default
+//! render device. Neither of those can go above 100%.
+//!
+//! None of these report changes made elsewhere, so [`poll_change`] is called
+//! periodically to notice the PC's own volume keys or mixer.
+
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Highest level volume up goes to unless a command allows more
+pub const DEFAULT_MAX_VOLUME: u8 = 100;
+/// Highest level a command may ask for, above 100% the signal is amplified
+pub const MAX_VOLUME: u8 = 150;
+
+/// State last changed through this app or seen by `poll_change`
+static LAST_KNOWN: Mutex<Option<VolumeState>> = Mutex::new(None);
+
+/// How long a reading is reused, so the probes and the change watcher of
+/// one poll share a single query of the sound server
+const READING_TTL: Duration = Duration::from_millis(500);
+
+static LAST_READING: Mutex<Option<(Instant, VolumeState)>> = Mutex::new(None);
+
+/// Level and mute state of the default output
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct VolumeState {
+    /// Volume in percent of the normal level
+    pub level: u8,
+    pub muted: bool,
+}
+
+/// A change to the default output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VolumeChange {
+    /// Absolute level in percent
+    Set(u8),
+    /// Relative change in percent, stepping up stops at `max`
+    Step { delta: i16, max: u8 },
+    /// Mute or unmute, toggling when `None`
+    Mute(Option<bool>),
+}
+
+impl VolumeChange {
+    /// Level after applying a set or step to the current level
+    fn level(self, current: u8) -> u8 {
+        match self {
+            VolumeChange::Set(level) => level,
+            // Never lower a level that is already above the cap
+            VolumeChange::Step { delta, max } if delta > 0 => {
+                (current as i16 + delta).min(max.max(current) as i16) as u8
+            }
+            VolumeChange::Step { delta, .. } => (current as i16 + delta).max(0) as u8,
+            VolumeChange::Mute(_) => current,
+        }
+    }
+}
+
+/// Read the level and mute state of the default output
+pub fn get_volume() -> Result<VolumeState, String> {
+    let mut reading = LAST_READING.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((at, state)) = *reading {
+        if at.elapsed() < READING_TTL {
+            return Ok(state);
+        }
+    }
+    let state = read()?;
+    *reading = Some((Instant::now(), state));
+    Ok(state)
+}
+
+fn read() -> Result<VolumeState, String> {
+    #[cfg(target_os = "linux")]
+    {
+        pulse::get_volume().or_else(|e| alsa::get_volume().map_err(|alsa_err| format!("{}; {}", e, alsa_err)))
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        applescript::get_volume()
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        core_audio::get_volume()
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        Err("Volume control not supported on this platform".to_string())
+    }
+}
+
+/// Change the default output and return its new state
+pub fn change_volume(change: VolumeChange) -> Result<VolumeState, String> {
+    let state = apply(change)?;
+    *LAST_READING.lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), state));
+    remember(state);
+    Ok(state)
+}
+
+/// Read the default output, returning its state if it changed since the last
+/// change made here or the last call
+///
+/// Changes made through this app are published when they happen, so only the
+/// ones from outside show up here.
+pub fn poll_change() -> Result<Option<VolumeState>, String> {
+    let state = get_volume()?;
+    Ok(remember(state).then_some(state))
+}
+
+/// Record the current state, returning whether it differs from the last known one
+fn remember(state: VolumeState) -> bool {
+    let mut last = LAST_KNOWN.lock().unwrap_or_else(|e| e.into_inner());
+    last.replace(state) != Some(state)
+}
+
+fn apply(change: VolumeChange) -> Result<VolumeState, String> {
+    #[cfg(target_os = "linux")]
+    {
+        pulse::change_volume(change)
+            .or_else(|e| alsa::change_volume(change).map_err(|alsa_err| format!("{}; {}", e, alsa_err)))
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        applescript::change_volume(change)
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        core_audio::change_volume(change)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        let _ = change;
+        Err("Volume control not supported on this platform".to_string())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod pulse {
+    use super::{VolumeChange, VolumeState};
+    use libpulse_binding::callbacks::ListResult;
+    use libpulse_binding::context::{Context, FlagSet, State};
+    use libpulse_binding::mainloop::standard::{IterateResult, Mainloop};
+    use libpulse_binding::operation::{Operation, State as OperationState};
+    use libpulse_binding::volume::{ChannelVolumes, Volume};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    const DEFAULT_SINK: &str = "@DEFAULT_SINK@";
+
+    /// A connection to the sound server driven by its own main loop
+    struct Connection {
+        mainloop: Mainloop,
+        context: Context,
+    }
+
+    impl Connection {
+        fn open() -> Result<Self, String> {
+            let mut mainloop = Mainloop::new().ok_or("PulseAudio: failed to create main loop")?;
+            let mut context = Context::new(&mainloop, "deck").ok_or("PulseAudio: failed to create context")?;
+            context
+                .connect(None, FlagSet::NOFLAGS, None)
+                .map_err(|e| format!("PulseAudio: failed to connect: {}", e))?;
+            loop {
+                iterate(&mut mainloop)?;
+                match context.get_state() {
+                    State::Ready => break,
+                    State::Failed | State::Terminated => {
+                        return Err("PulseAudio: no sound server is running".to_string());
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Self { mainloop, context })
+        }
+
+        fn wait<T: ?Sized>(&mut self, operation: Operation<T>) -> Result<(), String> {
+            while operation.get_state() == OperationState::Running {
+                iterate(&mut self.mainloop)?;
+            }
+            match operation.get_state() {
+                OperationState::Done => Ok(()),
+                _ => Err("PulseAudio: operation was cancelled".to_string()),
+            }
+        }
+
+        fn sink(&mut self) -> Result<(ChannelVolumes, bool), String> {
+            let found = Rc::new(RefCell::new(None));
+            let slot = found.clone();
+            let operation = self
+                .context
+                .introspect()
+                .get_sink_info_by_name(DEFAULT_SINK, move |result| {
+                    if let ListResult::Item(sink) = result {
+                        *slot.borrow_mut() = Some((sink.volume, sink.mute));
+                    }
+                });
+            self.wait(operation)?;
+            found.take().ok_or_else(|| "PulseAudio: no default output device".to_string())
+        }
+
+        fn set_volume(&mut self, volumes: &ChannelVolumes) -> Result<(), String> {
+            let done = Rc::new(Cell::new(false));
+            let flag = done.clone();
+            let operation = self.context.introspect().set_sink_volume_by_name(
+                DEFAULT_SINK,
+                volumes,
+                Some(Box::new(move |success| flag.set(success))),
+            );
+            self.wait(operation)?;
+            done.get().then_some(()).ok_or_else(|| "PulseAudio: failed to set volume".to_string())
+        }
+
+        fn set_mute(&mut self, muted: bool) -> Result<(), String> {
+            let done = Rc::new(Cell::new(false));
+            let flag = done.clone();
+            let operation = self.context.introspect().set_sink_mute_by_name(
+                DEFAULT_SINK,
+                muted,
+                Some(Box::new(move |success| flag.set(success))),
+            );
+            self.wait(operation)?;
+            done.get().then_some(()).ok_or_else(|| "PulseAudio: failed to set mute".to_string())
+        }
+
+        fn state(&mut self) -> Result<VolumeState, String> {
+            let (volumes, muted) = self.sink()?;
+            Ok(VolumeState {
+                level: to_percent(volumes.max()),
+                muted,
+            })
+        }
+    }
+
+    impl Drop for Connection {
+        fn drop(&mut self) {
+            self.context.disconnect();
+        }
+    }
+
+    fn iterate(mainloop: &mut Mainloop) -> Result<(), String> {
+        match mainloop.iterate(true) {
+            IterateResult::Success(_) => Ok(()),
+            IterateResult::Quit(_) => Err("PulseAudio: main loop quit".to_string()),
+            IterateResult::Err(e) => Err(format!("PulseAudio: {}", e)),
+        }
+    }
+
+    fn to_percent(volume: Volume) -> u8 {
+        let normal = Volume::NORMAL.0 as u64;
+        ((volume.0 as u64 * 100 + normal / 2) / normal).min(u8::MAX as u64) as u8
+    }
+
+    fn from_percent(percent: u8) -> Volume {
+        Volume((percent as u64 * Volume::NORMAL.0 as u64 / 100) as u32)
+    }
+
+    pub fn get_volume() -> Result<VolumeState, String> {
+        Connection::open()?.state()
+    }
+
+    pub fn change_volume(change: VolumeChange) -> Result<VolumeState, String> {
+        let mut connection = Connection::open()?;
+        let (mut volumes, muted) = connection.sink()?;
+        match change {
+            VolumeChange::Mute(target) => connection.set_mute(target.unwrap_or(!muted))?,
+            _ => {
+                let level = change.level(to_percent(volumes.max()));
+                // Scaling keeps the balance between channels
+                volumes.scale(from_percent(level));
+                connection.set_volume(&volumes)?;
+            }
+        }
+        connection.state()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod alsa {
+    use super::{VolumeChange, VolumeState};
+    use std::process::Command;
+
+    fn amixer(args: &[&str]) -> Result<VolumeState, String> {
+        let output = Command::new("amixer")
+            .args(args)
+            .output()
+            .map_err(|e| format!("Failed to run amixer: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("amixer failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        parse(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Read a state from lines like "Front Left: Playback 32768 [50%] [on]"
+    fn parse(output: &str) -> Result<VolumeState, String> {
+        let line = output
+            .lines()
+            .find(|line| line.contains("Playback") && line.contains('%'))
+            .ok_or("amixer: unexpected output")?;
+        let fields: Vec<&str> = line
+            .split('[')
+            .filter_map(|part| part.split(']').next())
+            .collect();
+        let level = fields
+            .iter()
+            .find_map(|f| f.strip_suffix('%').and_then(|p| p.parse().ok()))
+            .ok_or("amixer: unexpected output")?;
+        Ok(VolumeState {
+            level,
+            muted: fields.contains(&"off"),
+        })
+    }
+
+    pub fn get_volume() -> Result<VolumeState, String> {
+        amixer(&["get", "Master"])
+    }
+
+    pub fn change_volume(change: VolumeChange) -> Result<VolumeState, String> {
+        let value = match change {
+            VolumeChange::Mute(None) => "toggle".to_string(),
+            VolumeChange::Mute(Some(true)) => "mute".to_string(),
+            VolumeChange::Mute(Some(false)) => "unmute".to_string(),
+            _ => format!("{}%", change.level(get_volume()?.level)),
+        };
+        amixer(&["set", "Master", &value])
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod applescript {
+    use super::{VolumeChange, VolumeState};
+    use std::process::Command;
+
+    fn osascript(script: &str) -> Result<String, String> {
+        let output = Command::new("osascript")
+            .args(["-e", script])
+            .output()
+            .map_err(|e| format!("Failed to run osascript: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    pub fn get_volume() -> Result<VolumeState, String> {
+        // Output looks like "50,false"; outputs without a volume report "missing value"
+        let output = osascript(
+            r#"set settings to get volume settings
+            return ((output volume of settings) as text) & "," & ((output muted of settings) as text)"#,
+        )?;
+        let (level, muted) = output.split_once(',').ok_or("Unexpected volume output")?;
+        Ok(VolumeState {
+            level: level
+                .parse()
+                .map_err(|_| format!("Output device has no volume ({})", level))?,
+            muted: muted == "true",
+        })
+    }
+
+    pub fn change_volume(change: VolumeChange) -> Result<VolumeState, String> {
+        let current = get_volume()?;
+        let script = match change {
+            VolumeChange::Mute(target) => {
+                format!("set volume output muted {}", target.unwrap_or(!current.muted))
+            }
+            _ => format!("set volume output volume {}", change.level(current.level).min(100)),
+        };
+        osascript(&script)?;
+        get_volume()
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod core_audio {
+    use super::{VolumeChange, VolumeState};
+    use windows::Win32::Media::Audio::Endpoints::IAudioEndpointVolume;
+    use windows::Win32::Media::Audio::{eConsole, eRender, IMMDeviceEnumerator, MMDeviceEnumerator};
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_ALL, COINIT_MULTITHREADED,
+    };
+
+    /// Keeps COM initialized on this thread for as long as it lives
+    struct ComGuard {
+        initialized: bool,
+    }
+
+    impl ComGuard {
+        fn new() -> Self {
+            // Fails harmlessly when the thread already uses another apartment model
+            let initialized = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok();
+            Self { initialized }
+        }
+    }
+
+    impl Drop for ComGuard {
+        fn drop(&mut self) {
+            if self.initialized {
+                unsafe { CoUninitialize() };
+            }
+        }
+    }
+
+    /// Volume control of the default render device
+    fn endpoint() -> Result<IAudioEndpointVolume, String> {
+        // SAFETY: COM is initialized by the caller and the interfaces are used on this thread only
+        unsafe {
+            let enumerator: IMMDeviceEnumerator = CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)
+                .map_err(|e| format!("Failed to create device enumerator: {}", e))?;
+            let device = enumerator
+                .GetDefaultAudioEndpoint(eRender, eConsole)
+                .map_err(|e| format!("No default output device: {}", e))?;
+            device
+                .Activate(CLSCTX_ALL, None)
+                .map_err(|e| format!("Failed to open output volume: {}", e))
+        }
+    }
+
+    fn state(endpoint: &IAudioEndpointVolume) -> Result<VolumeState, String> {
+        unsafe {
+            let level = endpoint
+                .GetMasterVolumeLevelScalar()
+                .map_err(|e| format!("Failed to read volume: {}", e))?;
+            let muted = endpoint
+                .GetMute()
+                .map_err(|e| format!("Failed to read mute state: {}", e))?;
+            Ok(VolumeState {
+                level: (level * 100.0).round() as u8,
+                muted: muted.as_bool(),
+            })
+        }
+    }
+
+    pub fn get_volume() -> Result<VolumeState, String> {
+        let _com = ComGuard::new();
+        state(&endpoint()?)
+    }
+
+    pub fn change_volume(change: VolumeChange) -> Result<VolumeState, String> {
+        let _com = ComGuard::new();
+        let endpoint = endpoint()?;
+        let current = state(&endpoint)?;
+        unsafe {
+            match change {
+                VolumeChange::Mute(target) => endpoint
+                    .SetMute(target.unwrap_or(!current.muted), std::ptr::null())
+                    .map_err(|e| format!("Failed to set mute state: {}", e))?,
+                _ => {
+                    let level = change.level(current.level).min(100);
+                    endpoint
+                        .SetMasterVolumeLevelScalar(level as f32 / 100.0, std::ptr::null())
+                        .map_err(|e| format!("Failed to set volume: {}", e))?
+                }
+            }
+        }
+        state(&endpoint)
+    }
+}
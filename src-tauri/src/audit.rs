@@ -0,0 +1,360 @@
+//! Security audit log
+//!
+//! Every entry stores an HMAC-SHA256 of the previous entry's hash and its own
+//! fields, so editing or deleting a row breaks the chain from that point on.
+//! The table itself rejects UPDATE and DELETE through triggers.
+//!
+//! The HMAC key and the last entry written are kept in files next to the
+//! database, not in it. Someone who can change the database but not those files
+//! can neither re-chain edited entries nor cut entries off the end unnoticed.
+//! Someone who can also change the files can rewrite the log undetected.
+
+use crate::commands::CommandConfig;
+use crate::config::Settings;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Previous hash used by the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// File holding the HMAC key, next to the database
+const KEY_FILE: &str = "audit.key";
+
+/// File holding the id and hash of the last entry, next to the database
+const HEAD_FILE: &str = "audit.head";
+
+const KEY_LEN: usize = 32;
+
+/// Failed logins from one address are written at most once per window
+pub const AUTH_FAILURE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Kinds of audited events
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuditEvent {
+    AuthFailed,
+    AuthCodeRegenerated,
+    SettingsSaved,
+    CommandsSaved,
+    CommandImageSet,
+    CommandImageRemoved,
+    ScheduleSaved,
+    ScheduleDeleted,
+    ScheduleEnabledChanged,
+    HistoryCleared,
+    ServerStarted,
+    ServerStopped,
+}
+
+impl AuditEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEvent::AuthFailed => "auth_failed",
+            AuditEvent::AuthCodeRegenerated => "auth_code_regenerated",
+            AuditEvent::SettingsSaved => "settings_saved",
+            AuditEvent::CommandsSaved => "commands_saved",
+            AuditEvent::CommandImageSet => "command_image_set",
+            AuditEvent::CommandImageRemoved => "command_image_removed",
+            AuditEvent::ScheduleSaved => "schedule_saved",
+            AuditEvent::ScheduleDeleted => "schedule_deleted",
+            AuditEvent::ScheduleEnabledChanged => "schedule_enabled_changed",
+            AuditEvent::HistoryCleared => "history_cleared",
+            AuditEvent::ServerStarted => "server_started",
+            AuditEvent::ServerStopped => "server_stopped",
+        }
+    }
+}
+
+/// A stored audit log entry
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// RFC 3339 timestamp
+    pub at: String,
+    pub event: String,
+    /// Client IP for network events, "desktop" for changes made in the app
+    pub actor: String,
+    pub details: Value,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+/// Result of checking the hash chain
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditVerification {
+    pub valid: bool,
+    pub entries: usize,
+    /// First entry whose hash doesn't match, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_invalid_id: Option<i64>,
+    /// Whether the last entry recorded outside the database is missing from it
+    pub truncated: bool,
+    /// What the check can't catch, shown alongside the result
+    pub limitation: &'static str,
+}
+
+/// Shown with every verification result, so a valid chain isn't read as more than it is
+pub const VERIFICATION_LIMITATION: &str = "Edits are only detected if the audit key and head \
+     files next to the database were not changed as well";
+
+/// The HMAC key of the chain and the last entry written, kept outside the database
+pub struct AuditSeal {
+    key: Vec<u8>,
+    head_path: PathBuf,
+}
+
+impl AuditSeal {
+    /// Load the key from `dir`, creating it on first use
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        let key_path = dir.join(KEY_FILE);
+        let key = match fs::read(&key_path) {
+            Ok(key) if key.len() == KEY_LEN => key,
+            Ok(_) => return Err(format!("Audit key {} is corrupt", key_path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let key: [u8; KEY_LEN] = rand::random();
+                write_private(&key_path, &key)
+                    .map_err(|e| format!("Failed to create audit key {}: {}", key_path.display(), e))?;
+                key.to_vec()
+            }
+            Err(e) => return Err(format!("Failed to read audit key {}: {}", key_path.display(), e)),
+        };
+
+        Ok(Self {
+            key,
+            head_path: dir.join(HEAD_FILE),
+        })
+    }
+
+    /// Hash of an entry, chained to the previous one
+    pub fn hash(&self, prev_hash: &str, id: i64, at: &str, event: &str, actor: &str, details: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        for field in [prev_hash, &id.to_string(), at, event, actor, details] {
+            // Length prefix keeps field boundaries unambiguous
+            mac.update(&(field.len() as u64).to_be_bytes());
+            mac.update(field.as_bytes());
+        }
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Id and hash of the last entry written, if any
+    pub fn head(&self) -> Result<Option<(i64, String)>, String> {
+        let head = match fs::read_to_string(&self.head_path) {
+            Ok(head) => head,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read audit head: {}", e)),
+        };
+        head.trim()
+            .split_once(' ')
+            .and_then(|(id, hash)| Some((id.parse().ok()?, hash.to_string())))
+            .map(Some)
+            .ok_or_else(|| format!("Audit head {} is corrupt", self.head_path.display()))
+    }
+
+    /// Record the last entry written
+    pub fn set_head(&self, id: i64, hash: &str) -> Result<(), String> {
+        // Written aside and renamed, so a crash never leaves a half-written head
+        let temp = self.head_path.with_extension("head.tmp");
+        write_private(&temp, format!("{} {}\n", id, hash).as_bytes())
+            .and_then(|_| fs::rename(&temp, &self.head_path))
+            .map_err(|e| format!("Failed to write audit head: {}", e))
+    }
+}
+
+/// Write a file readable only by the current user
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Describe which settings changed, without recording the auth code itself
+pub fn settings_diff(old: &Settings, new: &Settings) -> Value {
+    let mut changed = Vec::new();
+    if old.port != new.port {
+        changed.push(json!({ "field": "port", "from": old.port, "to": new.port }));
+    }
+    if old.auth_code != new.auth_code {
+        changed.push(json!({ "field": "auth_code" }));
+    }
+    if new.history_retention_days.is_some() && old.history_retention_days != new.history_retention_days {
+        changed.push(json!({
+            "field": "history_retention_days",
+            "from": old.history_retention_days,
+            "to": new.history_retention_days,
+        }));
+    }
+    if new.allow_remote_input.is_some() && old.allow_remote_input != new.allow_remote_input {
+        changed.push(json!({
+            "field": "allow_remote_input",
+            "from": old.allow_remote_input,
+            "to": new.allow_remote_input,
+        }));
+    }
+    if new.upload_targets.is_some() && old.upload_targets != new.upload_targets {
+        changed.push(json!({
+            "field": "upload_targets",
+            "from": old.upload_targets,
+            "to": new.upload_targets,
+        }));
+    }
+    if new.max_upload_mb.is_some() && old.max_upload_mb != new.max_upload_mb {
+        changed.push(json!({
+            "field": "max_upload_mb",
+            "from": old.max_upload_mb,
+            "to": new.max_upload_mb,
+        }));
+    }
+    if new.browse_roots.is_some() && old.browse_roots != new.browse_roots {
+        changed.push(json!({
+            "field": "browse_roots",
+            "from": old.browse_roots,
+            "to": new.browse_roots,
+        }));
+    }
+    if new.allow_screen_stream.is_some() && old.allow_screen_stream != new.allow_screen_stream {
+        changed.push(json!({
+            "field": "allow_screen_stream",
+            "from": old.allow_screen_stream,
+            "to": new.allow_screen_stream,
+        }));
+    }
+    if new.stream_fps.is_some() && old.stream_fps != new.stream_fps {
+        changed.push(json!({
+            "field": "stream_fps",
+            "from": old.stream_fps,
+            "to": new.stream_fps,
+        }));
+    }
+    if new.stream_max_width.is_some() && old.stream_max_width != new.stream_max_width {
+        changed.push(json!({
+            "field": "stream_max_width",
+            "from": old.stream_max_width,
+            "to": new.stream_max_width,
+        }));
+    }
+    if new.max_volume.is_some() && old.max_volume != new.max_volume {
+        changed.push(json!({
+            "field": "max_volume",
+            "from": old.max_volume,
+            "to": new.max_volume,
+        }));
+    }
+    json!({ "changed": changed })
+}
+
+/// Describe added, removed and modified commands (with the modified field names)
+pub fn commands_diff(old: &[CommandConfig], new: &[CommandConfig]) -> Value {
+    let to_value = |cmd: &CommandConfig| serde_json::to_value(cmd).unwrap_or(Value::Null);
+
+    let added: Vec<&str> = new
+        .iter()
+        .filter(|n| !old.iter().any(|o| o.id == n.id))
+        .map(|n| n.id.as_str())
+        .collect();
+
+    let removed: Vec<&str> = old
+        .iter()
+        .filter(|o| !new.iter().any(|n| n.id == o.id))
+        .map(|o| o.id.as_str())
+        .collect();
+
+    let changed: Vec<Value> = new
+        .iter()
+        .filter_map(|n| {
+            let o = old.iter().find(|o| o.id == n.id)?;
+            let (old_value, new_value) = (to_value(o), to_value(n));
+            let (old_fields, new_fields) = (old_value.as_object()?, new_value.as_object()?);
+
+            let fields: BTreeSet<&String> = old_fields
+                .keys()
+                .chain(new_fields.keys())
+                .filter(|k| old_fields.get(*k) != new_fields.get(*k))
+                .collect();
+
+            (!fields.is_empty()).then(|| json!({ "id": n.id, "fields": fields }))
+        })
+        .collect();
+
+    json!({ "added": added, "removed": removed, "changed": changed })
+}
+
+/// Coalesces failed authentications per client address
+///
+/// The first failure in a window is logged right away; later ones are only
+/// counted and reported as a single entry once the window has passed, so an
+/// unauthenticated client cannot grow the log faster than two rows a minute.
+#[derive(Default)]
+pub struct AuthFailures {
+    windows: Mutex<HashMap<IpAddr, FailureWindow>>,
+}
+
+struct FailureWindow {
+    started: Instant,
+    repeated: u32,
+}
+
+impl AuthFailures {
+    /// Count a failure
+    ///
+    /// Returns `Some` when the failure opens a new window and should be logged,
+    /// carrying the failures left over from an expired window not yet reported.
+    pub fn record(&self, ip: IpAddr) -> Option<u32> {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        match windows.get_mut(&ip) {
+            Some(window) if window.started.elapsed() < AUTH_FAILURE_WINDOW => {
+                window.repeated = window.repeated.saturating_add(1);
+                None
+            }
+            previous => {
+                let carried = previous.map(|window| window.repeated).unwrap_or(0);
+                windows.insert(ip, FailureWindow { started: Instant::now(), repeated: 0 });
+                Some(carried)
+            }
+        }
+    }
+
+    /// Remove finished windows, returning the failures counted after the logged one
+    pub fn take_expired(&self) -> Vec<(IpAddr, u32)> {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        let expired: Vec<IpAddr> = windows
+            .iter()
+            .filter(|(_, window)| window.started.elapsed() >= AUTH_FAILURE_WINDOW)
+            .map(|(ip, _)| *ip)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|ip| windows.remove(&ip).map(|window| (ip, window.repeated)))
+            .filter(|(_, repeated)| *repeated > 0)
+            .collect()
+    }
+
+    /// Remove all windows, finished or not, returning the failures not yet logged
+    pub fn take_all(&self) -> Vec<(IpAddr, u32)> {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        windows
+            .drain()
+            .map(|(ip, window)| (ip, window.repeated))
+            .filter(|(_, repeated)| *repeated > 0)
+            .collect()
+    }
+}
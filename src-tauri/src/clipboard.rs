@@ -0,0 +1,131 @@
+//! Reading and writing the desktop clipboard
+//!
+//! On Linux, arboard talks to the Wayland compositor through the data-control
+//! protocol when available and falls back to X11 (or XWayland) otherwise.
+//! Clipboard contents are served by the process that set them, so a single
+//! clipboard handle is kept alive for the lifetime of the app.
+
+use arboard::{Clipboard, ImageData};
+use base64::Engine;
+use chrono::Local;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Mutex;
+
+/// Largest image accepted for the clipboard, in bytes (before decoding)
+pub const MAX_CLIPBOARD_BYTES: usize = 10 * 1024 * 1024;
+
+static CLIPBOARD: Mutex<Option<Clipboard>> = Mutex::new(None);
+
+/// Current clipboard contents, as sent to the phone
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClipboardContent {
+    Empty,
+    Text { text: String },
+    /// PNG image, base64-encoded
+    Image { mime_type: String, width: u32, height: u32, data: String },
+}
+
+/// Run `f` with the shared clipboard handle, creating it on first use
+fn with_clipboard<T>(f: impl FnOnce(&mut Clipboard) -> Result<T, arboard::Error>) -> Result<T, String> {
+    let mut guard = CLIPBOARD.lock().map_err(|_| "Clipboard lock poisoned".to_string())?;
+    if guard.is_none() {
+        *guard = Some(Clipboard::new().map_err(|e| format!("Clipboard unavailable: {}", e))?);
+    }
+    let clipboard = guard.as_mut().expect("clipboard was just created");
+    f(clipboard).map_err(|e| format!("Clipboard error: {}", e))
+}
+
+/// Treat "nothing of this kind on the clipboard" as `None` instead of an error
+fn optional<T>(result: Result<T, arboard::Error>) -> Result<Option<T>, arboard::Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(arboard::Error::ContentNotAvailable) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read the clipboard, preferring text over images
+pub fn get_clipboard() -> Result<ClipboardContent, String> {
+    if let Some(text) = with_clipboard(|c| optional(c.get_text()))? {
+        return Ok(ClipboardContent::Text { text });
+    }
+    match with_clipboard(|c| optional(c.get_image()))? {
+        Some(image) => encode_image(image),
+        None => Ok(ClipboardContent::Empty),
+    }
+}
+
+pub fn set_text(text: &str) -> Result<(), String> {
+    with_clipboard(|c| c.set_text(text))
+}
+
+/// Put an encoded image (PNG, JPEG, ...) on the clipboard
+pub fn set_image(bytes: &[u8]) -> Result<(), String> {
+    if bytes.len() > MAX_CLIPBOARD_BYTES {
+        return Err(format!(
+            "Image is too large ({} bytes, maximum is {} bytes)",
+            bytes.len(),
+            MAX_CLIPBOARD_BYTES
+        ));
+    }
+
+    let image = image::load_from_memory(bytes)
+        .map_err(|e| format!("Unsupported image: {}", e))?
+        .to_rgba8();
+    let (width, height) = image.dimensions();
+
+    with_clipboard(|c| {
+        c.set_image(ImageData {
+            width: width as usize,
+            height: height as usize,
+            bytes: Cow::Owned(image.into_raw()),
+        })
+    })
+}
+
+fn encode_image(image: ImageData) -> Result<ClipboardContent, String> {
+    let (width, height) = (image.width as u32, image.height as u32);
+    let rgba = image::RgbaImage::from_raw(width, height, image.bytes.into_owned())
+        .ok_or_else(|| "Clipboard image has an unexpected size".to_string())?;
+
+    let mut png = Vec::new();
+    rgba.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode clipboard image: {}", e))?;
+
+    Ok(ClipboardContent::Image {
+        mime_type: "image/png".to_string(),
+        width,
+        height,
+        data: base64::engine::general_purpose::STANDARD.encode(png),
+    })
+}
+
+/// Built-in variables available to clipboard templates
+///
+/// `${clipboard}` (the current clipboard text) is only read when referenced.
+pub fn template_variables(template: &str) -> HashMap<String, String> {
+    let now = Local::now();
+    let mut vars = HashMap::from([
+        ("date".to_string(), now.format("%Y-%m-%d").to_string()),
+        ("time".to_string(), now.format("%H:%M:%S").to_string()),
+        ("datetime".to_string(), now.to_rfc3339()),
+        ("timestamp".to_string(), now.timestamp().to_string()),
+    ]);
+    // Not from $HOSTNAME, which shells set but don't export to GUI apps
+    let host = gethostname::gethostname();
+    if !host.is_empty() {
+        vars.insert("hostname".to_string(), host.to_string_lossy().into_owned());
+    }
+    if template.contains("${clipboard}") {
+        let current = with_clipboard(|c| optional(c.get_text()))
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        vars.insert("clipboard".to_string(), current);
+    }
+    vars
+}
@@ -1,3 +1,5 @@
+use crate::audio::{DEFAULT_MAX_VOLUME, MAX_VOLUME};
+use crate::window_manager::TitlePattern;
 use serde::{Deserialize, Serialize};
 
 /// Available command types
@@ -14,6 +16,20 @@ pub enum CommandType {
     FocusApp,
     /// Send a keyboard shortcut
     Keybind,
+    /// Alternate between an "on" and an "off" action on each tap
+    Toggle,
+    /// Run a sequence of steps with delays in between
+    Macro,
+    /// Type a piece of text as if entered on the keyboard
+    TypeText,
+    /// Move the pointer, click or scroll
+    Mouse,
+    /// Control a media player (Linux, through MPRIS)
+    Media,
+    /// Put fixed or templated text on the clipboard
+    Clipboard,
+    /// Focus, arrange or close an application window
+    Window,
 }
 
 impl Default for CommandType {
@@ -28,13 +44,495 @@ impl Default for CommandType {
 pub enum VolumeDirection {
     Up,
     Down,
+    /// Toggle mute
     Mute,
+    MuteOn,
+    MuteOff,
+    /// Go to the level in `volume_level`
+    Set,
 }
 
-/// Configuration for a command
+/// Types of steps a macro can contain
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepType {
+    Shell,
+    Volume,
+    OpenDirectory,
+    FocusApp,
+    Keybind,
+    /// Wait before running the next step
+    Delay,
+    /// Run `then_steps` or `else_steps` depending on a condition
+    If,
+}
+
+/// Comparison used by the condition of an If step
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareOp {
+    Equals,
+    NotEquals,
+    Contains,
+    NotContains,
+    /// Numeric comparison, both sides must parse as numbers
+    GreaterThan,
+    LessThan,
+    /// `left` is empty (`right` is ignored)
+    IsEmpty,
+    NotEmpty,
+    /// A process named `left` is running (`right` is ignored)
+    AppRunning,
+}
+
+/// Condition of an If step; both sides may reference variables as `${name}`
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Condition {
+    pub left: String,
+    pub op: CompareOp,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right: Option<String>,
+}
+
+/// What a macro does when one of its steps fails
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorPolicy {
+    /// Abort the macro
+    #[default]
+    Stop,
+    /// Record the failure and run the next step
+    Continue,
+    /// Run the step again, then abort if it still fails
+    Retry,
+}
+
+/// A single step of a macro
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacroStep {
+    pub step_type: StepType,
+
+    /// Shell command to execute (for Shell step)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+
+    /// Volume direction (for Volume step)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_direction: Option<VolumeDirection>,
+
+    /// Volume step amount, default 5 (for Volume step)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_step: Option<u8>,
+
+    /// Absolute volume in percent (for Volume step with Set direction)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_level: Option<u8>,
+
+    /// Highest volume Up may reach in percent, default 100 (for Volume step)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_max: Option<u8>,
+
+    /// Directory path to open (for OpenDirectory step)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub directory_path: Option<String>,
+
+    /// Application name to focus (for FocusApp step)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+
+    /// Key combination to send (for Keybind step)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keybind: Option<String>,
+
+    /// Milliseconds to wait (for Delay step)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay_ms: Option<u64>,
+
+    #[serde(default)]
+    pub on_error: ErrorPolicy,
+
+    /// Extra attempts when `on_error` is Retry, default 2
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+
+    /// Milliseconds to wait between attempts, default 250
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_delay_ms: Option<u64>,
+
+    /// Store the step output (stdout, or the error message on failure) in this variable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture_output: Option<String>,
+
+    /// Store the exit code (0/1 for non-shell steps) in this variable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture_exit_code: Option<String>,
+
+    /// Condition to evaluate (for If step)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Condition>,
+
+    /// Steps run when the condition holds (for If step)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub then_steps: Vec<MacroStep>,
+
+    /// Steps run when the condition does not hold (for If step)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub else_steps: Vec<MacroStep>,
+}
+
+/// Longest single delay a macro step may use (one minute)
+pub const MAX_STEP_DELAY_MS: u64 = 60_000;
+
+/// Deepest nesting of If steps inside a macro
+pub const MAX_STEP_DEPTH: usize = 8;
+
+/// Longest pause between typed keys (one second)
+pub const MAX_KEY_DELAY_MS: u64 = 1_000;
+
+/// Check that a variable name is usable as `${name}`
+pub fn is_valid_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+impl MacroStep {
+    pub fn validate(&self) -> Result<(), String> {
+        self.validate_at_depth(0)
+    }
+
+    fn validate_at_depth(&self, depth: usize) -> Result<(), String> {
+        for name in [&self.capture_output, &self.capture_exit_code].into_iter().flatten() {
+            if !is_valid_variable_name(name) {
+                return Err(format!("Invalid variable name '{}'", name));
+            }
+        }
+
+        match self.step_type {
+            StepType::If => {
+                if depth >= MAX_STEP_DEPTH {
+                    return Err(format!("If steps cannot be nested more than {} deep", MAX_STEP_DEPTH));
+                }
+                if self.condition.is_none() {
+                    return Err("If step needs a condition".to_string());
+                }
+                for (branch, steps) in [("then", &self.then_steps), ("else", &self.else_steps)] {
+                    for (i, step) in steps.iter().enumerate() {
+                        step.validate_at_depth(depth + 1)
+                            .map_err(|e| format!("{} step {}: {}", branch, i + 1, e))?;
+                    }
+                }
+                Ok(())
+            }
+            StepType::Volume => {
+                validate_volume(&self.volume_direction, self.volume_level, self.volume_max)
+            }
+            StepType::Delay => match self.delay_ms {
+                Some(ms) if ms <= MAX_STEP_DELAY_MS => Ok(()),
+                Some(ms) => Err(format!(
+                    "Delay of {}ms is longer than the maximum of {}ms",
+                    ms, MAX_STEP_DELAY_MS
+                )),
+                None => Err("Delay step needs delay_ms".to_string()),
+            },
+            _ => match self.retry_delay_ms {
+                Some(ms) if ms > MAX_STEP_DELAY_MS => Err(format!(
+                    "Retry delay of {}ms is longer than the maximum of {}ms",
+                    ms, MAX_STEP_DELAY_MS
+                )),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+/// Built-in probes used to query the live state of a command
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StateProbe {
+    /// Run a shell command: exit code 0 means "on", trimmed stdout is the value
+    Shell,
+    /// Whether the default output device is muted
+    Muted,
+    /// Whether an application (process name) is running
+    AppRunning,
+    /// Current output volume in percent
+    VolumeLevel,
+}
+
+/// Optional live-state query attached to a command
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StateQuery {
+    pub probe: StateProbe,
+
+    /// Shell command to run (for Shell probe)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+
+    /// Exact process name to look for, ignoring case (for AppRunning probe)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_name: Option<String>,
+
+    /// Re-evaluate every N seconds; when unset the state is only refreshed after execution
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_secs: Option<u64>,
+
+    /// Label shown while the state is on/off, replaces the command name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub off_label: Option<String>,
+
+    /// Icon shown while the state is on/off, replaces the command icon
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_icon: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub off_icon: Option<String>,
+}
+
+/// Evaluated state of a command, as shown on the phone
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommandState {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+}
+
+impl StateQuery {
+    /// Map a raw probe result to the label/icon configured for it
+    pub fn to_state(&self, active: bool, value: Option<String>) -> CommandState {
+        let (label, icon) = if active {
+            (self.on_label.clone(), self.on_icon.clone())
+        } else {
+            (self.off_label.clone(), self.off_icon.clone())
+        };
+        CommandState { active, value, label, icon }
+    }
+}
+
+/// Media player action
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaAction {
+    PlayPause,
+    Play,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    /// Jump forward or back by `seek_offset_secs`
+    Seek,
+}
+
+/// What a mouse command does once the pointer is in place
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseActionKind {
+    Move,
+    Click,
+    DoubleClick,
+    RightClick,
+    MiddleClick,
+    Scroll,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Named point of a monitor the pointer can be sent to
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreenCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+/// Most scroll steps a single mouse command may send
+pub const MAX_SCROLL_AMOUNT: u32 = 100;
+
+/// Pointer action of a Mouse command
+///
+/// The pointer is first moved to `x`/`y` or `corner` if given, then the action
+/// runs at the pointer position.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MouseAction {
+    pub action: MouseActionKind,
+
+    /// Target position in desktop coordinates (offset when `relative` is set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<i32>,
+
+    /// Move by `x`/`y` from the current pointer position
+    #[serde(default)]
+    pub relative: bool,
+
+    /// Move to a corner (or the center) of a monitor instead of `x`/`y`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub corner: Option<ScreenCorner>,
+
+    /// Monitor `corner` refers to, e.g. "HDMI-1"; defaults to the primary monitor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<String>,
+
+    /// Scroll direction (for Scroll action)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scroll_direction: Option<ScrollDirection>,
+
+    /// Number of scroll steps, default 3 (for Scroll action)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scroll_amount: Option<u32>,
+}
+
+impl MouseAction {
+    /// Check that the position fields form a single, complete target
+    pub fn validate(&self) -> Result<(), String> {
+        if self.x.is_some() != self.y.is_some() {
+            return Err("Mouse position needs both x and y".to_string());
+        }
+        let has_position = self.x.is_some();
+        if has_position && self.corner.is_some() {
+            return Err("Mouse position can be x/y or a corner, not both".to_string());
+        }
+        if self.relative && !has_position {
+            return Err("Relative mouse movement needs x and y".to_string());
+        }
+        if self.monitor.is_some() && self.corner.is_none() {
+            return Err("A monitor can only be given together with a corner".to_string());
+        }
+        match self.action {
+            MouseActionKind::Move if !has_position && self.corner.is_none() => {
+                Err("Mouse move needs x/y or a corner".to_string())
+            }
+            MouseActionKind::Scroll if self.scroll_direction.is_none() => {
+                Err("Scroll needs a direction".to_string())
+            }
+            MouseActionKind::Scroll
+                if !(1..=MAX_SCROLL_AMOUNT).contains(&self.scroll_amount.unwrap_or(1)) =>
+            {
+                Err(format!("Scroll amount must be between 1 and {}", MAX_SCROLL_AMOUNT))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What a window command does
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowOperation {
+    Focus,
+    Minimize,
+    Maximize,
+    Close,
+    /// Place the window according to `layout`
+    Layout,
+    MoveToWorkspace,
+    MoveToMonitor,
+}
+
+/// Preset placement of a window on its monitor
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowLayout {
+    LeftHalf,
+    RightHalf,
+    TopHalf,
+    BottomHalf,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    /// Two thirds of the monitor, centered
+    Center,
+    /// The whole monitor, without switching to the maximized state
+    Fill,
+}
+
+/// Window action of a Window command
+///
+/// The window is picked by `window_id`, otherwise by `target`, otherwise the
+/// active window is used.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WindowAction {
+    pub action: WindowOperation,
+
+    /// Exact window id as listed by the window API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_id: Option<String>,
+
+    /// Part of the window title or class to look for (case-insensitive), or `/regex/`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+
+    /// Placement (for Layout action)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<WindowLayout>,
+
+    /// Workspace number, starting at 0 (for MoveToWorkspace action)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<u32>,
+
+    /// Monitor to move to (for MoveToMonitor), or to lay out on instead of the
+    /// window's current one (for Layout)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<String>,
+}
+
+impl WindowAction {
+    /// Check that the action has the fields it needs and no others
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window_id.is_some() && self.target.is_some() {
+            return Err("A window can be picked by id or by target, not both".to_string());
+        }
+        if let Some(ref target) = self.target {
+            TitlePattern::parse(target)?;
+        }
+        if self.layout.is_some() != (self.action == WindowOperation::Layout) {
+            return Err("A layout is needed for, and only used by, the layout action".to_string());
+        }
+        if self.workspace.is_some() != (self.action == WindowOperation::MoveToWorkspace) {
+            return Err("A workspace is needed for, and only used by, moving to a workspace".to_string());
+        }
+        match self.action {
+            WindowOperation::MoveToMonitor if self.monitor.is_none() => {
+                Err("Moving to a monitor needs a monitor".to_string())
+            }
+            WindowOperation::Layout | WindowOperation::MoveToMonitor => Ok(()),
+            _ if self.monitor.is_some() => {
+                Err("A monitor can only be given for layout or moving to a monitor".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Configuration for a command
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CommandConfig {
+    /// Unique identifier, optional only for nested actions
+    #[serde(default)]
     pub id: String,
+    /// Label of the button, optional only for nested actions
+    #[serde(default)]
     pub name: String,
     
     /// The type of command - defaults to "shell" for backward compatibility
@@ -53,6 +551,14 @@ pub struct CommandConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub volume_step: Option<u8>,
     
+    /// Absolute volume in percent (for Volume type with Set direction)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_level: Option<u8>,
+
+    /// Highest volume Up may reach in percent, default 100 (for Volume type)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_max: Option<u8>,
+    
     /// Directory path to open (for OpenDirectory type)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub directory_path: Option<String>,
@@ -64,8 +570,234 @@ pub struct CommandConfig {
     /// Key combination to send (for Keybind type), e.g., "cmd+shift+v"
     #[serde(skip_serializing_if = "Option::is_none")]
     pub keybind: Option<String>,
+
+    /// Text to type or copy, may span several lines (for TypeText and Clipboard types)
+    ///
+    /// For Clipboard, `${date}`, `${time}`, `${datetime}`, `${timestamp}`,
+    /// `${hostname}` and `${clipboard}` are replaced with their current values;
+    /// other `${...}` text is kept as written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+
+    /// Pause between typed keys in milliseconds (for TypeText type)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_delay_ms: Option<u64>,
+
+    /// Pointer action to perform (for Mouse type)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mouse: Option<MouseAction>,
+
+    /// Window to act on and what to do with it (for Window type)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<WindowAction>,
+
+    /// Player action (for Media type)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_action: Option<MediaAction>,
+
+    /// Player to control, e.g. "spotify"; the active player when unset (for Media type)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_player: Option<String>,
+
+    /// Seconds to seek, negative to go back (for Media type with Seek action)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seek_offset_secs: Option<i64>,
     
-    /// Window title to focus after execution (Windows only, deprecated - use FocusApp type instead)
+    /// Window to focus after execution: part of its title or class, or `/regex/` (deprecated - use FocusApp type instead)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub focus_app: Option<String>,
+
+    /// Built-in icon name shown on the button, e.g., "volume-up"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+
+    /// Button background color as a hex string, e.g., "#1e293b"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_color: Option<String>,
+
+    /// Button text/icon color as a hex string, e.g., "#f8fafc"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub foreground_color: Option<String>,
+
+    /// Secondary line of text shown under the name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtitle: Option<String>,
+
+    /// Query used to show live state (e.g. muted/unmuted) on the button
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_query: Option<StateQuery>,
+
+    /// Action run when switching on (for Toggle type); its id and name are ignored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_action: Option<Box<CommandConfig>>,
+
+    /// Action run when switching off (for Toggle type); its id and name are ignored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub off_action: Option<Box<CommandConfig>>,
+
+    /// Steps to run in order (for Macro type)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<MacroStep>,
+}
+
+impl CommandConfig {
+    /// State shown for a toggle, using the labels of its state query if it has one
+    pub fn toggle_state(&self, active: bool) -> CommandState {
+        match self.state_query {
+            Some(ref query) => query.to_state(active, None),
+            None => CommandState {
+                active,
+                value: None,
+                label: None,
+                icon: None,
+            },
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.is_empty() {
+            return Err("Command ID cannot be empty".to_string());
+        }
+        if self.name.trim().is_empty() {
+            return Err(format!("Command '{}' needs a name", self.id));
+        }
+        self.validate_action(&self.id)?;
+        for color in [&self.background_color, &self.foreground_color].into_iter().flatten() {
+            if !is_hex_color(color) {
+                return Err(format!(
+                    "Invalid color '{}' for command '{}', expected #rgb or #rrggbb",
+                    color, self.id
+                ));
+            }
+        }
+        if let Some(ref query) = self.state_query {
+            match query.probe {
+                StateProbe::Shell if query.command.as_deref().unwrap_or("").is_empty() => {
+                    return Err(format!("State query of '{}' needs a command", self.id));
+                }
+                StateProbe::AppRunning if query.app_name.as_deref().unwrap_or("").is_empty() => {
+                    return Err(format!("State query of '{}' needs an app name", self.id));
+                }
+                _ => {}
+            }
+            if query.interval_secs == Some(0) {
+                return Err(format!("State query interval of '{}' must be greater than 0", self.id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the fields the command type needs, for commands and nested actions alike
+    ///
+    /// `label` names the action in errors: the command id, or a path such as
+    /// "lights/on" for the action of a toggle, since nested actions have no id.
+    fn validate_action(&self, label: &str) -> Result<(), String> {
+        if let Some(ref title) = self.focus_app {
+            TitlePattern::parse(title).map_err(|e| format!("Window to focus after '{}': {}", label, e))?;
+        }
+        if self.command_type == CommandType::Toggle {
+            for (which, action) in [("on", &self.on_action), ("off", &self.off_action)] {
+                match action {
+                    Some(action) if action.command_type == CommandType::Toggle => {
+                        return Err(format!(
+                            "The {} action of '{}' cannot itself be a toggle",
+                            which, label
+                        ));
+                    }
+                    Some(action) => action.validate_action(&format!("{}/{}", label, which))?,
+                    None => {
+                        return Err(format!("Toggle '{}' needs an {} action", label, which));
+                    }
+                }
+            }
+        }
+        if self.command_type == CommandType::Shell
+            && self.command.as_deref().unwrap_or("").trim().is_empty()
+        {
+            return Err(format!("Command '{}' needs a shell command", label));
+        }
+        if self.command_type == CommandType::Macro {
+            if self.steps.is_empty() {
+                return Err(format!("Macro '{}' needs at least one step", label));
+            }
+            for (i, step) in self.steps.iter().enumerate() {
+                step.validate()
+                    .map_err(|e| format!("Step {} of macro '{}': {}", i + 1, label, e))?;
+            }
+        }
+        if self.command_type == CommandType::TypeText {
+            if self.text.as_deref().unwrap_or("").is_empty() {
+                return Err(format!("Command '{}' needs text to type", label));
+            }
+            if self.key_delay_ms.unwrap_or_default() > MAX_KEY_DELAY_MS {
+                return Err(format!(
+                    "Key delay of '{}' cannot exceed {}ms",
+                    label, MAX_KEY_DELAY_MS
+                ));
+            }
+        }
+        if self.command_type == CommandType::Clipboard && self.text.as_deref().unwrap_or("").is_empty() {
+            return Err(format!("Command '{}' needs text to copy", label));
+        }
+        if self.command_type == CommandType::Mouse {
+            match self.mouse {
+                Some(ref mouse) => mouse
+                    .validate()
+                    .map_err(|e| format!("Mouse action of '{}': {}", label, e))?,
+                None => return Err(format!("Command '{}' needs a mouse action", label)),
+            }
+        }
+        if self.command_type == CommandType::Window {
+            match self.window {
+                Some(ref window) => window
+                    .validate()
+                    .map_err(|e| format!("Window action of '{}': {}", label, e))?,
+                None => return Err(format!("Command '{}' needs a window action", label)),
+            }
+        }
+        if self.command_type == CommandType::Volume {
+            validate_volume(&self.volume_direction, self.volume_level, self.volume_max)
+                .map_err(|e| format!("Volume command '{}': {}", label, e))?;
+        }
+        if self.command_type == CommandType::Media {
+            match self.media_action {
+                Some(MediaAction::Seek) if self.seek_offset_secs.unwrap_or_default() == 0 => {
+                    return Err(format!("Seek command '{}' needs a non-zero offset", label));
+                }
+                Some(_) => {}
+                None => return Err(format!("Command '{}' needs a media action", label)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that a volume change has the level it needs and stays within range
+fn validate_volume(
+    direction: &Option<VolumeDirection>,
+    level: Option<u8>,
+    max: Option<u8>,
+) -> Result<(), String> {
+    let max = max.unwrap_or(DEFAULT_MAX_VOLUME);
+    if max > MAX_VOLUME {
+        return Err(format!("Maximum volume cannot exceed {}%", MAX_VOLUME));
+    }
+    match (direction, level) {
+        (Some(VolumeDirection::Set), None) => Err("Setting the volume needs a level".to_string()),
+        (Some(VolumeDirection::Set), Some(level)) if level > max => Err(format!(
+            "Volume level {}% is above the maximum of {}%",
+            level, max
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Check that a color is a CSS hex color (#rgb or #rrggbb)
+fn is_hex_color(color: &str) -> bool {
+    match color.strip_prefix('#') {
+        Some(hex) => {
+            (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit())
+        }
+        None => false,
+    }
 }
@@ -7,6 +7,69 @@ use std::path::Path;
 pub struct Settings {
     pub port: u16,
     pub auth_code: String,
+
+    /// Days of execution history to keep, 0 keeps everything (unset keeps the stored value)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_retention_days: Option<u32>,
+
+    /// Allow the phone to control the pointer and keyboard directly (unset keeps the stored value)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_remote_input: Option<bool>,
+
+    /// Directories the phone may upload files into (unset keeps the stored value)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_targets: Option<Vec<SharedDirectory>>,
+
+    /// Largest upload request accepted, in megabytes (unset keeps the stored value)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_upload_mb: Option<u64>,
+
+    /// Directories the phone may browse and download from (unset keeps the stored value)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub browse_roots: Option<Vec<SharedDirectory>>,
+
+    /// Allow the phone to see the desktop, as snapshots or a live stream (unset keeps the stored value)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_screen_stream: Option<bool>,
+
+    /// Frames per second of the desktop stream (unset keeps the stored value)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_fps: Option<u32>,
+
+    /// Width the desktop stream is scaled down to, in pixels (unset keeps the stored value)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_max_width: Option<u32>,
+
+    /// Highest level the phone's volume slider may set, above 100 amplifies (unset keeps the stored value)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_volume: Option<u8>,
+}
+
+/// A directory exposed to the phone, for uploads or browsing
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SharedDirectory {
+    /// Identifier used by the phone to pick the directory
+    pub id: String,
+    /// Display name shown on the phone
+    pub name: String,
+    /// Directory path, `~` expands to the home directory
+    pub path: String,
+}
+
+/// A shared directory as shown to the phone, without its path on disk
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryInfo {
+    pub id: String,
+    pub name: String,
+}
+
+impl From<&SharedDirectory> for DirectoryInfo {
+    fn from(dir: &SharedDirectory) -> Self {
+        Self {
+            id: dir.id.clone(),
+            name: dir.name.clone(),
+        }
+    }
 }
 
 impl Settings {
@@ -14,6 +77,15 @@ impl Settings {
         Self {
             port: 7776,
             auth_code: Self::generate_random_code(),
+            history_retention_days: Some(crate::history::DEFAULT_RETENTION_DAYS),
+            allow_remote_input: Some(false),
+            upload_targets: Some(Vec::new()),
+            max_upload_mb: Some(crate::upload::DEFAULT_MAX_UPLOAD_MB),
+            browse_roots: Some(Vec::new()),
+            allow_screen_stream: Some(false),
+            stream_fps: Some(crate::screen_stream::DEFAULT_FPS),
+            stream_max_width: Some(crate::screen_stream::DEFAULT_MAX_WIDTH),
+            max_volume: Some(crate::audio::DEFAULT_MAX_VOLUME),
         }
     }
 
@@ -36,6 +108,38 @@ impl Settings {
         if self.auth_code.is_empty() {
             return Err("Auth code cannot be empty".to_string());
         }
+        if let Some(mb) = self.max_upload_mb {
+            if mb == 0 || mb > crate::upload::MAX_UPLOAD_MB {
+                return Err(format!(
+                    "Upload size limit must be between 1 and {} MB",
+                    crate::upload::MAX_UPLOAD_MB
+                ));
+            }
+        }
+        if let Some(fps) = self.stream_fps {
+            if fps == 0 || fps > crate::screen_stream::MAX_FPS {
+                return Err(format!(
+                    "Stream frame rate must be between 1 and {} fps",
+                    crate::screen_stream::MAX_FPS
+                ));
+            }
+        }
+        if let Some(width) = self.stream_max_width {
+            if !(crate::screen_stream::MIN_WIDTH..=crate::screen_stream::MAX_WIDTH).contains(&width) {
+                return Err(format!(
+                    "Stream width must be between {} and {} pixels",
+                    crate::screen_stream::MIN_WIDTH,
+                    crate::screen_stream::MAX_WIDTH
+                ));
+            }
+        }
+        if let Some(max) = self.max_volume {
+            if max == 0 || max > crate::audio::MAX_VOLUME {
+                return Err(format!("Volume limit must be between 1 and {}%", crate::audio::MAX_VOLUME));
+            }
+        }
+        validate_directories("upload target", self.upload_targets.as_deref().unwrap_or_default())?;
+        validate_directories("browse root", self.browse_roots.as_deref().unwrap_or_default())?;
         Ok(())
     }
 
@@ -55,3 +159,19 @@ impl Settings {
             .map_err(|e| format!("Failed to write settings file: {}", e))
     }
 }
+
+/// Check that shared directories have an id and a path, and that ids are unique
+fn validate_directories(kind: &str, dirs: &[SharedDirectory]) -> Result<(), String> {
+    for (i, dir) in dirs.iter().enumerate() {
+        if dir.id.trim().is_empty() {
+            return Err(format!("Every {} needs an id", kind));
+        }
+        if dir.path.trim().is_empty() {
+            return Err(format!("The {} '{}' needs a directory", kind, dir.id));
+        }
+        if dirs[..i].iter().any(|d| d.id == dir.id) {
+            return Err(format!("Duplicate {} '{}'", kind, dir.id));
+        }
+    }
+    Ok(())
+}
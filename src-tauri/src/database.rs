@@ -1,18 +1,41 @@
-use crate::commands::{CommandConfig, CommandType, VolumeDirection};
+use crate::audio;
+use crate::audit::{self, AuditEntry, AuditEvent, AuditSeal, AuditVerification};
+use crate::commands::{CommandConfig, CommandType, MediaAction, VolumeDirection};
 use crate::config::Settings;
-use rusqlite::{Connection, Result as SqliteResult, params};
+use crate::history::{self, ExecutionRecord, HistoryFilter, HistoryPage};
+use crate::profiles::{Page, PageSlot, Profile, SlotKind};
+use crate::scheduler::{MissedRunPolicy, Schedule};
+use crate::screen_stream;
+use crate::upload;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Result as SqliteResult, ToSql};
 use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+const COMMAND_COLUMNS: &str = "id, name, command_type, command, volume_direction, volume_step,
+    directory_path, app_name, keybind, focus_app,
+    icon, background_color, foreground_color, subtitle, state_query,
+    on_action, off_action, steps, text, key_delay_ms, mouse,
+    media_action, media_player, seek_offset_secs, window, volume_level, volume_max";
+
 pub struct Database {
     conn: Connection,
+    audit_seal: AuditSeal,
+}
+
+/// An uploaded button image, already resized and re-encoded
+#[derive(Debug, Clone)]
+pub struct CommandImage {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+    pub etag: String,
 }
 
 impl Database {
-    pub fn new<P: AsRef<Path>>(path: P) -> SqliteResult<Self> {
+    pub fn new<P: AsRef<Path>>(path: P, audit_seal: AuditSeal) -> SqliteResult<Self> {
         let conn = Connection::open(path)?;
-        let db = Self { conn };
+        let db = Self { conn, audit_seal };
         db.initialize()?;
         Ok(db)
     }
@@ -44,6 +67,155 @@ impl Database {
             [],
         )?;
 
+        // Columns added after the initial schema
+        self.ensure_column("commands", "icon", "TEXT")?;
+        self.ensure_column("commands", "background_color", "TEXT")?;
+        self.ensure_column("commands", "foreground_color", "TEXT")?;
+        self.ensure_column("commands", "subtitle", "TEXT")?;
+        self.ensure_column("commands", "state_query", "TEXT")?;
+        self.ensure_column("commands", "on_action", "TEXT")?;
+        self.ensure_column("commands", "off_action", "TEXT")?;
+        self.ensure_column("commands", "steps", "TEXT")?;
+        self.ensure_column("commands", "text", "TEXT")?;
+        self.ensure_column("commands", "key_delay_ms", "INTEGER")?;
+        self.ensure_column("commands", "mouse", "TEXT")?;
+        self.ensure_column("commands", "media_action", "TEXT")?;
+        self.ensure_column("commands", "media_player", "TEXT")?;
+        self.ensure_column("commands", "seek_offset_secs", "INTEGER")?;
+        self.ensure_column("commands", "window", "TEXT")?;
+        self.ensure_column("commands", "volume_level", "INTEGER")?;
+        self.ensure_column("commands", "volume_max", "INTEGER")?;
+
+        // Create command images table (uploaded button images)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS command_images (
+                command_id TEXT PRIMARY KEY,
+                mime_type TEXT NOT NULL,
+                data BLOB NOT NULL,
+                etag TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Create toggle states table (current on/off state of Toggle commands)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS toggle_states (
+                command_id TEXT PRIMARY KEY,
+                active INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+
+        // Create profiles, pages and page slots tables (button layout hierarchy)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS profiles (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                position INTEGER NOT NULL DEFAULT 0,
+                home_page_id TEXT
+            )",
+            [],
+        )?;
+        self.ensure_column("profiles", "app_matchers", "TEXT")?;
+
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS pages (
+                id TEXT PRIMARY KEY,
+                profile_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                parent_page_id TEXT
+            )",
+            [],
+        )?;
+
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS page_slots (
+                page_id TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                command_id TEXT,
+                target_page_id TEXT,
+                label TEXT,
+                icon TEXT,
+                PRIMARY KEY (page_id, position)
+            )",
+            [],
+        )?;
+
+        // Create schedules table (cron expressions bound to commands)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS schedules (
+                id TEXT PRIMARY KEY,
+                command_id TEXT NOT NULL,
+                cron_expression TEXT NOT NULL,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                missed_run_policy TEXT NOT NULL DEFAULT 'skip',
+                last_run_at TEXT
+            )",
+            [],
+        )?;
+
+        // Create executions table (history of executed commands)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS executions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                command_id TEXT NOT NULL,
+                command_name TEXT NOT NULL,
+                executed_at TEXT NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                success INTEGER NOT NULL,
+                output TEXT NOT NULL,
+                exit_code INTEGER,
+                client TEXT NOT NULL
+            )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_executions_executed_at ON executions (executed_at)",
+            [],
+        )?;
+
+        // Create audit log table (append-only, hash-chained security events)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY,
+                at TEXT NOT NULL,
+                event TEXT NOT NULL,
+                actor TEXT NOT NULL,
+                details TEXT NOT NULL,
+                prev_hash TEXT NOT NULL,
+                hash TEXT NOT NULL
+            )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+             BEGIN SELECT RAISE(ABORT, 'audit log is append-only'); END",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+             BEGIN SELECT RAISE(ABORT, 'audit log is append-only'); END",
+            [],
+        )?;
+
+        Ok(())
+    }
+
+    /// Add a column to an existing table if it is missing (for databases created by older versions)
+    fn ensure_column(&self, table: &str, column: &str, definition: &str) -> SqliteResult<()> {
+        let mut stmt = self.conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(Result::ok)
+            .any(|name| name == column);
+
+        if !exists {
+            self.conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                [],
+            )?;
+        }
         Ok(())
     }
 
@@ -76,116 +248,109 @@ impl Database {
             .get_setting("auth_code")
             .unwrap_or_else(Settings::generate_random_code);
 
-        Settings { port, auth_code }
+        let history_retention_days = self
+            .get_setting("history_retention_days")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(history::DEFAULT_RETENTION_DAYS);
+
+        // Remote input is off until explicitly allowed
+        let allow_remote_input = self.get_setting("allow_remote_input").as_deref() == Some("true");
+
+        let upload_targets = self
+            .get_setting("upload_targets")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+
+        let max_upload_mb = self
+            .get_setting("max_upload_mb")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(upload::DEFAULT_MAX_UPLOAD_MB);
+
+        let browse_roots = self
+            .get_setting("browse_roots")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+
+        // Screen streaming is off until explicitly allowed
+        let allow_screen_stream = self.get_setting("allow_screen_stream").as_deref() == Some("true");
+
+        let stream_fps = self
+            .get_setting("stream_fps")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(screen_stream::DEFAULT_FPS);
+
+        let stream_max_width = self
+            .get_setting("stream_max_width")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(screen_stream::DEFAULT_MAX_WIDTH);
+
+        let max_volume = self
+            .get_setting("max_volume")
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(audio::DEFAULT_MAX_VOLUME);
+
+        Settings {
+            port,
+            auth_code,
+            history_retention_days: Some(history_retention_days),
+            allow_remote_input: Some(allow_remote_input),
+            upload_targets: Some(upload_targets),
+            max_upload_mb: Some(max_upload_mb),
+            browse_roots: Some(browse_roots),
+            allow_screen_stream: Some(allow_screen_stream),
+            stream_fps: Some(stream_fps),
+            stream_max_width: Some(stream_max_width),
+            max_volume: Some(max_volume),
+        }
     }
 
+    /// Save settings; optional fields that are unset keep their stored value
     pub fn save_settings(&self, settings: &Settings) -> SqliteResult<()> {
         self.set_setting("port", &settings.port.to_string())?;
         self.set_setting("auth_code", &settings.auth_code)?;
+        if let Some(days) = settings.history_retention_days {
+            self.set_setting("history_retention_days", &days.to_string())?;
+        }
+        if let Some(allow) = settings.allow_remote_input {
+            self.set_setting("allow_remote_input", &allow.to_string())?;
+        }
+        if let Some(targets) = to_json_column(&settings.upload_targets)? {
+            self.set_setting("upload_targets", &targets)?;
+        }
+        if let Some(mb) = settings.max_upload_mb {
+            self.set_setting("max_upload_mb", &mb.to_string())?;
+        }
+        if let Some(roots) = to_json_column(&settings.browse_roots)? {
+            self.set_setting("browse_roots", &roots)?;
+        }
+        if let Some(allow) = settings.allow_screen_stream {
+            self.set_setting("allow_screen_stream", &allow.to_string())?;
+        }
+        if let Some(fps) = settings.stream_fps {
+            self.set_setting("stream_fps", &fps.to_string())?;
+        }
+        if let Some(width) = settings.stream_max_width {
+            self.set_setting("stream_max_width", &width.to_string())?;
+        }
+        if let Some(max) = settings.max_volume {
+            self.set_setting("max_volume", &max.to_string())?;
+        }
         Ok(())
     }
 
     // Commands operations
     pub fn get_all_commands(&self) -> SqliteResult<Vec<CommandConfig>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, name, command_type, command, volume_direction, volume_step, 
-                    directory_path, app_name, keybind, focus_app 
-             FROM commands"
-        )?;
-
-        let commands = stmt.query_map([], |row| {
-            let id: String = row.get(0)?;
-            let name: String = row.get(1)?;
-            let command_type_str: String = row.get(2)?;
-            let command: Option<String> = row.get(3)?;
-            let volume_direction_str: Option<String> = row.get(4)?;
-            let volume_step: Option<u8> = row.get(5)?;
-            let directory_path: Option<String> = row.get(6)?;
-            let app_name: Option<String> = row.get(7)?;
-            let keybind: Option<String> = row.get(8)?;
-            let focus_app: Option<String> = row.get(9)?;
-
-            let command_type = match command_type_str.as_str() {
-                "shell" => CommandType::Shell,
-                "volume" => CommandType::Volume,
-                "open_directory" => CommandType::OpenDirectory,
-                "focus_app" => CommandType::FocusApp,
-                "keybind" => CommandType::Keybind,
-                _ => CommandType::Shell,
-            };
-
-            let volume_direction = volume_direction_str.map(|s| match s.as_str() {
-                "up" => VolumeDirection::Up,
-                "down" => VolumeDirection::Down,
-                "mute" => VolumeDirection::Mute,
-                _ => VolumeDirection::Up,
-            });
-
-            Ok(CommandConfig {
-                id,
-                name,
-                command_type,
-                command,
-                volume_direction,
-                volume_step,
-                directory_path,
-                app_name,
-                keybind,
-                focus_app,
-            })
-        })?;
-
+        let mut stmt = self.conn.prepare(&format!("SELECT {} FROM commands", COMMAND_COLUMNS))?;
+        let commands = stmt.query_map([], command_from_row)?;
         commands.collect()
     }
 
     pub fn get_command(&self, id: &str) -> Option<CommandConfig> {
         self.conn
             .query_row(
-                "SELECT id, name, command_type, command, volume_direction, volume_step, 
-                        directory_path, app_name, keybind, focus_app 
-                 FROM commands WHERE id = ?",
+                &format!("SELECT {} FROM commands WHERE id = ?", COMMAND_COLUMNS),
                 [id],
-                |row| {
-                    let id: String = row.get(0)?;
-                    let name: String = row.get(1)?;
-                    let command_type_str: String = row.get(2)?;
-                    let command: Option<String> = row.get(3)?;
-                    let volume_direction_str: Option<String> = row.get(4)?;
-                    let volume_step: Option<u8> = row.get(5)?;
-                    let directory_path: Option<String> = row.get(6)?;
-                    let app_name: Option<String> = row.get(7)?;
-                    let keybind: Option<String> = row.get(8)?;
-                    let focus_app: Option<String> = row.get(9)?;
-
-                    let command_type = match command_type_str.as_str() {
-                        "shell" => CommandType::Shell,
-                        "volume" => CommandType::Volume,
-                        "open_directory" => CommandType::OpenDirectory,
-                        "focus_app" => CommandType::FocusApp,
-                        "keybind" => CommandType::Keybind,
-                        _ => CommandType::Shell,
-                    };
-
-                    let volume_direction = volume_direction_str.map(|s| match s.as_str() {
-                        "up" => VolumeDirection::Up,
-                        "down" => VolumeDirection::Down,
-                        "mute" => VolumeDirection::Mute,
-                        _ => VolumeDirection::Up,
-                    });
-
-                    Ok(CommandConfig {
-                        id,
-                        name,
-                        command_type,
-                        command,
-                        volume_direction,
-                        volume_step,
-                        directory_path,
-                        app_name,
-                        keybind,
-                        focus_app,
-                    })
-                },
+                command_from_row,
             )
             .ok()
     }
@@ -197,19 +362,48 @@ impl Database {
             CommandType::OpenDirectory => "open_directory",
             CommandType::FocusApp => "focus_app",
             CommandType::Keybind => "keybind",
+            CommandType::Toggle => "toggle",
+            CommandType::Macro => "macro",
+            CommandType::TypeText => "type_text",
+            CommandType::Mouse => "mouse",
+            CommandType::Media => "media",
+            CommandType::Clipboard => "clipboard",
+            CommandType::Window => "window",
         };
 
         let volume_direction = cmd.volume_direction.as_ref().map(|d| match d {
             VolumeDirection::Up => "up",
             VolumeDirection::Down => "down",
             VolumeDirection::Mute => "mute",
+            VolumeDirection::MuteOn => "mute_on",
+            VolumeDirection::MuteOff => "mute_off",
+            VolumeDirection::Set => "set",
+        });
+
+        let state_query = to_json_column(&cmd.state_query)?;
+        let on_action = to_json_column(&cmd.on_action)?;
+        let off_action = to_json_column(&cmd.off_action)?;
+        let steps = to_json_column(&Some(&cmd.steps).filter(|s| !s.is_empty()))?;
+        let mouse = to_json_column(&cmd.mouse)?;
+        let window = to_json_column(&cmd.window)?;
+        let media_action = cmd.media_action.as_ref().map(|a| match a {
+            MediaAction::PlayPause => "play_pause",
+            MediaAction::Play => "play",
+            MediaAction::Pause => "pause",
+            MediaAction::Stop => "stop",
+            MediaAction::Next => "next",
+            MediaAction::Previous => "previous",
+            MediaAction::Seek => "seek",
         });
 
         self.conn.execute(
             "INSERT OR REPLACE INTO commands 
              (id, name, command_type, command, volume_direction, volume_step, 
-              directory_path, app_name, keybind, focus_app)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+              directory_path, app_name, keybind, focus_app,
+              icon, background_color, foreground_color, subtitle, state_query,
+              on_action, off_action, steps, text, key_delay_ms, mouse,
+              media_action, media_player, seek_offset_secs, window, volume_level, volume_max)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
             params![
                 cmd.id,
                 cmd.name,
@@ -221,6 +415,23 @@ impl Database {
                 cmd.app_name,
                 cmd.keybind,
                 cmd.focus_app,
+                cmd.icon,
+                cmd.background_color,
+                cmd.foreground_color,
+                cmd.subtitle,
+                state_query,
+                on_action,
+                off_action,
+                steps,
+                cmd.text,
+                cmd.key_delay_ms,
+                mouse,
+                media_action,
+                cmd.media_player,
+                cmd.seek_offset_secs,
+                window,
+                cmd.volume_level,
+                cmd.volume_max,
             ],
         )?;
         Ok(())
@@ -237,53 +448,753 @@ impl Database {
         for cmd in commands {
             self.save_command(cmd)?;
         }
+        // Drop images and toggle states belonging to commands that no longer exist
+        self.conn.execute(
+            "DELETE FROM command_images WHERE command_id NOT IN (SELECT id FROM commands)",
+            [],
+        )?;
+        self.conn.execute(
+            "DELETE FROM toggle_states WHERE command_id NOT IN (SELECT id FROM commands)",
+            [],
+        )?;
+        self.conn.execute(
+            "DELETE FROM page_slots WHERE kind = 'command' AND command_id NOT IN (SELECT id FROM commands)",
+            [],
+        )?;
         Ok(())
     }
 
-    /// Migrate data from old YAML/JSON files to SQLite
-    pub fn migrate_from_files(&self, settings_path: &Path, commands_path: &Path) -> SqliteResult<()> {
-        // Check if we already have data
-        let has_data: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM settings",
-            [],
+    // Toggle state operations
+    pub fn get_toggle_state(&self, command_id: &str) -> bool {
+        self.conn
+            .query_row(
+                "SELECT active FROM toggle_states WHERE command_id = ?",
+                [command_id],
+                |row| row.get(0),
+            )
+            .unwrap_or(false)
+    }
+
+    pub fn set_toggle_state(&self, command_id: &str, active: bool) -> SqliteResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO toggle_states (command_id, active) VALUES (?, ?)",
+            params![command_id, active],
+        )?;
+        Ok(())
+    }
+
+    // Command image operations
+    pub fn get_command_image(&self, command_id: &str) -> Option<CommandImage> {
+        self.conn
+            .query_row(
+                "SELECT mime_type, data, etag FROM command_images WHERE command_id = ?",
+                [command_id],
+                |row| {
+                    Ok(CommandImage {
+                        mime_type: row.get(0)?,
+                        data: row.get(1)?,
+                        etag: row.get(2)?,
+                    })
+                },
+            )
+            .ok()
+    }
+
+    pub fn has_command_image(&self, command_id: &str) -> bool {
+        self.conn
+            .query_row(
+                "SELECT 1 FROM command_images WHERE command_id = ?",
+                [command_id],
+                |_| Ok(()),
+            )
+            .is_ok()
+    }
+
+    pub fn save_command_image(&self, command_id: &str, image: &CommandImage) -> SqliteResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO command_images (command_id, mime_type, data, etag)
+             VALUES (?, ?, ?, ?)",
+            params![command_id, image.mime_type, image.data, image.etag],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_command_image(&self, command_id: &str) -> SqliteResult<bool> {
+        let rows = self
+            .conn
+            .execute("DELETE FROM command_images WHERE command_id = ?", [command_id])?;
+        Ok(rows > 0)
+    }
+
+    // Profile operations
+    pub fn get_profiles(&self) -> SqliteResult<Vec<Profile>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, name, position, home_page_id, app_matchers
+             FROM profiles ORDER BY position, name",
+        )?;
+
+        let profiles = stmt.query_map([], |row| {
+            Ok(Profile {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                position: row.get(2)?,
+                home_page_id: row.get(3)?,
+                app_matchers: from_json_column(row, 4)?.unwrap_or_default(),
+            })
+        })?;
+
+        profiles.collect()
+    }
+
+    pub fn get_profile(&self, id: &str) -> Option<Profile> {
+        self.get_profiles().ok()?.into_iter().find(|p| p.id == id)
+    }
+
+    pub fn save_profile(&self, profile: &Profile) -> SqliteResult<()> {
+        let app_matchers = to_json_column(&Some(&profile.app_matchers))?;
+
+        self.conn.execute(
+            "INSERT OR REPLACE INTO profiles (id, name, position, home_page_id, app_matchers)
+             VALUES (?, ?, ?, ?, ?)",
+            params![
+                profile.id,
+                profile.name,
+                profile.position,
+                profile.home_page_id,
+                app_matchers,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Delete a profile together with all of its pages
+    pub fn delete_profile(&self, id: &str) -> SqliteResult<bool> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM page_slots WHERE page_id IN (SELECT id FROM pages WHERE profile_id = ?)",
+            [id],
+        )?;
+        tx.execute("DELETE FROM pages WHERE profile_id = ?", [id])?;
+        let rows = tx.execute("DELETE FROM profiles WHERE id = ?", [id])?;
+        tx.commit()?;
+        Ok(rows > 0)
+    }
+
+    // Page operations
+    pub fn get_pages(&self, profile_id: &str) -> SqliteResult<Vec<Page>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, profile_id, name, parent_page_id FROM pages WHERE profile_id = ? ORDER BY name",
+        )?;
+
+        let pages = stmt
+            .query_map([profile_id], page_from_row)?
+            .collect::<SqliteResult<Vec<Page>>>()?;
+
+        pages
+            .into_iter()
+            .map(|mut page| {
+                page.slots = self.get_page_slots(&page.id)?;
+                Ok(page)
+            })
+            .collect()
+    }
+
+    pub fn get_page(&self, id: &str) -> Option<Page> {
+        let mut page = self
+            .conn
+            .query_row(
+                "SELECT id, profile_id, name, parent_page_id FROM pages WHERE id = ?",
+                [id],
+                page_from_row,
+            )
+            .ok()?;
+        page.slots = self.get_page_slots(id).ok()?;
+        Some(page)
+    }
+
+    fn get_page_slots(&self, page_id: &str) -> SqliteResult<Vec<PageSlot>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT position, kind, command_id, target_page_id, label, icon
+             FROM page_slots WHERE page_id = ? ORDER BY position",
+        )?;
+
+        let slots = stmt.query_map([page_id], |row| {
+            let kind_str: String = row.get(1)?;
+            let kind = match kind_str.as_str() {
+                "folder" => SlotKind::Folder,
+                _ => SlotKind::Command,
+            };
+
+            Ok(PageSlot {
+                position: row.get(0)?,
+                kind,
+                command_id: row.get(2)?,
+                target_page_id: row.get(3)?,
+                label: row.get(4)?,
+                icon: row.get(5)?,
+            })
+        })?;
+
+        slots.collect()
+    }
+
+    /// Save a page and replace all of its slots
+    pub fn save_page(&self, page: &Page) -> SqliteResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO pages (id, profile_id, name, parent_page_id) VALUES (?, ?, ?, ?)",
+            params![page.id, page.profile_id, page.name, page.parent_page_id],
+        )?;
+        tx.execute("DELETE FROM page_slots WHERE page_id = ?", [&page.id])?;
+        for slot in &page.slots {
+            let kind = match slot.kind {
+                SlotKind::Command => "command",
+                SlotKind::Folder => "folder",
+            };
+            tx.execute(
+                "INSERT INTO page_slots (page_id, position, kind, command_id, target_page_id, label, icon)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    page.id,
+                    slot.position,
+                    kind,
+                    slot.command_id,
+                    slot.target_page_id,
+                    slot.label,
+                    slot.icon,
+                ],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Delete a page, its sub-pages and every folder button leading to them
+    pub fn delete_page(&self, id: &str) -> SqliteResult<bool> {
+        // Collect the page and all of its descendants
+        let mut to_delete = vec![id.to_string()];
+        let mut i = 0;
+        while i < to_delete.len() {
+            let mut stmt = self.conn.prepare("SELECT id FROM pages WHERE parent_page_id = ?")?;
+            let children = stmt
+                .query_map([&to_delete[i]], |row| row.get::<_, String>(0))?
+                .collect::<SqliteResult<Vec<String>>>()?;
+            for child in children {
+                if !to_delete.contains(&child) {
+                    to_delete.push(child);
+                }
+            }
+            i += 1;
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        let mut rows = 0;
+        for page_id in &to_delete {
+            tx.execute("DELETE FROM page_slots WHERE page_id = ?", [page_id])?;
+            tx.execute(
+                "DELETE FROM page_slots WHERE kind = 'folder' AND target_page_id = ?",
+                [page_id],
+            )?;
+            tx.execute(
+                "UPDATE profiles SET home_page_id = NULL WHERE home_page_id = ?",
+                [page_id],
+            )?;
+            rows += tx.execute("DELETE FROM pages WHERE id = ?", [page_id])?;
+        }
+        tx.commit()?;
+        Ok(rows > 0)
+    }
+
+    // Schedule operations
+    pub fn get_schedules(&self) -> SqliteResult<Vec<Schedule>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, command_id, cron_expression, enabled, missed_run_policy, last_run_at
+             FROM schedules ORDER BY id",
+        )?;
+
+        let schedules = stmt.query_map([], |row| {
+            let policy_str: String = row.get(4)?;
+            let last_run_str: Option<String> = row.get(5)?;
+
+            let missed_run_policy = match policy_str.as_str() {
+                "run_once" => MissedRunPolicy::RunOnce,
+                _ => MissedRunPolicy::Skip,
+            };
+
+            let last_run_at = last_run_str
+                .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                .map(|dt| dt.with_timezone(&Utc));
+
+            Ok(Schedule {
+                id: row.get(0)?,
+                command_id: row.get(1)?,
+                cron_expression: row.get(2)?,
+                enabled: row.get(3)?,
+                missed_run_policy,
+                last_run_at,
+                next_run_at: None,
+            })
+        })?;
+
+        schedules.collect()
+    }
+
+    /// Insert or update a schedule, keeping its last run time
+    ///
+    /// New schedules start counting from now, so they never fire retroactively.
+    /// Changing the cron expression restarts the schedule from now as well, so
+    /// the new expression isn't measured against the old one's last run.
+    pub fn save_schedule(&self, schedule: &Schedule) -> SqliteResult<()> {
+        let policy = match schedule.missed_run_policy {
+            MissedRunPolicy::Skip => "skip",
+            MissedRunPolicy::RunOnce => "run_once",
+        };
+
+        // SET expressions see the old row, so `cron_expression` is the stored one
+        self.conn.execute(
+            "INSERT INTO schedules (id, command_id, cron_expression, enabled, missed_run_policy, last_run_at)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT(id) DO UPDATE SET
+                last_run_at = CASE WHEN cron_expression <> excluded.cron_expression
+                    THEN excluded.last_run_at ELSE last_run_at END,
+                command_id = excluded.command_id,
+                cron_expression = excluded.cron_expression,
+                enabled = excluded.enabled,
+                missed_run_policy = excluded.missed_run_policy",
+            params![
+                schedule.id,
+                schedule.command_id,
+                schedule.cron_expression,
+                schedule.enabled,
+                policy,
+                Utc::now().to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_schedule(&self, id: &str) -> SqliteResult<bool> {
+        let rows = self.conn.execute("DELETE FROM schedules WHERE id = ?", [id])?;
+        Ok(rows > 0)
+    }
+
+    /// Enable or disable a schedule
+    ///
+    /// Enabling restarts the schedule from now, so runs that fell in the disabled
+    /// period are not treated as missed.
+    pub fn set_schedule_enabled(&self, id: &str, enabled: bool) -> SqliteResult<bool> {
+        // SET expressions see the old row, so `enabled = 0` means "was disabled"
+        let rows = self.conn.execute(
+            "UPDATE schedules SET
+                last_run_at = CASE WHEN ?1 AND enabled = 0 THEN ?2 ELSE last_run_at END,
+                enabled = ?1
+             WHERE id = ?3",
+            params![enabled, Utc::now().to_rfc3339(), id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    pub fn set_schedule_last_run(&self, id: &str, at: DateTime<Utc>) -> SqliteResult<()> {
+        self.conn.execute(
+            "UPDATE schedules SET last_run_at = ? WHERE id = ?",
+            params![at.to_rfc3339(), id],
+        )?;
+        Ok(())
+    }
+
+    // Execution history operations
+    pub fn record_execution(&self, record: &ExecutionRecord) -> SqliteResult<()> {
+        self.conn.execute(
+            "INSERT INTO executions
+             (command_id, command_name, executed_at, duration_ms, success, output, exit_code, client)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                record.command_id,
+                record.command_name,
+                history::format_timestamp(&record.executed_at),
+                record.duration_ms,
+                record.success,
+                record.output,
+                record.exit_code,
+                record.client,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Delete executions older than the retention period (0 keeps everything)
+    pub fn prune_executions(&self, retention_days: u32) -> SqliteResult<usize> {
+        if retention_days == 0 {
+            return Ok(0);
+        }
+        let cutoff = chrono::Utc::now() - chrono::Duration::days(retention_days.into());
+        self.conn.execute(
+            "DELETE FROM executions WHERE executed_at < ?",
+            [history::format_timestamp(&cutoff)],
+        )
+    }
+
+    pub fn query_executions(&self, filter: &HistoryFilter) -> SqliteResult<HistoryPage> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+
+        if let Some(ref command_id) = filter.command_id {
+            conditions.push("command_id = ?");
+            values.push(Box::new(command_id.clone()));
+        }
+        if let Some(success) = filter.success {
+            conditions.push("success = ?");
+            values.push(Box::new(success));
+        }
+        if let Some(ref client) = filter.client {
+            conditions.push("client = ?");
+            values.push(Box::new(client.clone()));
+        }
+        if let Some(ref since) = filter.since {
+            conditions.push("executed_at >= ?");
+            values.push(Box::new(history::format_timestamp(since)));
+        }
+        if let Some(ref until) = filter.until {
+            conditions.push("executed_at < ?");
+            values.push(Box::new(history::format_timestamp(until)));
+        }
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        let total: u64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM executions {}", where_clause),
+            params_from_iter(values.iter()),
             |row| row.get(0),
         )?;
 
+        values.push(Box::new(filter.limit()));
+        values.push(Box::new(filter.offset()));
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, command_id, command_name, executed_at, duration_ms, success, output, exit_code, client
+             FROM executions {} ORDER BY executed_at DESC, id DESC LIMIT ? OFFSET ?",
+            where_clause
+        ))?;
+
+        let items = stmt
+            .query_map(params_from_iter(values.iter()), |row| {
+                let executed_at: String = row.get(3)?;
+                Ok(ExecutionRecord {
+                    id: row.get(0)?,
+                    command_id: row.get(1)?,
+                    command_name: row.get(2)?,
+                    executed_at: chrono::DateTime::parse_from_rfc3339(&executed_at)
+                        .map(|dt| dt.with_timezone(&chrono::Utc))
+                        .unwrap_or_default(),
+                    duration_ms: row.get(4)?,
+                    success: row.get(5)?,
+                    output: row.get(6)?,
+                    exit_code: row.get(7)?,
+                    client: row.get(8)?,
+                })
+            })?
+            .collect::<SqliteResult<Vec<_>>>()?;
+
+        Ok(HistoryPage { total, items })
+    }
+
+    pub fn clear_executions(&self) -> SqliteResult<usize> {
+        self.conn.execute("DELETE FROM executions", [])
+    }
+
+    // Audit log operations
+    /// Append an entry to the audit log, chained to the previous entry
+    pub fn append_audit(&self, event: AuditEvent, actor: &str, details: &serde_json::Value) -> SqliteResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        let (last_id, prev_hash): (i64, String) = tx
+            .query_row(
+                "SELECT id, hash FROM audit_log ORDER BY id DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .unwrap_or((0, audit::GENESIS_HASH.to_string()));
+
+        // IDs are assigned here rather than by SQLite because they are part of the hash
+        let id = last_id + 1;
+        let at = history::format_timestamp(&chrono::Utc::now());
+        let details = details.to_string();
+        let hash = self.audit_seal.hash(&prev_hash, id, &at, event.as_str(), actor, &details);
+
+        tx.execute(
+            "INSERT INTO audit_log (id, at, event, actor, details, prev_hash, hash)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![id, at, event.as_str(), actor, details, prev_hash, hash],
+        )?;
+        tx.commit()?;
+
+        // A head behind the log is harmless, later entries are keyed as well
+        if let Err(e) = self.audit_seal.set_head(id, &hash) {
+            eprintln!("{}", e);
+        }
+        Ok(())
+    }
+
+    pub fn get_audit_entries(&self) -> SqliteResult<Vec<AuditEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, at, event, actor, details, prev_hash, hash FROM audit_log ORDER BY id",
+        )?;
+
+        let entries = stmt.query_map([], |row| {
+            let details: String = row.get(4)?;
+            Ok(AuditEntry {
+                id: row.get(0)?,
+                at: row.get(1)?,
+                event: row.get(2)?,
+                actor: row.get(3)?,
+                details: serde_json::from_str(&details).unwrap_or(serde_json::Value::String(details)),
+                prev_hash: row.get(5)?,
+                hash: row.get(6)?,
+            })
+        })?;
+
+        entries.collect()
+    }
+
+    /// Walk the hash chain and report the first entry that doesn't match
+    ///
+    /// The log is also checked against the last entry recorded outside the
+    /// database, which catches entries removed from the end.
+    pub fn verify_audit_log(&self) -> Result<AuditVerification, String> {
+        let head = self.audit_seal.head()?;
+        self.check_audit_chain(head).map_err(|e| e.to_string())
+    }
+
+    fn check_audit_chain(&self, head: Option<(i64, String)>) -> SqliteResult<AuditVerification> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, at, event, actor, details, prev_hash, hash FROM audit_log ORDER BY id",
+        )?;
+        let mut rows = stmt.query([])?;
+
+        let mut expected_id = 1;
+        let mut expected_prev = audit::GENESIS_HASH.to_string();
+        let mut entries = 0;
+        let mut head_found = head.is_none();
+
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let at: String = row.get(1)?;
+            let event: String = row.get(2)?;
+            let actor: String = row.get(3)?;
+            let details: String = row.get(4)?;
+            let prev_hash: String = row.get(5)?;
+            let hash: String = row.get(6)?;
+
+            // A gap in IDs means entries were removed
+            let valid = id == expected_id
+                && prev_hash == expected_prev
+                && hash == self.audit_seal.hash(&prev_hash, id, &at, &event, &actor, &details);
+
+            if !valid {
+                return Ok(AuditVerification {
+                    valid: false,
+                    entries,
+                    first_invalid_id: Some(id),
+                    truncated: false,
+                    limitation: audit::VERIFICATION_LIMITATION,
+                });
+            }
+
+            if let Some((head_id, ref head_hash)) = head {
+                if id == head_id {
+                    head_found = hash == *head_hash;
+                }
+            }
+            entries += 1;
+            expected_id = id + 1;
+            expected_prev = hash;
+        }
+
+        Ok(AuditVerification {
+            valid: head_found,
+            entries,
+            first_invalid_id: None,
+            truncated: !head_found,
+            limitation: audit::VERIFICATION_LIMITATION,
+        })
+    }
+
+    /// Migrate data from old YAML/JSON files to SQLite
+    ///
+    /// Both files are attempted even if the other fails; the errors of both are returned.
+    pub fn migrate_from_files(&self, settings_path: &Path, commands_path: &Path) -> Result<(), String> {
+        // Check if we already have data
+        let has_data: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM settings", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+
         if has_data > 0 {
             return Ok(()); // Already migrated
         }
 
+        let mut errors = Vec::new();
+
         // Migrate settings from JSON
         if settings_path.exists() {
-            if let Ok(content) = std::fs::read_to_string(settings_path) {
-                if let Ok(settings) = serde_json::from_str::<Settings>(&content) {
-                    self.save_settings(&settings)?;
-                    println!("Migrated settings from JSON to SQLite");
-                }
+            match self.import_settings(settings_path) {
+                Ok(()) => println!("Migrated settings from JSON to SQLite"),
+                Err(e) => errors.push(format!("{}: {}", settings_path.display(), e)),
             }
         }
 
         // Migrate commands from YAML
         if commands_path.exists() {
-            if let Ok(content) = std::fs::read_to_string(commands_path) {
-                if let Ok(commands) = serde_yaml::from_str::<Vec<CommandConfig>>(&content) {
-                    for cmd in commands {
-                        self.save_command(&cmd)?;
-                    }
-                    println!("Migrated {} commands from YAML to SQLite", self.get_all_commands()?.len());
-                }
+            match self.import_commands(commands_path) {
+                Ok(count) => println!("Migrated {} commands from YAML to SQLite", count),
+                Err(e) => errors.push(format!("{}: {}", commands_path.display(), e)),
             }
         }
 
-        Ok(())
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    /// Save the settings of a legacy JSON settings file, failing if the file does not parse
+    fn import_settings(&self, path: &Path) -> SqliteResult<()> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let settings = serde_json::from_str::<Settings>(&content)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.save_settings(&settings)
+    }
+
+    /// Save every command of a YAML commands file, failing if the file does not parse
+    pub fn import_commands(&self, path: &Path) -> SqliteResult<usize> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let commands = serde_yaml::from_str::<Vec<CommandConfig>>(&content)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        for cmd in &commands {
+            self.save_command(cmd)?;
+        }
+        Ok(commands.len())
     }
 }
 
+/// Build a `CommandConfig` from a row selected with `COMMAND_COLUMNS`
+fn command_from_row(row: &rusqlite::Row) -> SqliteResult<CommandConfig> {
+    let command_type_str: String = row.get(2)?;
+    let volume_direction_str: Option<String> = row.get(4)?;
+
+    let command_type = match command_type_str.as_str() {
+        "shell" => CommandType::Shell,
+        "volume" => CommandType::Volume,
+        "open_directory" => CommandType::OpenDirectory,
+        "focus_app" => CommandType::FocusApp,
+        "keybind" => CommandType::Keybind,
+        "toggle" => CommandType::Toggle,
+        "macro" => CommandType::Macro,
+        "type_text" => CommandType::TypeText,
+        "mouse" => CommandType::Mouse,
+        "media" => CommandType::Media,
+        "clipboard" => CommandType::Clipboard,
+        "window" => CommandType::Window,
+        _ => CommandType::Shell,
+    };
+
+    let media_action_str: Option<String> = row.get(21)?;
+    let media_action = media_action_str.and_then(|s| match s.as_str() {
+        "play_pause" => Some(MediaAction::PlayPause),
+        "play" => Some(MediaAction::Play),
+        "pause" => Some(MediaAction::Pause),
+        "stop" => Some(MediaAction::Stop),
+        "next" => Some(MediaAction::Next),
+        "previous" => Some(MediaAction::Previous),
+        "seek" => Some(MediaAction::Seek),
+        _ => None,
+    });
+
+    let volume_direction = volume_direction_str.map(|s| match s.as_str() {
+        "up" => VolumeDirection::Up,
+        "down" => VolumeDirection::Down,
+        "mute" => VolumeDirection::Mute,
+        "mute_on" => VolumeDirection::MuteOn,
+        "mute_off" => VolumeDirection::MuteOff,
+        "set" => VolumeDirection::Set,
+        _ => VolumeDirection::Up,
+    });
+
+    Ok(CommandConfig {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        command_type,
+        command: row.get(3)?,
+        volume_direction,
+        volume_step: row.get(5)?,
+        directory_path: row.get(6)?,
+        app_name: row.get(7)?,
+        keybind: row.get(8)?,
+        focus_app: row.get(9)?,
+        icon: row.get(10)?,
+        background_color: row.get(11)?,
+        foreground_color: row.get(12)?,
+        subtitle: row.get(13)?,
+        state_query: from_json_column(row, 14)?,
+        on_action: from_json_column(row, 15)?,
+        off_action: from_json_column(row, 16)?,
+        steps: from_json_column(row, 17)?.unwrap_or_default(),
+        text: row.get(18)?,
+        key_delay_ms: row.get(19)?,
+        mouse: from_json_column(row, 20)?,
+        media_action,
+        media_player: row.get(22)?,
+        seek_offset_secs: row.get(23)?,
+        window: from_json_column(row, 24)?,
+        volume_level: row.get(25)?,
+        volume_max: row.get(26)?,
+    })
+}
+
+fn page_from_row(row: &rusqlite::Row) -> SqliteResult<Page> {
+    Ok(Page {
+        id: row.get(0)?,
+        profile_id: row.get(1)?,
+        name: row.get(2)?,
+        parent_page_id: row.get(3)?,
+        slots: Vec::new(),
+    })
+}
+
+/// Serialize an optional nested value into a JSON TEXT column
+fn to_json_column<T: serde::Serialize>(value: &Option<T>) -> SqliteResult<Option<String>> {
+    value
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/// Deserialize an optional nested value from a JSON TEXT column
+fn from_json_column<T: serde::de::DeserializeOwned>(
+    row: &rusqlite::Row,
+    idx: usize,
+) -> SqliteResult<Option<T>> {
+    let text: Option<String> = row.get(idx)?;
+    text.map(|t| serde_json::from_str(&t))
+        .transpose()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e)))
+}
+
 /// Thread-safe database wrapper for async contexts
 pub type SharedDatabase = Arc<Mutex<Database>>;
 
 pub fn create_shared_database<P: AsRef<Path>>(path: P) -> Result<SharedDatabase, String> {
-    let db = Database::new(path).map_err(|e| format!("Failed to create database: {}", e))?;
+    let dir = path.as_ref().parent().unwrap_or(Path::new("."));
+    let audit_seal = AuditSeal::open(dir)?;
+    let db = Database::new(path, audit_seal).map_err(|e| format!("Failed to create database: {}", e))?;
     Ok(Arc::new(Mutex::new(db)))
 }
 
@@ -0,0 +1,51 @@
+//! Live events pushed to connected clients
+//!
+//! Events are broadcast to every subscriber; the HTTP server forwards them to
+//! phones over Server-Sent Events on `/api/events`.
+
+use crate::audio::VolumeState;
+use crate::commands::CommandState;
+use crate::upload::UploadStatus;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Number of events buffered per subscriber before old ones are dropped
+const EVENT_BUFFER: usize = 64;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// The live state of a command changed
+    CommandState { id: String, state: CommandState },
+    /// The focused application switched the active profile
+    ActiveProfile {
+        profile_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        page_id: Option<String>,
+    },
+    /// Progress of a file upload from a phone
+    Upload {
+        upload_id: String,
+        file_name: String,
+        received_bytes: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total_bytes: Option<u64>,
+        status: UploadStatus,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// The level or mute state of the default output changed
+    Volume(VolumeState),
+}
+
+pub type EventSender = broadcast::Sender<ServerEvent>;
+
+pub fn create_event_channel() -> EventSender {
+    let (tx, _) = broadcast::channel(EVENT_BUFFER);
+    tx
+}
+
+/// Publish an event, ignoring the case where nobody is listening
+pub fn publish(events: &EventSender, event: ServerEvent) {
+    let _ = events.send(event);
+}
@@ -0,0 +1,140 @@
+//! Running configured commands
+//!
+//! Wraps `system_commands::execute_command` with the bookkeeping that needs the
+//! database: persisted toggle state, the legacy `focus_app` field and the
+//! execution history.
+
+use crate::commands::{CommandConfig, CommandType};
+use crate::database::SharedDatabase;
+use crate::events::{self, EventSender, ServerEvent};
+use crate::history::{self, ExecutionRecord};
+use crate::system_commands::{self, CommandResult};
+use crate::windows_focus;
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// One lock per toggle, held from reading its state until the flipped state is
+/// saved, so quick taps run one after the other instead of repeating an action
+static TOGGLE_LOCKS: Mutex<BTreeMap<String, Arc<tokio::sync::Mutex<()>>>> = Mutex::new(BTreeMap::new());
+
+/// The lock of a toggle, taken by anything that reads and writes its persisted state
+pub fn toggle_lock(id: &str) -> Arc<tokio::sync::Mutex<()>> {
+    let mut locks = TOGGLE_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    locks.entry(id.to_string()).or_default().clone()
+}
+
+/// Drop the locks of toggles that are no longer among `commands`
+pub fn prune_toggle_locks(commands: &[CommandConfig]) {
+    let mut locks = TOGGLE_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    locks.retain(|id, _| {
+        commands
+            .iter()
+            .any(|cmd| cmd.id == *id && cmd.command_type == CommandType::Toggle)
+    });
+}
+
+/// Execute a command on a blocking thread, update its persisted state and record it
+///
+/// `client` identifies who triggered the execution in the history.
+pub async fn run_command(
+    cmd: &CommandConfig,
+    database: &SharedDatabase,
+    events: &EventSender,
+    client: &str,
+) -> CommandResult {
+    let executed_at = Utc::now();
+    let started = Instant::now();
+
+    let mut result = if cmd.command_type == CommandType::Toggle {
+        let lock = toggle_lock(&cmd.id);
+        let _turn = lock.lock().await;
+        let active = database.lock().await.get_toggle_state(&cmd.id);
+        let config = cmd.clone();
+        let result = run_blocking(move || system_commands::execute_toggle(&config, active)).await;
+        if result.success {
+            set_toggle_state(cmd, !active, database, events).await;
+        }
+        result
+    } else {
+        let config = cmd.clone();
+        run_blocking(move || system_commands::execute_command(&config)).await
+    };
+
+    // Handle legacy focus_app field for backward compatibility
+    if result.success {
+        if let Some(ref app_title) = cmd.focus_app {
+            let title = app_title.clone();
+            let focused = tokio::task::spawn_blocking(move || windows_focus::focus_window_by_title(&title))
+                .await
+                .unwrap_or_else(|e| Err(format!("Focus panicked: {}", e)));
+            if let Err(e) = focused {
+                eprintln!("Failed to focus window '{}': {}", app_title, e);
+                result.success = false;
+                result.message = format!("{}; failed to focus '{}': {}", result.message, app_title, e);
+            }
+        }
+    }
+
+    // Let other phones move their volume sliders
+    if let Some(volume) = result.volume {
+        events::publish(events, ServerEvent::Volume(volume));
+    }
+
+    let record = ExecutionRecord {
+        id: 0,
+        command_id: cmd.id.clone(),
+        command_name: cmd.name.clone(),
+        executed_at,
+        duration_ms: started.elapsed().as_millis() as u64,
+        success: result.success,
+        output: history::truncate_output(&result.message),
+        exit_code: result.exit_code,
+        client: client.to_string(),
+    };
+
+    let db = database.lock().await;
+    if let Err(e) = db.record_execution(&record) {
+        eprintln!("Failed to record execution of '{}': {}", cmd.id, e);
+    }
+    let retention_days = db
+        .get_settings()
+        .history_retention_days
+        .unwrap_or(history::DEFAULT_RETENTION_DAYS);
+    if let Err(e) = db.prune_executions(retention_days) {
+        eprintln!("Failed to prune execution history: {}", e);
+    }
+    drop(db);
+
+    result
+}
+
+/// Persist the on/off state of a toggle and notify clients
+pub async fn set_toggle_state(
+    cmd: &CommandConfig,
+    active: bool,
+    database: &SharedDatabase,
+    events: &EventSender,
+) {
+    if let Err(e) = database.lock().await.set_toggle_state(&cmd.id, active) {
+        eprintln!("Failed to save toggle state for '{}': {}", cmd.id, e);
+    }
+
+    events::publish(
+        events,
+        ServerEvent::CommandState {
+            id: cmd.id.clone(),
+            state: cmd.toggle_state(active),
+        },
+    );
+}
+
+async fn run_blocking<F>(f: F) -> CommandResult
+where
+    F: FnOnce() -> CommandResult + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|e| CommandResult::err(format!("Command panicked: {}", e)))
+}
@@ -0,0 +1,219 @@
+//! Read-only access to allow-listed directories
+//!
+//! Paths from the phone are relative to one of the browse roots in the
+//! settings. Every path is canonicalized before use, which resolves `..` and
+//! symlinks, and anything that doesn't end up inside its root is treated as
+//! missing.
+
+use crate::config::SharedDirectory;
+use crate::system_commands;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Entries listed per directory; larger directories are cut off
+const MAX_ENTRIES: usize = 5000;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Directory,
+    File,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    /// Size in bytes (files only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Last modification time in RFC 3339
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryListing {
+    pub root: String,
+    /// Path relative to the root, with `/` separators; empty for the root itself
+    pub path: String,
+    pub entries: Vec<FileEntry>,
+    /// Whether entries were left out because the directory is too large
+    pub truncated: bool,
+}
+
+/// Canonical path of a shared directory, which must already exist
+pub fn resolve_root(dir: &SharedDirectory) -> Result<PathBuf, String> {
+    let path = system_commands::expand_home(&dir.path);
+    let root = Path::new(&path)
+        .canonicalize()
+        .map_err(|e| format!("Directory '{}' is not available: {}", path, e))?;
+    if !root.is_dir() {
+        return Err(format!("'{}' is not a directory", path));
+    }
+    Ok(root)
+}
+
+/// Canonical path of `relative` inside `root`, `None` if missing or outside the root
+pub fn resolve(root: &Path, relative: &str) -> Option<PathBuf> {
+    let relative = Path::new(relative.trim_start_matches('/'));
+    // Reject `..` and absolute parts up front, symlinks are caught below
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return None;
+    }
+
+    let path = root.join(relative).canonicalize().ok()?;
+    path.starts_with(root).then_some(path)
+}
+
+/// List a directory inside `root`, directories first, then by name
+pub fn list(root_id: &str, root: &Path, dir: &Path) -> Result<DirectoryListing, String> {
+    let read = fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    for entry in read.flatten() {
+        if entries.len() >= MAX_ENTRIES {
+            truncated = true;
+            break;
+        }
+        // Symlinks pointing out of the root are hidden like they don't exist
+        let Ok(target) = entry.path().canonicalize() else { continue };
+        if !target.starts_with(root) {
+            continue;
+        }
+        let Ok(metadata) = fs::metadata(&target) else { continue };
+        let kind = if metadata.is_dir() {
+            EntryKind::Directory
+        } else if metadata.is_file() {
+            EntryKind::File
+        } else {
+            continue;
+        };
+
+        entries.push(FileEntry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            kind,
+            size: (kind == EntryKind::File).then_some(metadata.len()),
+            modified: metadata
+                .modified()
+                .ok()
+                .map(|t| DateTime::<Utc>::from(t).to_rfc3339()),
+        });
+    }
+
+    entries.sort_by(|a, b| {
+        (a.kind != EntryKind::Directory)
+            .cmp(&(b.kind != EntryKind::Directory))
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+
+    let path = dir
+        .strip_prefix(root)
+        .unwrap_or(Path::new(""))
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    Ok(DirectoryListing {
+        root: root_id.to_string(),
+        path,
+        entries,
+        truncated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// A canonical root holding `a/b/file.txt`, next to a directory with `secret.txt`
+    struct Tree {
+        _base: tempfile::TempDir,
+        root: PathBuf,
+        outside: PathBuf,
+    }
+
+    fn tree() -> Tree {
+        let base = tempfile::tempdir().unwrap();
+        let root = base.path().join("root");
+        let outside = base.path().join("outside");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(root.join("a/b/file.txt"), "inside").unwrap();
+        fs::write(outside.join("secret.txt"), "outside").unwrap();
+        Tree {
+            root: root.canonicalize().unwrap(),
+            outside: outside.canonicalize().unwrap(),
+            _base: base,
+        }
+    }
+
+    #[test]
+    fn resolves_nested_paths() {
+        let tree = tree();
+        let file = tree.root.join("a/b/file.txt");
+        assert_eq!(resolve(&tree.root, "a/b/file.txt"), Some(file.clone()));
+        assert_eq!(resolve(&tree.root, "/a/./b/file.txt"), Some(file));
+        assert_eq!(resolve(&tree.root, ""), Some(tree.root.clone()));
+        assert_eq!(resolve(&tree.root, "a/missing.txt"), None);
+    }
+
+    #[test]
+    fn refuses_parent_components() {
+        let tree = tree();
+        assert_eq!(resolve(&tree.root, ".."), None);
+        assert_eq!(resolve(&tree.root, "../outside/secret.txt"), None);
+        assert_eq!(resolve(&tree.root, "a/../../outside/secret.txt"), None);
+        // Even when it would end up inside the root again
+        assert_eq!(resolve(&tree.root, "a/../a/b/file.txt"), None);
+    }
+
+    #[test]
+    fn refuses_percent_encoded_parents_once_decoded() {
+        let tree = tree();
+        let uri = "/api/files?path=%2e%2e%2Foutside%2Fsecret.txt".parse().unwrap();
+        let query = axum::extract::Query::<HashMap<String, String>>::try_from_uri(&uri).unwrap();
+        assert_eq!(query.0["path"], "../outside/secret.txt");
+        assert_eq!(resolve(&tree.root, &query.0["path"]), None);
+    }
+
+    #[test]
+    fn keeps_absolute_paths_inside_the_root() {
+        let tree = tree();
+        let absolute = tree.outside.join("secret.txt");
+        assert_eq!(resolve(&tree.root, &absolute.to_string_lossy()), None);
+        assert_eq!(resolve(&tree.root, "//a/b/file.txt"), Some(tree.root.join("a/b/file.txt")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_symlinks_leading_out() {
+        use std::os::unix::fs::symlink;
+
+        let tree = tree();
+        symlink("/", tree.root.join("slash")).unwrap();
+        symlink(&tree.outside, tree.root.join("a/out")).unwrap();
+        symlink(tree.outside.join("secret.txt"), tree.root.join("secret.txt")).unwrap();
+        symlink(tree.root.join("a/b"), tree.root.join("alias")).unwrap();
+
+        assert_eq!(resolve(&tree.root, "slash"), None);
+        assert_eq!(resolve(&tree.root, "slash/etc"), None);
+        assert_eq!(resolve(&tree.root, "a/out"), None);
+        assert_eq!(resolve(&tree.root, "a/out/secret.txt"), None);
+        assert_eq!(resolve(&tree.root, "secret.txt"), None);
+        // Symlinks that stay inside the root are fine
+        assert_eq!(resolve(&tree.root, "alias/file.txt"), Some(tree.root.join("a/b/file.txt")));
+
+        let listing = list("docs", &tree.root, &tree.root).unwrap();
+        let names: Vec<_> = listing.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["a", "alias"]);
+    }
+}
@@ -0,0 +1,74 @@
+//! Automatic profile switching based on the focused application
+//!
+//! Polls the foreground window and activates the first profile (in profile
+//! order) whose app matchers match it. When nothing matches, the current
+//! profile stays active.
+
+use crate::database::SharedDatabase;
+use crate::events::{self, EventSender, ServerEvent};
+use crate::system_commands::{self, FocusedApp};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// How often the foreground window is checked
+const POLL_INTERVAL: Duration = Duration::from_millis(750);
+
+/// ID of the profile currently shown on the phones
+pub type ActiveProfile = Arc<Mutex<Option<String>>>;
+
+pub fn create_active_profile() -> ActiveProfile {
+    Arc::new(Mutex::new(None))
+}
+
+pub async fn run_focus_watcher(database: SharedDatabase, active_profile: ActiveProfile, events: EventSender) {
+    let mut last_focused: Option<FocusedApp> = None;
+    let mut ticker = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+
+        let profiles = {
+            let db = database.lock().await;
+            db.get_profiles().unwrap_or_default()
+        };
+
+        // Don't query the window system when no profile is bound to an app
+        if profiles.iter().all(|p| p.app_matchers.is_empty()) {
+            last_focused = None;
+            continue;
+        }
+
+        let focused = tokio::task::spawn_blocking(system_commands::get_focused_application)
+            .await
+            .ok()
+            .flatten();
+
+        if focused == last_focused {
+            continue;
+        }
+        last_focused = focused.clone();
+
+        let focused = match focused {
+            Some(app) => app,
+            None => continue,
+        };
+
+        let profile = match profiles.into_iter().find(|p| p.matches(&focused)) {
+            Some(profile) => profile,
+            None => continue,
+        };
+
+        let mut active = active_profile.lock().await;
+        if active.as_deref() != Some(profile.id.as_str()) {
+            *active = Some(profile.id.clone());
+            events::publish(
+                &events,
+                ServerEvent::ActiveProfile {
+                    profile_id: profile.id,
+                    page_id: profile.home_page_id,
+                },
+            );
+        }
+    }
+}
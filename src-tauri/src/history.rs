@@ -0,0 +1,83 @@
+//! Persistent record of executed commands
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Days of history kept when no retention is configured
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+/// Longest output stored per execution, in bytes
+const MAX_OUTPUT_LEN: usize = 4096;
+
+/// Largest page size accepted by history queries
+pub const MAX_PAGE_SIZE: u32 = 500;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+
+/// One executed command
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionRecord {
+    pub id: i64,
+    pub command_id: String,
+    /// Name of the command at the time it ran
+    pub command_name: String,
+    pub executed_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// Output or error message, truncated to a few KB
+    pub output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Who triggered it: the client IP, "scheduler:<id>" or "desktop"
+    pub client: String,
+}
+
+/// Filters and paging for history queries; every field is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HistoryFilter {
+    pub command_id: Option<String>,
+    pub success: Option<bool>,
+    pub client: Option<String>,
+    /// Only executions at or after this time (RFC 3339)
+    pub since: Option<DateTime<Utc>>,
+    /// Only executions before this time (RFC 3339)
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl HistoryFilter {
+    pub fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+    }
+
+    pub fn offset(&self) -> u32 {
+        self.offset.unwrap_or(0)
+    }
+}
+
+/// A page of history, newest first
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPage {
+    /// Number of executions matching the filter, across all pages
+    pub total: u64,
+    pub items: Vec<ExecutionRecord>,
+}
+
+/// Format a timestamp the way it is stored, so stored values sort and compare as text
+pub fn format_timestamp(at: &DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Cut output down to `MAX_OUTPUT_LEN` bytes without splitting a character
+pub fn truncate_output(output: &str) -> String {
+    if output.len() <= MAX_OUTPUT_LEN {
+        return output.to_string();
+    }
+
+    let mut end = MAX_OUTPUT_LEN;
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}… (truncated)", &output[..end])
+}
@@ -0,0 +1,51 @@
+//! Processing of uploaded button images
+//!
+//! Images are decoded, downscaled to fit the button grid and re-encoded as PNG
+//! before being stored in the database, so the phone never downloads originals.
+
+use crate::database::CommandImage;
+use image::{imageops::FilterType, ImageFormat};
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+
+/// Largest width/height of a stored button image in pixels
+const MAX_IMAGE_SIZE: u32 = 256;
+
+/// Largest accepted upload before decoding (10 MB)
+const MAX_UPLOAD_BYTES: usize = 10 * 1024 * 1024;
+
+/// Decode an uploaded image, resize it and re-encode it as PNG
+pub fn process_uploaded_image(bytes: &[u8]) -> Result<CommandImage, String> {
+    if bytes.is_empty() {
+        return Err("Image is empty".to_string());
+    }
+    if bytes.len() > MAX_UPLOAD_BYTES {
+        return Err(format!(
+            "Image is too large ({} bytes, max {} bytes)",
+            bytes.len(),
+            MAX_UPLOAD_BYTES
+        ));
+    }
+
+    let img = image::load_from_memory(bytes)
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+
+    let img = if img.width() > MAX_IMAGE_SIZE || img.height() > MAX_IMAGE_SIZE {
+        img.resize(MAX_IMAGE_SIZE, MAX_IMAGE_SIZE, FilterType::Lanczos3)
+    } else {
+        img
+    };
+
+    let mut data = Vec::new();
+    img.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+
+    // A content hash that stays the same across builds, so phones keep their caches
+    let etag = format!("\"{:x}\"", Sha256::digest(&data));
+
+    Ok(CommandImage {
+        mime_type: "image/png".to_string(),
+        data,
+        etag,
+    })
+}
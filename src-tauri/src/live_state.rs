@@ -0,0 +1,126 @@
+//! Periodic evaluation of command state queries
+//!
+//! Results are cached so `/api/commands` can return them without running the
+//! probes on every request, and changes are published as events. The output
+//! volume is watched here too, so changes from outside the app reach phones.
+
+use crate::audio;
+use crate::commands::{CommandConfig, CommandState, CommandType};
+use crate::database::SharedDatabase;
+use crate::events::{self, EventSender, ServerEvent};
+use crate::executor;
+use crate::system_commands;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// How often the poller checks which queries are due
+const POLL_TICK: Duration = Duration::from_secs(1);
+
+/// Last known state of each command, keyed by command ID
+pub type StateCache = Arc<Mutex<HashMap<String, CommandState>>>;
+
+pub fn create_state_cache() -> StateCache {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// Evaluate the state query of a command and publish it if it changed
+///
+/// For toggles the probe result also re-syncs the persisted on/off state.
+pub async fn refresh_command_state(
+    cmd: &CommandConfig,
+    database: &SharedDatabase,
+    cache: &StateCache,
+    events: &EventSender,
+) {
+    let query = match cmd.state_query {
+        Some(ref query) => query.clone(),
+        None => return,
+    };
+
+    let result = tokio::task::spawn_blocking(move || system_commands::query_state(&query)).await;
+
+    let state = match result {
+        Ok(Ok(state)) => state,
+        Ok(Err(e)) => {
+            eprintln!("State query for '{}' failed: {}", cmd.id, e);
+            return;
+        }
+        Err(e) => {
+            eprintln!("State query for '{}' panicked: {}", cmd.id, e);
+            return;
+        }
+    };
+
+    if cmd.command_type == CommandType::Toggle {
+        // A tap in progress saves the flipped state itself, so don't overwrite it mid-action
+        if let Ok(_turn) = executor::toggle_lock(&cmd.id).try_lock_owned() {
+            let db = database.lock().await;
+            if db.get_toggle_state(&cmd.id) != state.active {
+                if let Err(e) = db.set_toggle_state(&cmd.id, state.active) {
+                    eprintln!("Failed to save toggle state for '{}': {}", cmd.id, e);
+                }
+            }
+        }
+    }
+
+    let mut cache = cache.lock().await;
+    if cache.get(&cmd.id) != Some(&state) {
+        cache.insert(cmd.id.clone(), state.clone());
+        events::publish(
+            events,
+            ServerEvent::CommandState {
+                id: cmd.id.clone(),
+                state,
+            },
+        );
+    }
+}
+
+/// Re-evaluate state queries whose interval has elapsed, forever
+///
+/// Every query is evaluated once at startup, including those without an interval.
+pub async fn run_state_poller(database: SharedDatabase, cache: StateCache, events: EventSender) {
+    let mut last_run: HashMap<String, Instant> = HashMap::new();
+    let mut ticker = tokio::time::interval(POLL_TICK);
+
+    loop {
+        ticker.tick().await;
+
+        // Only worth reading while someone is subscribed to events
+        if events.receiver_count() > 0 {
+            if let Ok(Ok(Some(volume))) = tokio::task::spawn_blocking(audio::poll_change).await {
+                events::publish(&events, ServerEvent::Volume(volume));
+            }
+        }
+
+        let commands = {
+            let db = database.lock().await;
+            db.get_all_commands().unwrap_or_default()
+        };
+
+        // Forget state of commands that were removed or lost their query
+        let has_query = |id: &String| commands.iter().any(|c| &c.id == id && c.state_query.is_some());
+        cache.lock().await.retain(|id, _| has_query(id));
+        last_run.retain(|id, _| has_query(id));
+
+        for cmd in &commands {
+            let query = match cmd.state_query {
+                Some(ref query) => query,
+                None => continue,
+            };
+
+            let due = match (last_run.get(&cmd.id), query.interval_secs) {
+                (None, _) => true,
+                (Some(last), Some(secs)) => last.elapsed() >= Duration::from_secs(secs),
+                (Some(_), None) => false,
+            };
+
+            if due {
+                last_run.insert(cmd.id.clone(), Instant::now());
+                refresh_command_state(cmd, &database, &cache, &events).await;
+            }
+        }
+    }
+}
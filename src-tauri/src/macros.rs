@@ -0,0 +1,517 @@
+//! Execution of macro commands
+//!
+//! Steps run in order on the calling thread. Each step is turned into a plain
+//! command and dispatched through `system_commands::execute_command`, except
+//! delays which simply sleep and If steps which pick a branch.
+//!
+//! Steps can capture their output and exit code into variables, which later
+//! steps reference as `${name}`. Values substituted into shell commands are
+//! escaped for the platform shell, taking into account whether the reference
+//! sits inside quotes, so the shell always sees them as literal text.
+
+use crate::commands::{
+    CommandConfig, CommandType, CompareOp, Condition, ErrorPolicy, MacroStep, StepType,
+};
+use crate::system_commands::{self, CommandResult, StepOutcome};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::thread;
+use std::time::Duration;
+
+/// Extra attempts for Retry steps when `retries` is unset
+const DEFAULT_RETRIES: u32 = 2;
+
+/// Pause between attempts when `retry_delay_ms` is unset
+const DEFAULT_RETRY_DELAY_MS: u64 = 250;
+
+/// Variables captured so far, by name
+pub type Variables = HashMap<String, String>;
+
+/// Run all steps of a macro, honoring each step's error policy
+pub fn execute_macro(steps: &[MacroStep]) -> CommandResult {
+    if steps.is_empty() {
+        return CommandResult::err("Macro has no steps");
+    }
+
+    let mut vars = Variables::new();
+    let mut outcomes = Vec::new();
+    let completed = run_steps(steps, None, &mut vars, &mut outcomes);
+
+    let total = outcomes.len();
+    let succeeded = outcomes.iter().filter(|o| o.success).count();
+    let mut result = if completed {
+        CommandResult::ok(format!("{} of {} steps succeeded", succeeded, total))
+    } else {
+        let failed = outcomes.last();
+        CommandResult::err(format!(
+            "Macro stopped at step {}: {}",
+            failed.map(step_label).unwrap_or_default(),
+            failed.map(|o| o.message.as_str()).unwrap_or_default()
+        ))
+    };
+    result.steps = outcomes;
+    result
+}
+
+/// Human-readable position of a step, e.g. "3" or "2.then.1" (1-based)
+fn step_label(outcome: &StepOutcome) -> String {
+    match outcome.parent {
+        Some(ref parent) => format!("{}.{}", one_based_path(parent), outcome.index + 1),
+        None => (outcome.index + 1).to_string(),
+    }
+}
+
+fn one_based_path(path: &str) -> String {
+    path.split('.')
+        .map(|part| match part.parse::<usize>() {
+            Ok(i) => (i + 1).to_string(),
+            Err(_) => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Run a list of steps, returning false if the macro was aborted
+fn run_steps(
+    steps: &[MacroStep],
+    parent: Option<&str>,
+    vars: &mut Variables,
+    outcomes: &mut Vec<StepOutcome>,
+) -> bool {
+    for (index, step) in steps.iter().enumerate() {
+        if step.step_type == StepType::If {
+            let (branch, result) = match step.condition {
+                Some(ref condition) => match evaluate_condition(condition, vars) {
+                    Ok(true) => ("then", CommandResult::ok("Condition is true, running then")),
+                    Ok(false) => ("else", CommandResult::ok("Condition is false, running else")),
+                    Err(e) => ("", CommandResult::err(e)),
+                },
+                None => ("", CommandResult::err("If step needs a condition")),
+            };
+
+            let success = result.success;
+            outcomes.push(outcome(index, step, parent, result, 1));
+            if !success {
+                if step.on_error == ErrorPolicy::Continue {
+                    continue;
+                }
+                return false;
+            }
+
+            let branch_steps = if branch == "then" { &step.then_steps } else { &step.else_steps };
+            let path = match parent {
+                Some(parent) => format!("{}.{}.{}", parent, index, branch),
+                None => format!("{}.{}", index, branch),
+            };
+            if !run_steps(branch_steps, Some(&path), vars, outcomes) {
+                return false;
+            }
+            continue;
+        }
+
+        let max_attempts = match step.on_error {
+            ErrorPolicy::Retry => 1 + step.retries.unwrap_or(DEFAULT_RETRIES),
+            _ => 1,
+        };
+
+        let mut attempts = 0;
+        let result = loop {
+            attempts += 1;
+            let result = execute_step(step, vars);
+            if result.success || attempts >= max_attempts {
+                break result;
+            }
+            thread::sleep(Duration::from_millis(
+                step.retry_delay_ms.unwrap_or(DEFAULT_RETRY_DELAY_MS),
+            ));
+        };
+
+        if let Some(ref name) = step.capture_output {
+            vars.insert(name.clone(), result.message.trim_end().to_string());
+        }
+        if let Some(ref name) = step.capture_exit_code {
+            let code = result.exit_code.unwrap_or(if result.success { 0 } else { 1 });
+            vars.insert(name.clone(), code.to_string());
+        }
+
+        let success = result.success;
+        outcomes.push(outcome(index, step, parent, result, attempts));
+
+        if !success && step.on_error != ErrorPolicy::Continue {
+            return false;
+        }
+    }
+    true
+}
+
+fn outcome(
+    index: usize,
+    step: &MacroStep,
+    parent: Option<&str>,
+    result: CommandResult,
+    attempts: u32,
+) -> StepOutcome {
+    StepOutcome {
+        index,
+        step_type: step.step_type.clone(),
+        success: result.success,
+        message: result.message,
+        attempts,
+        parent: parent.map(str::to_string),
+    }
+}
+
+/// Run a single step once, with variables substituted into its fields
+fn execute_step(step: &MacroStep, vars: &Variables) -> CommandResult {
+    let command_type = match step.step_type {
+        StepType::Delay => {
+            let ms = step.delay_ms.unwrap_or_default();
+            thread::sleep(Duration::from_millis(ms));
+            return CommandResult::ok(format!("Waited {}ms", ms));
+        }
+        StepType::If => return CommandResult::err("If steps are handled by run_steps"),
+        StepType::Shell => CommandType::Shell,
+        StepType::Volume => CommandType::Volume,
+        StepType::OpenDirectory => CommandType::OpenDirectory,
+        StepType::FocusApp => CommandType::FocusApp,
+        StepType::Keybind => CommandType::Keybind,
+    };
+
+    match step_config(step, command_type, vars) {
+        Ok(config) => system_commands::execute_command(&config),
+        Err(e) => CommandResult::err(e),
+    }
+}
+
+/// Build the command a step runs, substituting variables into its fields
+fn step_config(
+    step: &MacroStep,
+    command_type: CommandType,
+    vars: &Variables,
+) -> Result<CommandConfig, String> {
+    let literal = |field: &Option<String>| field.as_deref().map(|t| substitute(t, vars, str::to_string));
+
+    Ok(CommandConfig {
+        command_type,
+        command: step.command.as_deref().map(|t| shell_command(t, vars)).transpose()?,
+        volume_direction: step.volume_direction.clone(),
+        volume_step: step.volume_step,
+        volume_level: step.volume_level,
+        volume_max: step.volume_max,
+        directory_path: literal(&step.directory_path),
+        app_name: literal(&step.app_name),
+        keybind: literal(&step.keybind),
+        ..Default::default()
+    })
+}
+
+/// Evaluate the condition of an If step
+fn evaluate_condition(condition: &Condition, vars: &Variables) -> Result<bool, String> {
+    let left = substitute(&condition.left, vars, str::to_string);
+    let right = condition
+        .right
+        .as_deref()
+        .map(|r| substitute(r, vars, str::to_string))
+        .unwrap_or_default();
+
+    let number = |s: &str| -> Result<f64, String> {
+        s.trim()
+            .parse::<f64>()
+            .map_err(|_| format!("'{}' is not a number", s))
+    };
+
+    Ok(match condition.op {
+        CompareOp::Equals => left == right,
+        CompareOp::NotEquals => left != right,
+        CompareOp::Contains => left.contains(&right),
+        CompareOp::NotContains => !left.contains(&right),
+        CompareOp::GreaterThan => number(&left)? > number(&right)?,
+        CompareOp::LessThan => number(&left)? < number(&right)?,
+        CompareOp::IsEmpty => left.trim().is_empty(),
+        CompareOp::NotEmpty => !left.trim().is_empty(),
+        CompareOp::AppRunning => system_commands::is_app_running(&left)?,
+    })
+}
+
+/// Replace `${name}` references with variable values passed through `escape`
+///
+/// Anything that isn't a known variable is kept as is, so shell syntax like
+/// `$HOME`, `${PATH}` or `${1:-x}` still works.
+pub fn substitute(template: &str, vars: &Variables, escape: impl Fn(&str) -> String) -> String {
+    replace_variables(template, vars, |value, _| Ok::<_, Infallible>(escape(value)))
+        .unwrap_or_else(|never| match never {})
+}
+
+/// Substitute variables into a shell command, escaped for the platform shell
+///
+/// Fails on Windows for values cmd.exe cannot take literally at that position.
+fn shell_command(template: &str, vars: &Variables) -> Result<String, String> {
+    replace_variables(template, vars, |value, before| {
+        if cfg!(target_os = "windows") {
+            cmd_quote(value, cmd_quoting(before))
+        } else {
+            Ok(sh_quote(value, sh_quoting(before)))
+        }
+    })
+}
+
+/// Core of `substitute`: `escape` gets each value and the template text before it
+fn replace_variables<E>(
+    template: &str,
+    vars: &Variables,
+    escape: impl Fn(&str, &str) -> Result<String, E>,
+) -> Result<String, E> {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+
+        let value = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            crate::commands::is_valid_variable_name(name)
+                .then(|| vars.get(name))
+                .flatten()
+                .map(|value| (name, value))
+        });
+        match value {
+            Some((name, value)) => {
+                let before = &template[..template.len() - rest.len() + start];
+                result.push_str(&escape(value, before)?);
+                rest = &after[name.len() + 1..];
+            }
+            _ => {
+                result.push_str("${");
+                rest = after;
+            }
+        }
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Quoting in effect at some point of a command line
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Quoting {
+    None,
+    Single,
+    Double,
+}
+
+/// Quoting in effect at the end of `text`, following POSIX sh rules
+///
+/// Quotes inside `$(...)` or backticks are not tracked separately.
+fn sh_quoting(text: &str) -> Quoting {
+    let mut quoting = Quoting::None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        quoting = match (quoting, c) {
+            (Quoting::None | Quoting::Double, '\\') => {
+                chars.next();
+                quoting
+            }
+            (Quoting::None, '\'') => Quoting::Single,
+            (Quoting::None, '"') => Quoting::Double,
+            (Quoting::Single, '\'') | (Quoting::Double, '"') => Quoting::None,
+            _ => quoting,
+        };
+    }
+    quoting
+}
+
+/// Escape a value so POSIX sh takes it literally at a point with the given quoting
+fn sh_quote(value: &str, quoting: Quoting) -> String {
+    match quoting {
+        // Nothing is special inside single quotes except the quote itself
+        Quoting::None => format!("'{}'", value.replace('\'', r"'\''")),
+        Quoting::Single => value.replace('\'', r"'\''"),
+        Quoting::Double => {
+            let mut escaped = String::with_capacity(value.len());
+            for c in value.chars() {
+                if matches!(c, '\\' | '"' | '$' | '`') {
+                    escaped.push('\\');
+                }
+                escaped.push(c);
+            }
+            escaped
+        }
+    }
+}
+
+/// Quoting in effect at the end of `text` for cmd.exe, where every unescaped `"` toggles it
+fn cmd_quoting(text: &str) -> Quoting {
+    let mut quoting = Quoting::None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        quoting = match (quoting, c) {
+            (Quoting::None, '^') => {
+                chars.next();
+                quoting
+            }
+            (Quoting::None, '"') => Quoting::Double,
+            (Quoting::Double, '"') => Quoting::None,
+            _ => quoting,
+        };
+    }
+    quoting
+}
+
+/// Escape a value so cmd.exe passes it literally as a program argument
+///
+/// Outside quotes the value is quoted by the rules programs use to split their
+/// command line, then every character cmd.exe treats specially is escaped with
+/// `^`, so no quote in it changes cmd's quoting. Inside quotes `^` has no effect,
+/// so values containing what cannot be escaped there are refused. cmd.exe ends a
+/// command at a line break, so line breaks are refused everywhere.
+fn cmd_quote(value: &str, quoting: Quoting) -> Result<String, String> {
+    if value.contains(['\r', '\n']) {
+        return Err("Variables with line breaks cannot be used in Windows commands".to_string());
+    }
+
+    if quoting == Quoting::Double {
+        if value.contains(['"', '%']) || value.ends_with('\\') {
+            return Err(format!(
+                "'{}' cannot be placed inside quotes in a Windows command, put the variable outside them",
+                value
+            ));
+        }
+        return Ok(value.to_string());
+    }
+
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in value.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            // Backslashes before a quote are doubled, plus one to escape the quote
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes + 1));
+                backslashes = 0;
+            }
+            _ => backslashes = 0,
+        }
+        quoted.push(c);
+    }
+    quoted.push_str(&"\\".repeat(backslashes));
+    quoted.push('"');
+
+    let mut escaped = String::with_capacity(quoted.len() * 2);
+    for c in quoted.chars() {
+        if matches!(c, '(' | ')' | '%' | '!' | '^' | '"' | '<' | '>' | '&' | '|') {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    Ok(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRICKY: &[&str] = &[
+        "it's",
+        "say \"hi\"",
+        "$(touch /tmp/pwned)",
+        "`id`",
+        "line one\nline two",
+        "back\\slash $HOME ${HOME} 'both' \"kinds\"",
+        "",
+    ];
+
+    fn vars(value: &str) -> Variables {
+        Variables::from([("x".to_string(), value.to_string())])
+    }
+
+    #[test]
+    fn keeps_what_is_not_a_known_variable() {
+        let vars = vars("value");
+        let keep = |t: &str| substitute(t, &vars, str::to_string);
+
+        assert_eq!(keep("a ${unknown} b"), "a ${unknown} b");
+        assert_eq!(keep("${1:-x} $HOME ${HOME}"), "${1:-x} $HOME ${HOME}");
+        assert_eq!(keep("open ${x"), "open ${x");
+        assert_eq!(keep("${x} ${"), "value ${");
+        assert_eq!(keep("$${x}}"), "$value}");
+    }
+
+    #[test]
+    fn tracks_sh_quoting() {
+        assert_eq!(sh_quoting("echo "), Quoting::None);
+        assert_eq!(sh_quoting("echo '"), Quoting::Single);
+        assert_eq!(sh_quoting("echo \"a"), Quoting::Double);
+        assert_eq!(sh_quoting("echo \"a\\\""), Quoting::Double);
+        assert_eq!(sh_quoting("echo \\'"), Quoting::None);
+        assert_eq!(sh_quoting("echo '\\'"), Quoting::None);
+        assert_eq!(sh_quoting("echo \"it's"), Quoting::Double);
+    }
+
+    #[cfg(unix)]
+    fn sh_output(command: &str) -> String {
+        let output = std::process::Command::new("sh")
+            .args(["-c", command])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}: {:?}", command, output);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_gets_values_literally() {
+        for value in TRICKY {
+            let vars = vars(value);
+            for (template, expected) in [
+                ("printf %s ${x}", value.to_string()),
+                ("printf %s \"${x}\"", value.to_string()),
+                ("printf %s '${x}'", value.to_string()),
+                ("printf %s \"<${x}>\"", format!("<{}>", value)),
+                ("printf %s 'it'\\''s ${x}'", format!("it's {}", value)),
+                ("printf %s \"${x}\"'${x}'${x}", value.repeat(3)),
+            ] {
+                let command = shell_command(template, &vars).unwrap();
+                assert_eq!(sh_output(&command), expected, "{}", command);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_syntax_passes_through() {
+        let vars = vars("v");
+        let command = shell_command("X=1; printf %s \"${X}$X${1:-d}${x}${missing}\"", &vars).unwrap();
+        assert_eq!(sh_output(&command), "11dv");
+    }
+
+    #[test]
+    fn cmd_escapes_outside_quotes() {
+        let quote = |value: &str| cmd_quote(value, Quoting::None).unwrap();
+
+        assert_eq!(quote("plain"), "^\"plain^\"");
+        assert_eq!(quote("a & b | c"), "^\"a ^& b ^| c^\"");
+        assert_eq!(quote("say \"hi\""), "^\"say \\^\"hi\\^\"^\"");
+        assert_eq!(quote("100% ^(x)!"), "^\"100^% ^^^(x^)^!^\"");
+        assert_eq!(quote("C:\\dir\\"), "^\"C:\\dir\\\\^\"");
+        assert_eq!(quote("a\\\"b"), "^\"a\\\\\\^\"b^\"");
+    }
+
+    #[test]
+    fn cmd_refuses_what_it_cannot_escape() {
+        assert!(cmd_quote("two\nlines", Quoting::None).is_err());
+        assert!(cmd_quote("a \"quote\"", Quoting::Double).is_err());
+        assert!(cmd_quote("50%", Quoting::Double).is_err());
+        assert!(cmd_quote("C:\\dir\\", Quoting::Double).is_err());
+        assert_eq!(cmd_quote("a & b", Quoting::Double).unwrap(), "a & b");
+    }
+
+    #[test]
+    fn tracks_cmd_quoting() {
+        assert_eq!(cmd_quoting("echo "), Quoting::None);
+        assert_eq!(cmd_quoting("echo \"a"), Quoting::Double);
+        assert_eq!(cmd_quoting("echo \"a\" "), Quoting::None);
+        assert_eq!(cmd_quoting("echo ^\"a"), Quoting::None);
+        assert_eq!(cmd_quoting("echo \"a^\""), Quoting::None);
+    }
+}
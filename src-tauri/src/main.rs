@@ -1,16 +1,47 @@
 // Prevents additional console window on Windows in release mode
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod audio;
+mod audit;
+mod clipboard;
 mod commands;
 mod config;
 mod database;
+mod events;
+mod executor;
+mod files;
+mod focus_watcher;
+mod history;
+mod icons;
+mod live_state;
+mod macros;
+mod media;
+mod mouse;
+mod profiles;
+mod remote_input;
+mod scheduler;
+mod screen;
+mod screen_stream;
 mod server;
 mod system_commands;
+#[cfg(target_os = "linux")]
+mod uinput;
+mod upload;
+#[cfg(target_os = "linux")]
+mod wayland;
+mod window_manager;
 mod windows_focus;
+#[cfg(target_os = "linux")]
+mod x11;
 
+use audit::{AuditEvent, AuditVerification};
 use commands::CommandConfig;
 use config::Settings;
-use database::{create_shared_database, SharedDatabase};
+use database::{create_shared_database, Database, SharedDatabase};
+use events::EventSender;
+use history::{HistoryFilter, HistoryPage};
+use profiles::{Page, Profile, SlotKind};
+use scheduler::Schedule;
 use server::ServerHandle;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -20,6 +51,14 @@ use tokio::sync::Mutex;
 struct AppState {
     server_handle: Arc<Mutex<Option<ServerHandle>>>,
     database: SharedDatabase,
+    events: EventSender,
+}
+
+/// Record a change made from the desktop app; failures are logged, not returned
+fn audit_desktop(db: &Database, event: AuditEvent, details: serde_json::Value) {
+    if let Err(e) = db.append_audit(event, "desktop", &details) {
+        eprintln!("Failed to write audit log: {}", e);
+    }
 }
 
 #[tauri::command]
@@ -35,6 +74,9 @@ async fn toggle_server(
         // Gracefully shutdown the server
         handle.shutdown();
         println!("Server stopped");
+
+        let db = state.database.lock().await;
+        audit_desktop(&db, AuditEvent::ServerStopped, serde_json::json!({}));
         Ok(false)
     } else {
         // Save settings to database
@@ -70,11 +112,15 @@ async fn toggle_server(
         let server_handle = server::start_server(
             settings.clone(),
             state.database.clone(),
+            state.events.clone(),
             mobile_dist_path,
         ).await?;
 
         *handle_lock = Some(server_handle);
         println!("Server started on port {}", settings.port);
+
+        let db = state.database.lock().await;
+        audit_desktop(&db, AuditEvent::ServerStarted, serde_json::json!({ "port": settings.port }));
         Ok(true)
     }
 }
@@ -93,7 +139,9 @@ async fn save_settings(
     settings.validate()?;
     
     let db = state.database.lock().await;
+    let previous = db.get_settings();
     db.save_settings(&settings).map_err(|e| e.to_string())?;
+    audit_desktop(&db, AuditEvent::SettingsSaved, audit::settings_diff(&previous, &settings));
     Ok(())
 }
 
@@ -105,6 +153,7 @@ async fn regenerate_auth_code(state: State<'_, AppState>) -> Result<String, Stri
     let mut settings = db.get_settings();
     settings.auth_code = new_code.clone();
     db.save_settings(&settings).map_err(|e| e.to_string())?;
+    audit_desktop(&db, AuditEvent::AuthCodeRegenerated, serde_json::json!({}));
     
     Ok(new_code)
 }
@@ -120,8 +169,276 @@ async fn save_commands(
     commands_vec: Vec<CommandConfig>,
     state: State<'_, AppState>,
 ) -> Result<(), String> {
+    for cmd in &commands_vec {
+        cmd.validate()?;
+    }
+
+    let db = state.database.lock().await;
+    let previous = db.get_all_commands().map_err(|e| e.to_string())?;
+    db.save_all_commands(&commands_vec).map_err(|e| e.to_string())?;
+    executor::prune_toggle_locks(&commands_vec);
+    audit_desktop(&db, AuditEvent::CommandsSaved, audit::commands_diff(&previous, &commands_vec));
+    Ok(())
+}
+
+/// Upload a button image for a command (base64-encoded file contents)
+#[tauri::command]
+async fn set_command_image(
+    id: String,
+    image_base64: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    use base64::Engine;
+
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(image_base64.trim())
+        .map_err(|e| format!("Invalid base64 image data: {}", e))?;
+    let image = icons::process_uploaded_image(&bytes)?;
+
+    let db = state.database.lock().await;
+    db.save_command_image(&id, &image).map_err(|e| e.to_string())?;
+    audit_desktop(&db, AuditEvent::CommandImageSet, serde_json::json!({ "command_id": id }));
+    Ok(())
+}
+
+/// Remove the uploaded button image of a command
+#[tauri::command]
+async fn remove_command_image(id: String, state: State<'_, AppState>) -> Result<bool, String> {
+    let db = state.database.lock().await;
+    let removed = db.delete_command_image(&id).map_err(|e| e.to_string())?;
+    if removed {
+        audit_desktop(&db, AuditEvent::CommandImageRemoved, serde_json::json!({ "command_id": id }));
+    }
+    Ok(removed)
+}
+
+#[tauri::command]
+async fn get_profiles(state: State<'_, AppState>) -> Result<Vec<Profile>, String> {
+    let db = state.database.lock().await;
+    db.get_profiles().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn save_profile(profile: Profile, state: State<'_, AppState>) -> Result<(), String> {
+    profile.validate()?;
+
+    let db = state.database.lock().await;
+    if let Some(ref home) = profile.home_page_id {
+        match db.get_page(home) {
+            Some(page) if page.profile_id == profile.id => {}
+            _ => return Err(format!("Home page '{}' does not belong to this profile", home)),
+        }
+    }
+    db.save_profile(&profile).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_profile(id: String, state: State<'_, AppState>) -> Result<bool, String> {
+    let db = state.database.lock().await;
+    db.delete_profile(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_pages(profile_id: String, state: State<'_, AppState>) -> Result<Vec<Page>, String> {
+    let db = state.database.lock().await;
+    db.get_pages(&profile_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn save_page(page: Page, state: State<'_, AppState>) -> Result<(), String> {
+    page.validate()?;
+
+    let db = state.database.lock().await;
+    check_page_references(&db, &page)?;
+    db.save_page(&page).map_err(|e| e.to_string())
+}
+
+/// Delete a page along with its sub-pages
+#[tauri::command]
+async fn delete_page(id: String, state: State<'_, AppState>) -> Result<bool, String> {
+    let db = state.database.lock().await;
+    db.delete_page(&id).map_err(|e| e.to_string())
+}
+
+/// Check that everything a page points to exists and stays within its profile
+fn check_page_references(db: &Database, page: &Page) -> Result<(), String> {
+    if db.get_profile(&page.profile_id).is_none() {
+        return Err(format!("Profile '{}' does not exist", page.profile_id));
+    }
+
+    if let Some(ref parent_id) = page.parent_page_id {
+        // Walk up from the new parent to make sure we don't create a cycle
+        let mut current = Some(parent_id.clone());
+        while let Some(id) = current {
+            if id == page.id {
+                return Err(format!("Page '{}' cannot be nested inside itself", page.id));
+            }
+            let parent = db
+                .get_page(&id)
+                .ok_or_else(|| format!("Parent page '{}' does not exist", id))?;
+            if parent.profile_id != page.profile_id {
+                return Err(format!("Parent page '{}' belongs to another profile", id));
+            }
+            current = parent.parent_page_id;
+        }
+    }
+
+    for slot in &page.slots {
+        match slot.kind {
+            SlotKind::Command => {
+                let command_id = slot.command_id.as_deref().unwrap_or_default();
+                if db.get_command(command_id).is_none() {
+                    return Err(format!("Command '{}' does not exist", command_id));
+                }
+            }
+            SlotKind::Folder => {
+                let target_id = slot.target_page_id.as_deref().unwrap_or_default();
+                match db.get_page(target_id) {
+                    Some(target) if target.profile_id == page.profile_id => {}
+                    Some(_) => {
+                        return Err(format!("Page '{}' belongs to another profile", target_id));
+                    }
+                    None => return Err(format!("Page '{}' does not exist", target_id)),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Get all schedules, with their next run time filled in
+#[tauri::command]
+async fn get_schedules(state: State<'_, AppState>) -> Result<Vec<Schedule>, String> {
+    let db = state.database.lock().await;
+    let mut schedules = db.get_schedules().map_err(|e| e.to_string())?;
+    for schedule in &mut schedules {
+        if schedule.enabled {
+            schedule.next_run_at = scheduler::preview_runs(&schedule.cron_expression, 1)
+                .ok()
+                .and_then(|runs| runs.into_iter().next());
+        }
+    }
+    Ok(schedules)
+}
+
+#[tauri::command]
+async fn save_schedule(schedule: Schedule, state: State<'_, AppState>) -> Result<(), String> {
+    schedule.validate()?;
+
+    let db = state.database.lock().await;
+    if db.get_command(&schedule.command_id).is_none() {
+        return Err(format!("Command '{}' does not exist", schedule.command_id));
+    }
+    db.save_schedule(&schedule).map_err(|e| e.to_string())?;
+    audit_desktop(
+        &db,
+        AuditEvent::ScheduleSaved,
+        serde_json::json!({
+            "schedule_id": schedule.id,
+            "command_id": schedule.command_id,
+            "cron_expression": schedule.cron_expression,
+            "enabled": schedule.enabled,
+        }),
+    );
+    Ok(())
+}
+
+#[tauri::command]
+async fn delete_schedule(id: String, state: State<'_, AppState>) -> Result<bool, String> {
+    let db = state.database.lock().await;
+    let command_id = schedule_command_id(&db, &id);
+    let deleted = db.delete_schedule(&id).map_err(|e| e.to_string())?;
+    if deleted {
+        audit_desktop(
+            &db,
+            AuditEvent::ScheduleDeleted,
+            serde_json::json!({ "schedule_id": id, "command_id": command_id }),
+        );
+    }
+    Ok(deleted)
+}
+
+#[tauri::command]
+async fn set_schedule_enabled(
+    id: String,
+    enabled: bool,
+    state: State<'_, AppState>,
+) -> Result<bool, String> {
+    let db = state.database.lock().await;
+    let updated = db.set_schedule_enabled(&id, enabled).map_err(|e| e.to_string())?;
+    if updated {
+        audit_desktop(
+            &db,
+            AuditEvent::ScheduleEnabledChanged,
+            serde_json::json!({
+                "schedule_id": id,
+                "command_id": schedule_command_id(&db, &id),
+                "enabled": enabled,
+            }),
+        );
+    }
+    Ok(updated)
+}
+
+/// Command a schedule runs, for audit entries
+fn schedule_command_id(db: &Database, schedule_id: &str) -> Option<String> {
+    db.get_schedules()
+        .ok()?
+        .into_iter()
+        .find(|schedule| schedule.id == schedule_id)
+        .map(|schedule| schedule.command_id)
+}
+
+/// Preview the next run times of a cron expression (RFC 3339, local time)
+#[tauri::command]
+fn preview_schedule(cron_expression: String, count: Option<usize>) -> Result<Vec<String>, String> {
+    let runs = scheduler::preview_runs(&cron_expression, count.unwrap_or(5).min(50))?;
+    Ok(runs.iter().map(|run| run.to_rfc3339()).collect())
+}
+
+#[tauri::command]
+async fn get_execution_history(
+    filter: Option<HistoryFilter>,
+    state: State<'_, AppState>,
+) -> Result<HistoryPage, String> {
+    let db = state.database.lock().await;
+    db.query_executions(&filter.unwrap_or_default())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn clear_execution_history(state: State<'_, AppState>) -> Result<usize, String> {
+    let db = state.database.lock().await;
+    let cleared = db.clear_executions().map_err(|e| e.to_string())?;
+    audit_desktop(&db, AuditEvent::HistoryCleared, serde_json::json!({ "entries": cleared }));
+    Ok(cleared)
+}
+
+/// Export the audit log as JSON lines, oldest entry first
+#[tauri::command]
+async fn export_audit_log(state: State<'_, AppState>) -> Result<String, String> {
+    let db = state.database.lock().await;
+    let entries = db.get_audit_entries().map_err(|e| e.to_string())?;
+
+    let mut lines = String::new();
+    for entry in &entries {
+        lines.push_str(&serde_json::to_string(entry).map_err(|e| e.to_string())?);
+        lines.push('\n');
+    }
+    Ok(lines)
+}
+
+/// Check the audit log's hash chain for tampering
+#[tauri::command]
+async fn verify_audit_log(state: State<'_, AppState>) -> Result<AuditVerification, String> {
     let db = state.database.lock().await;
-    db.save_all_commands(&commands_vec).map_err(|e| e.to_string())
+    db.verify_audit_log()
+}
+
+/// List connected monitors, for picking the target of mouse commands
+#[tauri::command]
+fn get_monitors() -> Result<Vec<mouse::Monitor>, String> {
+    mouse::get_monitors()
 }
 
 #[tauri::command]
@@ -130,6 +447,13 @@ async fn get_server_status(state: State<'_, AppState>) -> Result<bool, String> {
     Ok(handle.is_some())
 }
 
+/// Number of phones looking at the desktop (stream or recent snapshots), shown as an indicator in the app
+#[tauri::command]
+async fn get_screen_stream_viewers(state: State<'_, AppState>) -> Result<usize, String> {
+    let handle = state.server_handle.lock().await;
+    Ok(handle.as_ref().map_or(0, |h| h.screen_stream_viewers()))
+}
+
 #[tauri::command]
 fn get_local_ips() -> Vec<String> {
     let mut ips = Vec::new();
@@ -199,6 +523,12 @@ fn get_running_applications() -> Vec<String> {
     system_commands::get_running_applications()
 }
 
+/// List open windows, for picking the target of window commands
+#[tauri::command]
+fn get_windows() -> Result<Vec<window_manager::WindowInfo>, String> {
+    window_manager::list_windows()
+}
+
 /// Check if accessibility permissions are granted (required for keybind commands)
 #[tauri::command]
 fn check_accessibility_permission() -> bool {
@@ -258,16 +588,23 @@ fn main() {
                     let db = futures::executor::block_on(database.lock());
                     let existing_commands = db.get_all_commands().unwrap_or_default();
                     if existing_commands.is_empty() {
-                        if let Err(e) = db.migrate_from_files(&PathBuf::new(), &bundled_commands) {
-                            eprintln!("Failed to migrate bundled commands: {}", e);
+                        match db.import_commands(&bundled_commands) {
+                            Ok(count) => println!("Imported {} bundled commands", count),
+                            Err(e) => eprintln!("Failed to import bundled commands: {}", e),
                         }
                     }
                 }
             }
 
+            let events = events::create_event_channel();
+
+            // Scheduled commands run whether or not the server is started
+            tauri::async_runtime::spawn(scheduler::run_scheduler(database.clone(), events.clone()));
+
             let app_state = AppState {
                 server_handle: Arc::new(Mutex::new(None)),
                 database,
+                events,
             };
 
             app.manage(app_state);
@@ -279,9 +616,29 @@ fn main() {
             save_settings,
             get_commands,
             save_commands,
+            set_command_image,
+            remove_command_image,
+            get_profiles,
+            save_profile,
+            delete_profile,
+            get_pages,
+            save_page,
+            delete_page,
+            get_schedules,
+            save_schedule,
+            delete_schedule,
+            set_schedule_enabled,
+            preview_schedule,
+            get_execution_history,
+            clear_execution_history,
+            export_audit_log,
+            verify_audit_log,
             get_server_status,
+            get_screen_stream_viewers,
             get_local_ips,
+            get_monitors,
             get_running_applications,
+            get_windows,
             regenerate_auth_code,
             check_accessibility_permission,
             request_accessibility_permission,
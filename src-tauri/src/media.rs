@@ -0,0 +1,453 @@
+//! Media player control
+//!
+//! On Linux players are driven through MPRIS on the D-Bus session bus, so any
+//! compliant player (Spotify, VLC, browsers, mpv with the mpris plugin) works.
+//! The bus is found through `DBUS_SESSION_BUS_ADDRESS`, which also lets this run
+//! headless against a private bus with a mock player. Other platforms are not
+//! supported yet.
+
+use crate::commands::MediaAction;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Track currently loaded in a player
+#[derive(Debug, Clone, Serialize)]
+pub struct NowPlaying {
+    /// Player name as used in `media_player`, e.g. "spotify"
+    pub player: String,
+    /// "Playing", "Paused" or "Stopped"
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// All artists, comma-separated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length_ms: Option<u64>,
+    /// Cover image: a web URL, or `/api/media/art` for covers stored on disk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub art_url: Option<String>,
+}
+
+/// Run a player action and describe what was done
+pub fn control(action: &MediaAction, player: Option<&str>, seek_offset_secs: Option<i64>) -> Result<String, String> {
+    #[cfg(target_os = "linux")]
+    {
+        mpris::Mpris::session()?.control(action, player, seek_offset_secs)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (action, player, seek_offset_secs);
+        Err("Media control is only supported on Linux".to_string())
+    }
+}
+
+/// What the selected player is playing, `None` when no player is running
+pub fn now_playing(player: Option<&str>) -> Result<Option<NowPlaying>, String> {
+    #[cfg(target_os = "linux")]
+    {
+        mpris::Mpris::session()?.now_playing(player)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = player;
+        Err("Media control is only supported on Linux".to_string())
+    }
+}
+
+/// Local cover image of the current track, if the player stores it on disk
+///
+/// Only the path reported by the player is ever returned, so this can't be
+/// used to read arbitrary files.
+pub fn art_file(player: Option<&str>) -> Result<Option<PathBuf>, String> {
+    #[cfg(target_os = "linux")]
+    {
+        mpris::Mpris::session()?.art_file(player)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = player;
+        Err("Media control is only supported on Linux".to_string())
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub mod mpris {
+    use super::NowPlaying;
+    use crate::commands::MediaAction;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use zbus::blocking::fdo::DBusProxy;
+    use zbus::blocking::proxy::Builder;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::proxy::CacheProperties;
+    use zbus::zvariant::{Array, OwnedValue};
+
+    const BUS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+    const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+    const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+    type Metadata = HashMap<String, OwnedValue>;
+
+    /// MPRIS client on a D-Bus connection
+    pub struct Mpris {
+        conn: Connection,
+    }
+
+    impl Mpris {
+        /// Connect to the session bus
+        pub fn session() -> Result<Self, String> {
+            Connection::session()
+                .map(Self::new)
+                .map_err(|e| format!("Failed to connect to the session bus: {}", e))
+        }
+
+        pub fn new(conn: Connection) -> Self {
+            Self { conn }
+        }
+
+        /// Names of running players, without the "org.mpris.MediaPlayer2." prefix
+        pub fn players(&self) -> Result<Vec<String>, String> {
+            let names = DBusProxy::new(&self.conn)
+                .map_err(|e| e.to_string())
+                .and_then(|dbus| dbus.list_names().map_err(|e| e.to_string()))
+                .map_err(|e| format!("Failed to list players: {}", e))?;
+
+            let mut players: Vec<String> = names
+                .iter()
+                .filter_map(|name| name.as_str().strip_prefix(BUS_PREFIX))
+                .map(str::to_string)
+                .collect();
+            players.sort();
+            Ok(players)
+        }
+
+        /// Pick a player: the named one, otherwise the one playing, otherwise any
+        ///
+        /// A name also matches instances, so "chromium" finds "chromium.instance42".
+        pub fn select(&self, name: Option<&str>) -> Result<Option<String>, String> {
+            let players = self.players()?;
+
+            if let Some(name) = name {
+                let wanted = name.to_lowercase();
+                return Ok(players.into_iter().find(|p| {
+                    let p = p.to_lowercase();
+                    p == wanted || p.starts_with(&format!("{}.", wanted))
+                }));
+            }
+
+            for status in ["Playing", "Paused"] {
+                if let Some(player) = players.iter().find(|p| {
+                    self.proxy(p)
+                        .and_then(|proxy| property::<String>(&proxy, "PlaybackStatus"))
+                        .map(|s| s == status)
+                        .unwrap_or(false)
+                }) {
+                    return Ok(Some(player.clone()));
+                }
+            }
+            Ok(players.into_iter().next())
+        }
+
+        pub fn control(&self, action: &MediaAction, player: Option<&str>, seek_offset_secs: Option<i64>) -> Result<String, String> {
+            let name = self.require(player)?;
+            let proxy = self.proxy(&name)?;
+
+            let method = match action {
+                MediaAction::PlayPause => "PlayPause",
+                MediaAction::Play => "Play",
+                MediaAction::Pause => "Pause",
+                MediaAction::Stop => "Stop",
+                MediaAction::Next => "Next",
+                MediaAction::Previous => "Previous",
+                MediaAction::Seek => {
+                    let secs = seek_offset_secs.unwrap_or_default();
+                    // MPRIS positions are in microseconds
+                    proxy
+                        .call_method("Seek", &(secs.saturating_mul(1_000_000),))
+                        .map_err(|e| format!("Failed to seek in {}: {}", name, e))?;
+                    return Ok(format!("Seeked {}s in {}", secs, name));
+                }
+            };
+
+            proxy
+                .call_method(method, &())
+                .map_err(|e| format!("Failed to send {} to {}: {}", method, name, e))?;
+            Ok(format!("Sent {} to {}", method, name))
+        }
+
+        pub fn now_playing(&self, player: Option<&str>) -> Result<Option<NowPlaying>, String> {
+            let name = match self.select(player)? {
+                Some(name) => name,
+                None => return Ok(None),
+            };
+            let proxy = self.proxy(&name)?;
+
+            let status = property::<String>(&proxy, "PlaybackStatus")?;
+            let metadata = property::<Metadata>(&proxy, "Metadata").unwrap_or_default();
+            // Not every player reports a position
+            let position_ms = property::<i64>(&proxy, "Position")
+                .ok()
+                .map(|us| us.max(0) as u64 / 1000);
+
+            let artist = metadata.get("xesam:artist").and_then(|v| {
+                let artists: Vec<String> = v
+                    .downcast_ref::<&Array>()
+                    .ok()?
+                    .iter()
+                    .filter_map(|a| a.downcast_ref::<&str>().ok().map(str::to_string))
+                    .collect();
+                (!artists.is_empty()).then(|| artists.join(", "))
+            });
+            let length_ms = metadata.get("mpris:length").and_then(|v| {
+                // Spec says int64, but some players send uint64
+                v.downcast_ref::<i64>()
+                    .map(|us| us.max(0) as u64)
+                    .or_else(|_| v.downcast_ref::<u64>())
+                    .ok()
+                    .map(|us| us / 1000)
+            });
+            let art_url = text(&metadata, "mpris:artUrl").map(|url| {
+                if url.starts_with("file://") {
+                    format!("/api/media/art?player={}", name)
+                } else {
+                    url
+                }
+            });
+
+            Ok(Some(NowPlaying {
+                title: text(&metadata, "xesam:title"),
+                album: text(&metadata, "xesam:album"),
+                artist,
+                status,
+                position_ms,
+                length_ms,
+                art_url,
+                player: name,
+            }))
+        }
+
+        pub fn art_file(&self, player: Option<&str>) -> Result<Option<PathBuf>, String> {
+            let name = match self.select(player)? {
+                Some(name) => name,
+                None => return Ok(None),
+            };
+            let metadata = property::<Metadata>(&self.proxy(&name)?, "Metadata")?;
+
+            Ok(text(&metadata, "mpris:artUrl")
+                .and_then(|url| url.strip_prefix("file://").map(percent_decode))
+                .map(PathBuf::from))
+        }
+
+        /// The selected player, or an error when none is running
+        fn require(&self, player: Option<&str>) -> Result<String, String> {
+            self.select(player)?.ok_or_else(|| match player {
+                Some(name) => format!("Media player '{}' is not running", name),
+                None => "No media player is running".to_string(),
+            })
+        }
+
+        fn proxy(&self, player: &str) -> Result<Proxy<'static>, String> {
+            Builder::<Proxy>::new(&self.conn)
+                .destination(format!("{}{}", BUS_PREFIX, player))
+                .and_then(|b| b.path(OBJECT_PATH))
+                .and_then(|b| b.interface(PLAYER_INTERFACE))
+                // Values change all the time, always read them fresh
+                .map(|b| b.cache_properties(CacheProperties::No))
+                .and_then(|b| b.build())
+                .map_err(|e| format!("Failed to reach player {}: {}", player, e))
+        }
+    }
+
+    fn property<T>(proxy: &Proxy, name: &str) -> Result<T, String>
+    where
+        T: TryFrom<OwnedValue>,
+        T::Error: Into<zbus::Error>,
+    {
+        proxy
+            .get_property(name)
+            .map_err(|e| format!("Failed to read {}: {}", name, e))
+    }
+
+    /// A non-empty string entry of the metadata
+    fn text(metadata: &Metadata, key: &str) -> Option<String> {
+        metadata
+            .get(key)?
+            .downcast_ref::<&str>()
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    }
+
+    /// Decode %XX escapes of a file URL path
+    pub fn percent_decode(path: &str) -> String {
+        let bytes = path.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| u8::from_str_radix(h, 16).ok());
+            match (bytes[i], hex) {
+                (b'%', Some(byte)) => {
+                    decoded.push(byte);
+                    i += 3;
+                }
+                (byte, _) => {
+                    decoded.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&decoded).into_owned()
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::mpris::Mpris;
+    use crate::commands::MediaAction;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::path::PathBuf;
+    use std::process::{Child, Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use zbus::blocking::connection::Builder;
+    use zbus::blocking::Connection;
+    use zbus::zvariant::{OwnedValue, Value};
+
+    /// Private session bus, stopped when dropped
+    struct Bus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl Bus {
+        /// Start a bus, `None` when dbus-daemon isn't installed
+        fn start() -> Option<Self> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+            let address = address.trim().to_string();
+            if address.is_empty() {
+                let _ = daemon.kill();
+                return None;
+            }
+            Some(Self { daemon, address })
+        }
+
+        fn connect(&self) -> Connection {
+            Builder::address(self.address.as_str()).unwrap().build().unwrap()
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    /// Player that records the methods called on it
+    struct MockPlayer {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MockPlayer {
+        fn record(&self, call: String) {
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    #[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl MockPlayer {
+        fn play_pause(&self) {
+            self.record("PlayPause".to_string());
+        }
+
+        fn next(&self) {
+            self.record("Next".to_string());
+        }
+
+        fn seek(&self, offset: i64) {
+            self.record(format!("Seek {}", offset));
+        }
+
+        #[zbus(property)]
+        fn playback_status(&self) -> String {
+            "Playing".to_string()
+        }
+
+        #[zbus(property)]
+        fn position(&self) -> i64 {
+            1_500_000
+        }
+
+        #[zbus(property)]
+        fn metadata(&self) -> HashMap<String, OwnedValue> {
+            let value = |v: Value| OwnedValue::try_from(v).unwrap();
+            HashMap::from([
+                ("xesam:title".to_string(), value(Value::from("Song"))),
+                ("xesam:album".to_string(), value(Value::from("Album"))),
+                ("xesam:artist".to_string(), value(Value::from(vec!["Alice", "Bob"]))),
+                ("mpris:length".to_string(), value(Value::from(240_000_000i64))),
+                ("mpris:artUrl".to_string(), value(Value::from("file:///tmp/cover%20art.png"))),
+            ])
+        }
+    }
+
+    #[test]
+    fn mock_player_on_private_bus() {
+        let Some(bus) = Bus::start() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let _player = Builder::address(bus.address.as_str())
+            .unwrap()
+            .name("org.mpris.MediaPlayer2.mock.instance7")
+            .unwrap()
+            .serve_at("/org/mpris/MediaPlayer2", MockPlayer { calls: calls.clone() })
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let mpris = Mpris::new(bus.connect());
+        assert_eq!(mpris.players().unwrap(), vec!["mock.instance7"]);
+        assert_eq!(mpris.select(Some("Mock")).unwrap().as_deref(), Some("mock.instance7"));
+        assert_eq!(mpris.select(None).unwrap().as_deref(), Some("mock.instance7"));
+        assert_eq!(mpris.select(Some("vlc")).unwrap(), None);
+
+        let playing = mpris.now_playing(None).unwrap().expect("player is running");
+        assert_eq!(playing.player, "mock.instance7");
+        assert_eq!(playing.status, "Playing");
+        assert_eq!(playing.title.as_deref(), Some("Song"));
+        assert_eq!(playing.artist.as_deref(), Some("Alice, Bob"));
+        assert_eq!(playing.album.as_deref(), Some("Album"));
+        assert_eq!(playing.position_ms, Some(1500));
+        assert_eq!(playing.length_ms, Some(240_000));
+        assert_eq!(playing.art_url.as_deref(), Some("/api/media/art?player=mock.instance7"));
+        assert_eq!(mpris.art_file(None).unwrap(), Some(PathBuf::from("/tmp/cover art.png")));
+
+        mpris.control(&MediaAction::PlayPause, None, None).unwrap();
+        mpris.control(&MediaAction::Next, Some("mock"), None).unwrap();
+        mpris.control(&MediaAction::Seek, None, Some(-10)).unwrap();
+        assert_eq!(*calls.lock().unwrap(), vec!["PlayPause", "Next", "Seek -10000000"]);
+
+        let err = mpris.control(&MediaAction::Play, Some("vlc"), None).unwrap_err();
+        assert!(err.contains("'vlc' is not running"), "{}", err);
+    }
+}
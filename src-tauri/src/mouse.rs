@@ -0,0 +1,582 @@
+//! Pointer control and screen geometry
+//!
+//! Primitives used by mouse commands: listing monitors, reading and moving the
+//! pointer, clicking and scrolling. Linux uses xdotool on X11 and ydotool on
+//! Wayland, macOS posts CoreGraphics events through JavaScript for Automation,
+//! and Windows calls user32 directly.
+
+use crate::commands::{ScreenCorner, ScrollDirection};
+use serde::{Deserialize, Serialize};
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+use std::process::Command;
+
+/// A monitor and its position in desktop coordinates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Monitor {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub primary: bool,
+}
+
+impl Monitor {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && (x as i64) < self.x as i64 + self.width as i64
+            && (y as i64) < self.y as i64 + self.height as i64
+    }
+
+    /// Desktop coordinates of a corner, kept one pixel inside the monitor
+    pub fn corner(&self, corner: &ScreenCorner) -> (i32, i32) {
+        let right = self.x + self.width.saturating_sub(1) as i32;
+        let bottom = self.y + self.height.saturating_sub(1) as i32;
+        match corner {
+            ScreenCorner::TopLeft => (self.x, self.y),
+            ScreenCorner::TopRight => (right, self.y),
+            ScreenCorner::BottomLeft => (self.x, bottom),
+            ScreenCorner::BottomRight => (right, bottom),
+            ScreenCorner::Center => (self.x + self.width as i32 / 2, self.y + self.height as i32 / 2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    #[default]
+    Left,
+    Right,
+    Middle,
+}
+
+/// Find a monitor by name (case-insensitive), or the primary one when no name is given
+pub fn find_monitor<'a>(monitors: &'a [Monitor], name: Option<&str>) -> Result<&'a Monitor, String> {
+    match name {
+        Some(name) => monitors
+            .iter()
+            .find(|m| m.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                let names: Vec<&str> = monitors.iter().map(|m| m.name.as_str()).collect();
+                format!("Monitor '{}' not found (available: {})", name, names.join(", "))
+            }),
+        None => monitors
+            .iter()
+            .find(|m| m.primary)
+            .or_else(|| monitors.first())
+            .ok_or_else(|| "No monitors found".to_string()),
+    }
+}
+
+/// List the connected monitors
+pub fn get_monitors() -> Result<Vec<Monitor>, String> {
+    #[cfg(target_os = "macos")]
+    {
+        let script = r#"ObjC.import('AppKit');
+var screens = $.NSScreen.screens.js;
+var mainHeight = screens[0].frame.size.height;
+JSON.stringify(screens.map(function (s, i) {
+  var f = s.frame;
+  return {
+    name: s.localizedName.js,
+    x: Math.round(f.origin.x),
+    y: Math.round(mainHeight - f.origin.y - f.size.height),
+    width: Math.round(f.size.width),
+    height: Math.round(f.size.height),
+    primary: i == 0
+  };
+}))"#;
+        let output = run_jxa(script)?;
+        serde_json::from_str(&output).map_err(|e| format!("Failed to read monitors: {}", e))
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        windows_mouse::get_monitors()
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let monitors = if crate::system_commands::is_wayland_session() {
+            wayland_monitors().or_else(|_| xrandr_monitors())?
+        } else {
+            xrandr_monitors()?
+        };
+        if monitors.is_empty() {
+            return Err("No monitors found".to_string());
+        }
+        Ok(monitors)
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        Err("Screen geometry not supported on this platform".to_string())
+    }
+}
+
+/// Current pointer position in desktop coordinates
+pub fn pointer_position() -> Result<(i32, i32), String> {
+    #[cfg(target_os = "macos")]
+    {
+        let output = run_jxa(
+            r#"ObjC.import('CoreGraphics');
+var p = $.CGEventGetLocation($.CGEventCreate(null));
+Math.round(p.x) + ' ' + Math.round(p.y)"#,
+        )?;
+        let mut parts = output.split_whitespace().map(|v| v.parse::<i32>());
+        match (parts.next(), parts.next()) {
+            (Some(Ok(x)), Some(Ok(y))) => Ok((x, y)),
+            _ => Err(format!("Unexpected pointer position '{}'", output)),
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        windows_mouse::pointer_position()
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if crate::system_commands::is_wayland_session() {
+            return Err("Reading the pointer position is not supported on Wayland".to_string());
+        }
+
+        let output = run_tool("xdotool", &["getmouselocation", "--shell"])?;
+        let value = |key: &str| {
+            output
+                .lines()
+                .find_map(|line| line.strip_prefix(key))
+                .and_then(|v| v.trim().parse::<i32>().ok())
+        };
+        match (value("X="), value("Y=")) {
+            (Some(x), Some(y)) => Ok((x, y)),
+            _ => Err(format!("Unexpected pointer position '{}'", output.trim())),
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        Err("Mouse control not supported on this platform".to_string())
+    }
+}
+
+/// Move the pointer to a position in desktop coordinates
+pub fn move_to(x: i32, y: i32) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    {
+        run_jxa(&format!(
+            r#"ObjC.import('CoreGraphics');
+// 5 = kCGEventMouseMoved, 0 = kCGHIDEventTap
+$.CGEventPost(0, $.CGEventCreateMouseEvent(null, 5, {{ x: {}, y: {} }}, 0));"#,
+            x, y
+        ))
+        .map(|_| ())
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        windows_mouse::move_to(x, y)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let (x, y) = (x.to_string(), y.to_string());
+        if crate::system_commands::is_wayland_session() {
+            run_tool("ydotool", &["mousemove", "--absolute", "-x", &x, "-y", &y]).map(|_| ())
+        } else {
+            run_tool("xdotool", &["mousemove", "--", &x, &y]).map(|_| ())
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        let _ = (x, y);
+        Err("Mouse control not supported on this platform".to_string())
+    }
+}
+
+/// Move the pointer by an offset from its current position
+pub fn move_by(dx: i32, dy: i32) -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    {
+        // ydotool can't read the position, but it can move relatively
+        if crate::system_commands::is_wayland_session() {
+            run_tool("ydotool", &["mousemove", "-x", &dx.to_string(), "-y", &dy.to_string()]).map(|_| ())
+        } else {
+            // Called for every remote trackpad frame, so XTest comes first
+            crate::x11::with_fallback(
+                || crate::x11::move_pointer_by(dx, dy),
+                || run_tool("xdotool", &["mousemove_relative", "--", &dx.to_string(), &dy.to_string()]).map(|_| ()),
+            )
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let (x, y) = pointer_position()?;
+        move_to(x.saturating_add(dx), y.saturating_add(dy))
+    }
+}
+
+/// Click a button `count` times at the pointer position
+pub fn click(button: MouseButton, count: u32) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    {
+        // CGEventType down/up pairs and CGMouseButton values
+        let (down, up, number) = match button {
+            MouseButton::Left => (1, 2, 0),
+            MouseButton::Right => (3, 4, 1),
+            MouseButton::Middle => (25, 26, 2),
+        };
+        run_jxa(&format!(
+            r#"ObjC.import('CoreGraphics');
+var p = $.CGEventGetLocation($.CGEventCreate(null));
+for (var i = 1; i <= {count}; i++) {{
+  [{down}, {up}].forEach(function (type) {{
+    var e = $.CGEventCreateMouseEvent(null, type, p, {number});
+    // 1 = kCGMouseEventClickState, lets apps see a double click
+    $.CGEventSetIntegerValueField(e, 1, i);
+    $.CGEventPost(0, e);
+  }});
+}}"#,
+            count = count,
+            down = down,
+            up = up,
+            number = number
+        ))
+        .map(|_| ())
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        windows_mouse::click(button, count)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let repeat = count.to_string();
+        if crate::system_commands::is_wayland_session() {
+            // ydotool button codes with the "press and release" bits set
+            let code = match button {
+                MouseButton::Left => "0xC0",
+                MouseButton::Right => "0xC1",
+                MouseButton::Middle => "0xC2",
+            };
+            run_tool("ydotool", &["click", "--repeat", &repeat, "--next-delay", "50", code]).map(|_| ())
+        } else {
+            let number = match button {
+                MouseButton::Left => "1",
+                MouseButton::Middle => "2",
+                MouseButton::Right => "3",
+            };
+            run_tool("xdotool", &["click", "--repeat", &repeat, number]).map(|_| ())
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        let _ = (button, count);
+        Err("Mouse control not supported on this platform".to_string())
+    }
+}
+
+/// Scroll `amount` steps at the pointer position
+pub fn scroll(direction: &ScrollDirection, amount: u32) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    {
+        let amount = amount as i32;
+        let (vertical, horizontal) = match direction {
+            ScrollDirection::Up => (amount, 0),
+            ScrollDirection::Down => (-amount, 0),
+            ScrollDirection::Left => (0, amount),
+            ScrollDirection::Right => (0, -amount),
+        };
+        run_jxa(&format!(
+            r#"ObjC.import('CoreGraphics');
+// 1 = kCGScrollEventUnitLine, 2 wheels: vertical then horizontal
+$.CGEventPost(0, $.CGEventCreateScrollWheelEvent(null, 1, 2, {}, {}));"#,
+            vertical, horizontal
+        ))
+        .map(|_| ())
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        windows_mouse::scroll(direction, amount)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if crate::system_commands::is_wayland_session() {
+            // Positive wheel values scroll up/right
+            let amount = amount as i32;
+            let (x, y) = match direction {
+                ScrollDirection::Up => (0, amount),
+                ScrollDirection::Down => (0, -amount),
+                ScrollDirection::Left => (-amount, 0),
+                ScrollDirection::Right => (amount, 0),
+            };
+            let (x, y) = (x.to_string(), y.to_string());
+            run_tool("ydotool", &["mousemove", "--wheel", "-x", &x, "-y", &y]).map(|_| ())
+        } else {
+            // X11 maps the wheel to buttons 4-7
+            let button = match direction {
+                ScrollDirection::Up => "4",
+                ScrollDirection::Down => "5",
+                ScrollDirection::Left => "6",
+                ScrollDirection::Right => "7",
+            };
+            run_tool("xdotool", &["click", "--repeat", &amount.to_string(), button]).map(|_| ())
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        let _ = (direction, amount);
+        Err("Mouse control not supported on this platform".to_string())
+    }
+}
+
+/// Run a JavaScript for Automation script and return its result
+#[cfg(target_os = "macos")]
+pub fn run_jxa(script: &str) -> Result<String, String> {
+    let output = Command::new("osascript")
+        .args(["-l", "JavaScript", "-e", script])
+        .output()
+        .map_err(|e| format!("Failed to run osascript: {}", e))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// Run an input tool and return its stdout
+#[cfg(target_os = "linux")]
+pub fn run_tool(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Monitors as reported by `xrandr`, which also works under XWayland
+#[cfg(target_os = "linux")]
+fn xrandr_monitors() -> Result<Vec<Monitor>, String> {
+    let output = run_tool("xrandr", &["--query"])?;
+    Ok(parse_xrandr(&output))
+}
+
+/// Parse the output lines like "HDMI-1 connected primary 1920x1080+0+0 (normal ...) ..."
+#[cfg(target_os = "linux")]
+fn parse_xrandr(output: &str) -> Vec<Monitor> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with(char::is_whitespace))
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let name = words.next()?;
+            if words.next()? != "connected" {
+                return None;
+            }
+            let rest: Vec<&str> = words.collect();
+            // Connected outputs that are switched off have no geometry
+            let (width, height, x, y) = rest.iter().find_map(|w| parse_geometry(w))?;
+            Some(Monitor {
+                name: name.to_string(),
+                x,
+                y,
+                width,
+                height,
+                primary: rest.contains(&"primary"),
+            })
+        })
+        .collect()
+}
+
+/// Parse an X geometry like "1920x1080+0+0" (offsets may be "+-1920")
+#[cfg(target_os = "linux")]
+fn parse_geometry(geometry: &str) -> Option<(u32, u32, i32, i32)> {
+    let mut parts = geometry.split('+');
+    let (width, height) = parts.next()?.split_once('x')?;
+    let x = parts.next()?.parse().ok()?;
+    let y = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((width.parse().ok()?, height.parse().ok()?, x, y))
+}
+
+/// Monitors of a Sway or Hyprland session, which name outputs like the kernel does
+#[cfg(target_os = "linux")]
+fn wayland_monitors() -> Result<Vec<Monitor>, String> {
+    use serde_json::Value;
+
+    let number = |v: &Value, key: &str| v.get(key).and_then(Value::as_i64).unwrap_or(0);
+
+    if let Ok(output) = run_tool("swaymsg", &["-t", "get_outputs", "--raw"]) {
+        let outputs: Vec<Value> = serde_json::from_str(&output).map_err(|e| e.to_string())?;
+        let mut monitors: Vec<Monitor> = outputs
+            .iter()
+            .filter(|o| o.get("active").and_then(Value::as_bool).unwrap_or(true))
+            .filter_map(|o| {
+                let rect = o.get("rect")?;
+                Some(Monitor {
+                    name: o.get("name")?.as_str()?.to_string(),
+                    x: number(rect, "x") as i32,
+                    y: number(rect, "y") as i32,
+                    width: number(rect, "width") as u32,
+                    height: number(rect, "height") as u32,
+                    primary: false,
+                })
+            })
+            .collect();
+        // Wayland has no primary output; treat the first one as primary
+        if let Some(first) = monitors.first_mut() {
+            first.primary = true;
+        }
+        return Ok(monitors);
+    }
+
+    let output = run_tool("hyprctl", &["monitors", "-j"])?;
+    let outputs: Vec<Value> = serde_json::from_str(&output).map_err(|e| e.to_string())?;
+    let mut monitors: Vec<Monitor> = outputs
+        .iter()
+        .filter_map(|o| {
+            // Hyprland reports the mode size; the layout size accounts for scaling
+            let scale = o.get("scale").and_then(Value::as_f64).unwrap_or(1.0).max(0.1);
+            Some(Monitor {
+                name: o.get("name")?.as_str()?.to_string(),
+                x: number(o, "x") as i32,
+                y: number(o, "y") as i32,
+                width: (number(o, "width") as f64 / scale).round() as u32,
+                height: (number(o, "height") as f64 / scale).round() as u32,
+                primary: false,
+            })
+        })
+        .collect();
+    if let Some(first) = monitors.first_mut() {
+        first.primary = true;
+    }
+    Ok(monitors)
+}
+
+#[cfg(target_os = "windows")]
+mod windows_mouse {
+    use super::{Monitor, MouseButton};
+    use crate::commands::ScrollDirection;
+    use windows::Win32::Foundation::{LPARAM, POINT, RECT};
+    use windows::Win32::Graphics::Gdi::{EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFOEXW};
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        SendInput, INPUT, INPUT_0, INPUT_MOUSE, MOUSEEVENTF_HWHEEL, MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP,
+        MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP,
+        MOUSEEVENTF_WHEEL, MOUSEINPUT, MOUSE_EVENT_FLAGS,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{GetCursorPos, SetCursorPos, MONITORINFOF_PRIMARY, WHEEL_DELTA};
+    use windows::core::BOOL;
+
+    pub fn get_monitors() -> Result<Vec<Monitor>, String> {
+        unsafe extern "system" fn collect(monitor: HMONITOR, _: HDC, _: *mut RECT, data: LPARAM) -> BOOL {
+            let monitors = &mut *(data.0 as *mut Vec<Monitor>);
+            let mut info = MONITORINFOEXW::default();
+            info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+            if GetMonitorInfoW(monitor, &mut info.monitorInfo).as_bool() {
+                let rect = info.monitorInfo.rcMonitor;
+                let len = info.szDevice.iter().position(|&c| c == 0).unwrap_or(info.szDevice.len());
+                let device = String::from_utf16_lossy(&info.szDevice[..len]);
+                monitors.push(Monitor {
+                    // "\\.\DISPLAY1" -> "DISPLAY1"
+                    name: device.trim_start_matches(['\\', '.']).to_string(),
+                    x: rect.left,
+                    y: rect.top,
+                    width: (rect.right - rect.left) as u32,
+                    height: (rect.bottom - rect.top) as u32,
+                    primary: info.monitorInfo.dwFlags & MONITORINFOF_PRIMARY != 0,
+                });
+            }
+            BOOL::from(true)
+        }
+
+        let mut monitors: Vec<Monitor> = Vec::new();
+        unsafe {
+            EnumDisplayMonitors(None, None, Some(collect), LPARAM(&mut monitors as *mut _ as isize))
+                .ok()
+                .map_err(|e| format!("Failed to list monitors: {:?}", e))?;
+        }
+        Ok(monitors)
+    }
+
+    pub fn pointer_position() -> Result<(i32, i32), String> {
+        let mut point = POINT::default();
+        unsafe {
+            GetCursorPos(&mut point).map_err(|e| format!("Failed to read pointer position: {:?}", e))?;
+        }
+        Ok((point.x, point.y))
+    }
+
+    pub fn move_to(x: i32, y: i32) -> Result<(), String> {
+        unsafe { SetCursorPos(x, y).map_err(|e| format!("Failed to move pointer: {:?}", e)) }
+    }
+
+    pub fn click(button: MouseButton, count: u32) -> Result<(), String> {
+        let (down, up) = match button {
+            MouseButton::Left => (MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP),
+            MouseButton::Right => (MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP),
+            MouseButton::Middle => (MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP),
+        };
+        let inputs: Vec<INPUT> = (0..count)
+            .flat_map(|_| [mouse_input(down, 0), mouse_input(up, 0)])
+            .collect();
+        send(&inputs)
+    }
+
+    pub fn scroll(direction: &ScrollDirection, amount: u32) -> Result<(), String> {
+        // Positive wheel data scrolls up (vertical) or right (horizontal)
+        let delta = (amount * WHEEL_DELTA) as i32;
+        let (flags, data) = match direction {
+            ScrollDirection::Up => (MOUSEEVENTF_WHEEL, delta),
+            ScrollDirection::Down => (MOUSEEVENTF_WHEEL, -delta),
+            ScrollDirection::Left => (MOUSEEVENTF_HWHEEL, -delta),
+            ScrollDirection::Right => (MOUSEEVENTF_HWHEEL, delta),
+        };
+        send(&[mouse_input(flags, data as u32)])
+    }
+
+    fn mouse_input(flags: MOUSE_EVENT_FLAGS, data: u32) -> INPUT {
+        INPUT {
+            r#type: INPUT_MOUSE,
+            Anonymous: INPUT_0 {
+                mi: MOUSEINPUT {
+                    dx: 0,
+                    dy: 0,
+                    mouseData: data,
+                    dwFlags: flags,
+                    time: 0,
+                    dwExtraInfo: 0,
+                },
+            },
+        }
+    }
+
+    fn send(inputs: &[INPUT]) -> Result<(), String> {
+        let sent = unsafe { SendInput(inputs, std::mem::size_of::<INPUT>() as i32) };
+        if sent as usize == inputs.len() {
+            Ok(())
+        } else {
+            Err("Failed to send mouse input (blocked by another application?)".to_string())
+        }
+    }
+}
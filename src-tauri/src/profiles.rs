@@ -0,0 +1,144 @@
+use crate::system_commands::FocusedApp;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// A named set of pages, e.g. "Streaming" or "Design"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+
+    /// Sort order among profiles
+    #[serde(default)]
+    pub position: u32,
+
+    /// Page shown first when the profile is opened
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub home_page_id: Option<String>,
+
+    /// Switch to this profile when the focused app name or window title contains
+    /// one of these (case-insensitive), e.g. "figma" or "terminal"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub app_matchers: Vec<String>,
+}
+
+/// A grid of slots belonging to a profile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page {
+    pub id: String,
+    pub profile_id: String,
+    pub name: String,
+
+    /// Page containing the folder button that leads here (None for top-level pages)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_page_id: Option<String>,
+
+    #[serde(default)]
+    pub slots: Vec<PageSlot>,
+}
+
+/// What a slot on a page does when tapped
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SlotKind {
+    /// Execute a command
+    Command,
+    /// Navigate to another page
+    Folder,
+}
+
+/// A single button position on a page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageSlot {
+    /// Index in the grid, starting at 0
+    pub position: u32,
+    pub kind: SlotKind,
+
+    /// Command to execute (for Command kind)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_id: Option<String>,
+
+    /// Page to open (for Folder kind)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_page_id: Option<String>,
+
+    /// Folder button label, defaults to the target page name (for Folder kind)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    /// Folder button icon (for Folder kind)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+}
+
+impl Profile {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.is_empty() {
+            return Err("Profile ID cannot be empty".to_string());
+        }
+        if self.name.is_empty() {
+            return Err("Profile name cannot be empty".to_string());
+        }
+        if self.app_matchers.iter().any(|m| m.trim().is_empty()) {
+            return Err(format!("Profile '{}' has an empty app matcher", self.id));
+        }
+        Ok(())
+    }
+
+    /// Check whether the focused application is bound to this profile
+    pub fn matches(&self, app: &FocusedApp) -> bool {
+        let app_name = app.app_name.to_lowercase();
+        let title = app.title.to_lowercase();
+        self.app_matchers.iter().any(|matcher| {
+            let matcher = matcher.trim().to_lowercase();
+            app_name.contains(&matcher) || title.contains(&matcher)
+        })
+    }
+}
+
+impl Page {
+    /// Check the page on its own; references to other pages and commands are checked by the caller
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.is_empty() {
+            return Err("Page ID cannot be empty".to_string());
+        }
+        if self.profile_id.is_empty() {
+            return Err(format!("Page '{}' must belong to a profile", self.id));
+        }
+        if self.parent_page_id.as_deref() == Some(self.id.as_str()) {
+            return Err(format!("Page '{}' cannot be its own parent", self.id));
+        }
+
+        let mut positions = HashSet::new();
+        for slot in &self.slots {
+            if !positions.insert(slot.position) {
+                return Err(format!(
+                    "Page '{}' has more than one slot at position {}",
+                    self.id, slot.position
+                ));
+            }
+            match slot.kind {
+                SlotKind::Command if slot.command_id.as_deref().unwrap_or("").is_empty() => {
+                    return Err(format!(
+                        "Slot {} of page '{}' needs a command",
+                        slot.position, self.id
+                    ));
+                }
+                SlotKind::Folder if slot.target_page_id.as_deref().unwrap_or("").is_empty() => {
+                    return Err(format!(
+                        "Slot {} of page '{}' needs a target page",
+                        slot.position, self.id
+                    ));
+                }
+                SlotKind::Folder if slot.target_page_id.as_deref() == Some(self.id.as_str()) => {
+                    return Err(format!(
+                        "Slot {} of page '{}' cannot open its own page",
+                        slot.position, self.id
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
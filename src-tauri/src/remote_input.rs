@@ -0,0 +1,292 @@
+//! Remote trackpad and keyboard over a WebSocket
+//!
+//! The phone sends small JSON messages: pointer deltas, clicks, scroll, text and
+//! key combinations. Pointer and scroll deltas are summed and injected at most
+//! once per frame, so a burst of touch events turns into a single move. Other
+//! events keep their order and are rate-limited. The endpoint is only served
+//! when remote input is allowed in the settings, independently of commands,
+//! and open connections are closed once the permission is revoked.
+
+use crate::commands::{CommandConfig, CommandType, ScrollDirection, MAX_SCROLL_AMOUNT};
+use crate::database::SharedDatabase;
+use crate::mouse::{self, MouseButton};
+use crate::system_commands;
+use axum::extract::ws::{Message, WebSocket};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
+
+/// How often queued input is injected (about 60 times per second)
+const FRAME: Duration = Duration::from_millis(16);
+
+/// Clicks, text and keys accepted per second, also the burst size
+const MAX_EVENTS_PER_SEC: f64 = 20.0;
+
+/// Events waiting for injection before new ones are rejected
+const MAX_QUEUED: usize = 32;
+
+/// Longest text accepted in one message, in characters
+const MAX_TEXT_LEN: usize = 1000;
+
+/// How often an open connection checks that remote input is still allowed
+const PERMISSION_CHECK: Duration = Duration::from_secs(1);
+
+/// A message from the phone
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputEvent {
+    /// Move the pointer by a delta in pixels
+    Move { dx: f64, dy: f64 },
+    /// Scroll by a delta in wheel steps; positive `dy` scrolls down, positive `dx` right
+    Scroll {
+        #[serde(default)]
+        dx: f64,
+        #[serde(default)]
+        dy: f64,
+    },
+    Click {
+        #[serde(default)]
+        button: MouseButton,
+        #[serde(default = "default_click_count")]
+        count: u32,
+    },
+    /// Type text into the focused window
+    Text { text: String },
+    /// Send a key combination, e.g. "enter" or "ctrl+c"
+    Key { key: String },
+}
+
+fn default_click_count() -> u32 {
+    1
+}
+
+/// A message to the phone
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputReply {
+    Error { message: String },
+}
+
+/// Input ready to be sent to the desktop, with whole pixels and steps
+#[derive(Debug)]
+enum Injection {
+    Move(i32, i32),
+    Scroll(ScrollDirection, u32),
+    Click(MouseButton, u32),
+    Text(String),
+    Key(String),
+}
+
+/// Queued events of one connection
+struct InputQueue {
+    events: VecDeque<InputEvent>,
+    /// Sub-pixel / sub-step leftovers carried into the next frame
+    move_rest: (f64, f64),
+    scroll_rest: (f64, f64),
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl InputQueue {
+    fn new() -> Self {
+        Self {
+            events: VecDeque::new(),
+            move_rest: (0.0, 0.0),
+            scroll_rest: (0.0, 0.0),
+            tokens: MAX_EVENTS_PER_SEC,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Queue an event, merging it into the previous one when both are moves or scrolls
+    fn push(&mut self, event: InputEvent) -> Result<(), String> {
+        match event {
+            InputEvent::Move { dx, dy } | InputEvent::Scroll { dx, dy }
+                if !dx.is_finite() || !dy.is_finite() =>
+            {
+                return Err("Deltas must be finite numbers".to_string());
+            }
+            InputEvent::Move { .. } | InputEvent::Scroll { .. } => {
+                if self.merge(&event) {
+                    return Ok(());
+                }
+            }
+            InputEvent::Click { count, .. } if !(1..=3).contains(&count) => {
+                return Err("Click count must be between 1 and 3".to_string());
+            }
+            InputEvent::Text { ref text } if text.chars().count() > MAX_TEXT_LEN => {
+                return Err(format!("Text is longer than {} characters", MAX_TEXT_LEN));
+            }
+            _ => self.take_token()?,
+        }
+
+        if self.events.len() >= MAX_QUEUED {
+            return Err("Too many pending events".to_string());
+        }
+        self.events.push_back(event);
+        Ok(())
+    }
+
+    /// Add a delta to the last queued event if it is of the same kind
+    fn merge(&mut self, event: &InputEvent) -> bool {
+        match (self.events.back_mut(), event) {
+            (Some(InputEvent::Move { dx, dy }), InputEvent::Move { dx: ex, dy: ey })
+            | (Some(InputEvent::Scroll { dx, dy }), InputEvent::Scroll { dx: ex, dy: ey }) => {
+                *dx += ex;
+                *dy += ey;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Token bucket for clicks, text and keys
+    fn take_token(&mut self) -> Result<(), String> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * MAX_EVENTS_PER_SEC).min(MAX_EVENTS_PER_SEC);
+        self.refilled_at = now;
+
+        if self.tokens < 1.0 {
+            return Err("Too many events, slow down".to_string());
+        }
+        self.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Take everything queued, converted to whole pixels and steps
+    fn drain(&mut self) -> Vec<Injection> {
+        let mut injections = Vec::new();
+
+        while let Some(event) = self.events.pop_front() {
+            match event {
+                InputEvent::Move { dx, dy } => {
+                    let (x, y) = carry(&mut self.move_rest, dx, dy);
+                    if x != 0 || y != 0 {
+                        injections.push(Injection::Move(x, y));
+                    }
+                }
+                InputEvent::Scroll { dx, dy } => {
+                    let (x, y) = carry(&mut self.scroll_rest, dx, dy);
+                    if y != 0 {
+                        let direction = if y > 0 { ScrollDirection::Down } else { ScrollDirection::Up };
+                        injections.push(Injection::Scroll(direction, y.unsigned_abs().min(MAX_SCROLL_AMOUNT)));
+                    }
+                    if x != 0 {
+                        let direction = if x > 0 { ScrollDirection::Right } else { ScrollDirection::Left };
+                        injections.push(Injection::Scroll(direction, x.unsigned_abs().min(MAX_SCROLL_AMOUNT)));
+                    }
+                }
+                InputEvent::Click { button, count } => injections.push(Injection::Click(button, count)),
+                InputEvent::Text { text } => injections.push(Injection::Text(text)),
+                InputEvent::Key { key } => injections.push(Injection::Key(key)),
+            }
+        }
+
+        injections
+    }
+}
+
+/// Add a delta to the leftover of previous frames and split off the whole part
+fn carry(rest: &mut (f64, f64), dx: f64, dy: f64) -> (i32, i32) {
+    let (x, y) = (rest.0 + dx, rest.1 + dy);
+    let (whole_x, whole_y) = (x.trunc(), y.trunc());
+    *rest = (x - whole_x, y - whole_y);
+    (whole_x as i32, whole_y as i32)
+}
+
+/// Send a batch of input to the desktop, stopping at the first failure
+fn inject(injections: Vec<Injection>) -> Result<(), String> {
+    for injection in injections {
+        match injection {
+            Injection::Move(dx, dy) => mouse::move_by(dx, dy)?,
+            Injection::Scroll(direction, amount) => mouse::scroll(&direction, amount)?,
+            Injection::Click(button, count) => mouse::click(button, count)?,
+            Injection::Text(text) => run(CommandConfig {
+                command_type: CommandType::TypeText,
+                text: Some(text),
+                ..Default::default()
+            })?,
+            Injection::Key(key) => run(CommandConfig {
+                command_type: CommandType::Keybind,
+                keybind: Some(key),
+                ..Default::default()
+            })?,
+        }
+    }
+    Ok(())
+}
+
+fn run(config: CommandConfig) -> Result<(), String> {
+    let result = system_commands::execute_command(&config);
+    if result.success {
+        Ok(())
+    } else {
+        Err(result.message)
+    }
+}
+
+/// Whether remote input is allowed, read fresh so it can be revoked without restarting
+pub async fn is_allowed(database: &SharedDatabase) -> bool {
+    database.lock().await.get_settings().allow_remote_input.unwrap_or(false)
+}
+
+/// Serve one remote input connection until the phone disconnects, the
+/// permission is revoked or the server stops
+pub async fn handle_socket(mut socket: WebSocket, database: SharedDatabase, mut closing: watch::Receiver<bool>) {
+    let mut queue = InputQueue::new();
+    let mut ticker = tokio::time::interval(FRAME);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut permission = tokio::time::interval(PERMISSION_CHECK);
+    permission.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let shutdown = async move {
+        let _ = closing.wait_for(|closing| *closing).await;
+    };
+    tokio::pin!(shutdown);
+
+    loop {
+        let error = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => serde_json::from_str::<InputEvent>(&text)
+                    .map_err(|e| format!("Invalid input event: {}", e))
+                    .and_then(|event| queue.push(event))
+                    .err(),
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Pings are answered by axum, binary messages aren't used
+                Some(Ok(_)) => None,
+            },
+            _ = ticker.tick() => {
+                let injections = queue.drain();
+                if injections.is_empty() {
+                    continue;
+                }
+                // Injection blocks; events arriving meanwhile are merged for the next frame
+                match tokio::task::spawn_blocking(move || inject(injections)).await {
+                    Ok(result) => result.err(),
+                    Err(e) => Some(e.to_string()),
+                }
+            }
+            _ = permission.tick() => {
+                if !is_allowed(&database).await {
+                    println!("Remote input was disallowed, closing connection");
+                    let _ = socket.send(Message::Close(None)).await;
+                    break;
+                }
+                None
+            }
+            _ = &mut shutdown => break,
+        };
+
+        if let Some(message) = error {
+            let reply = serde_json::to_string(&InputReply::Error { message }).unwrap_or_default();
+            if socket.send(Message::Text(reply)).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    println!("Remote input connection closed");
+}
@@ -0,0 +1,266 @@
+//! Scheduled execution of commands
+//!
+//! Schedules link a cron expression to a command. The scheduler runs for the
+//! whole lifetime of the app, independently of the HTTP server, and executes
+//! due commands through `executor::run_command`.
+
+use crate::database::SharedDatabase;
+use crate::events::EventSender;
+use crate::executor;
+use chrono::{DateTime, Local, TimeZone, Utc};
+use cron::Schedule as CronSchedule;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How often the scheduler checks for due commands
+const TICK: Duration = Duration::from_secs(1);
+
+/// A run later than this after its due time counts as missed (e.g. the app was closed)
+const MISSED_GRACE_SECS: i64 = 60;
+
+/// What to do with runs that were due while the app wasn't running
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedRunPolicy {
+    /// Drop missed runs and wait for the next one
+    #[default]
+    Skip,
+    /// Run once to catch up, however many runs were missed
+    RunOnce,
+}
+
+/// A cron expression bound to a command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    pub command_id: String,
+
+    /// Standard 5-field cron ("30 9 * * MON-FRI") or 6-field with seconds
+    pub cron_expression: String,
+
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    #[serde(default)]
+    pub missed_run_policy: MissedRunPolicy,
+
+    /// When the schedule last fired (or was created), managed by the scheduler
+    #[serde(default, skip_deserializing)]
+    pub last_run_at: Option<DateTime<Utc>>,
+
+    /// Next time the schedule will fire, computed when listing
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub next_run_at: Option<DateTime<Local>>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl Schedule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.is_empty() {
+            return Err("Schedule ID cannot be empty".to_string());
+        }
+        if self.command_id.is_empty() {
+            return Err(format!("Schedule '{}' needs a command", self.id));
+        }
+        parse_cron(&self.cron_expression)?;
+        Ok(())
+    }
+}
+
+/// Parse a cron expression, accepting the standard 5-field form without seconds
+pub fn parse_cron(expression: &str) -> Result<CronSchedule, String> {
+    let expression = expression.trim();
+    let normalized = if expression.split_whitespace().count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression.to_string()
+    };
+
+    CronSchedule::from_str(&normalized)
+        .map_err(|e| format!("Invalid cron expression '{}': {}", expression, e))
+}
+
+/// Upcoming run times of a cron expression, in local time
+pub fn preview_runs(expression: &str, count: usize) -> Result<Vec<DateTime<Local>>, String> {
+    let cron = parse_cron(expression)?;
+    Ok(cron.upcoming(Local).take(count).collect())
+}
+
+/// What the scheduler should do with a schedule on this tick
+#[derive(Debug, PartialEq)]
+enum Decision {
+    /// Nothing came due since the last run
+    Wait,
+    /// Execute the command and record the run
+    Run,
+    /// Record the run without executing, the due runs were all missed
+    Skip,
+}
+
+/// Decide whether a schedule fires, based on the latest occurrence in `(last_run, now]`
+///
+/// The latest occurrence runs if it is within the grace period. Only when it is
+/// older than that, meaning every occurrence since `last_run` was missed, does
+/// the missed run policy apply.
+fn decide<Tz: TimeZone>(
+    cron: &CronSchedule,
+    last_run: &DateTime<Tz>,
+    now: &DateTime<Tz>,
+    policy: &MissedRunPolicy,
+) -> Decision {
+    let latest = match cron.after(last_run).take_while(|t| t <= now).last() {
+        Some(latest) => latest,
+        None => return Decision::Wait,
+    };
+
+    if (now.clone() - latest).num_seconds() <= MISSED_GRACE_SECS {
+        return Decision::Run;
+    }
+    match policy {
+        MissedRunPolicy::Skip => Decision::Skip,
+        MissedRunPolicy::RunOnce => Decision::Run,
+    }
+}
+
+/// Execute due schedules, forever
+pub async fn run_scheduler(database: SharedDatabase, events: EventSender) {
+    let mut ticker = tokio::time::interval(TICK);
+
+    loop {
+        ticker.tick().await;
+
+        let schedules = {
+            let db = database.lock().await;
+            db.get_schedules().unwrap_or_default()
+        };
+        let now = Utc::now();
+
+        for schedule in schedules.iter().filter(|s| s.enabled) {
+            let cron = match parse_cron(&schedule.cron_expression) {
+                Ok(cron) => cron,
+                Err(_) => continue,
+            };
+
+            let last_run = match schedule.last_run_at {
+                Some(last_run) => last_run,
+                None => {
+                    // Nothing to catch up on for a schedule that never had a baseline
+                    mark_run(&database, &schedule.id, now).await;
+                    continue;
+                }
+            };
+
+            let last_run = last_run.with_timezone(&Local);
+            let now_local = now.with_timezone(&Local);
+            let run = match decide(&cron, &last_run, &now_local, &schedule.missed_run_policy) {
+                Decision::Wait => continue,
+                Decision::Run => true,
+                Decision::Skip => false,
+            };
+
+            mark_run(&database, &schedule.id, now).await;
+
+            if !run {
+                println!("Skipping missed run of schedule '{}'", schedule.id);
+                continue;
+            }
+
+            let cmd = {
+                let db = database.lock().await;
+                db.get_command(&schedule.command_id)
+            };
+            let cmd = match cmd {
+                Some(cmd) => cmd,
+                None => {
+                    eprintln!(
+                        "Schedule '{}' refers to missing command '{}'",
+                        schedule.id, schedule.command_id
+                    );
+                    continue;
+                }
+            };
+
+            // Run in the background so a slow command doesn't delay other schedules
+            let database = database.clone();
+            let events = events.clone();
+            let client = format!("scheduler:{}", schedule.id);
+            tokio::spawn(async move {
+                let result = executor::run_command(&cmd, &database, &events, &client).await;
+                if result.success {
+                    println!("Scheduled command '{}' executed", cmd.id);
+                } else {
+                    eprintln!("Scheduled command '{}' failed: {}", cmd.id, result.message);
+                }
+            });
+        }
+    }
+}
+
+async fn mark_run(database: &SharedDatabase, schedule_id: &str, at: DateTime<Utc>) {
+    let db = database.lock().await;
+    if let Err(e) = db.set_schedule_last_run(schedule_id, at) {
+        eprintln!("Failed to update schedule '{}': {}", schedule_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn daily() -> CronSchedule {
+        parse_cron("30 9 * * *").unwrap()
+    }
+
+    #[test]
+    fn waits_until_due() {
+        let last = at("2024-03-04T09:30:00Z");
+        let now = at("2024-03-05T09:29:59Z");
+        for policy in [MissedRunPolicy::Skip, MissedRunPolicy::RunOnce] {
+            assert_eq!(decide(&daily(), &last, &now, &policy), Decision::Wait);
+        }
+    }
+
+    #[test]
+    fn runs_on_time() {
+        let last = at("2024-03-04T09:30:00Z");
+        let now = at("2024-03-05T09:30:00Z");
+        for policy in [MissedRunPolicy::Skip, MissedRunPolicy::RunOnce] {
+            assert_eq!(decide(&daily(), &last, &now, &policy), Decision::Run);
+        }
+    }
+
+    #[test]
+    fn runs_recent_occurrence_after_downtime() {
+        // Closed for three days, started 20 seconds after today's run was due
+        let last = at("2024-03-01T09:30:00Z");
+        let now = at("2024-03-04T09:30:20Z");
+        for policy in [MissedRunPolicy::Skip, MissedRunPolicy::RunOnce] {
+            assert_eq!(decide(&daily(), &last, &now, &policy), Decision::Run);
+        }
+    }
+
+    #[test]
+    fn applies_policy_to_missed_runs() {
+        let last = at("2024-03-01T09:30:00Z");
+        let now = at("2024-03-04T12:00:00Z");
+        assert_eq!(decide(&daily(), &last, &now, &MissedRunPolicy::Skip), Decision::Skip);
+        assert_eq!(decide(&daily(), &last, &now, &MissedRunPolicy::RunOnce), Decision::Run);
+    }
+
+    #[test]
+    fn grace_period_boundary() {
+        let last = at("2024-03-04T09:30:00Z");
+        let inside = at("2024-03-05T09:31:00Z");
+        let outside = at("2024-03-05T09:31:01Z");
+        assert_eq!(decide(&daily(), &last, &inside, &MissedRunPolicy::Skip), Decision::Run);
+        assert_eq!(decide(&daily(), &last, &outside, &MissedRunPolicy::Skip), Decision::Skip);
+    }
+}
@@ -0,0 +1,436 @@
+//! Desktop screenshots
+//!
+//! Every backend captures the whole desktop; a single monitor is cut out of it
+//! using the geometry from `mouse::get_monitors`. On Linux, X11 (including
+//! Xvfb) is read directly over the X protocol, and Wayland sessions go through
+//! the xdg-desktop-portal Screenshot interface, falling back to `grim`.
+
+use crate::mouse::{self, Monitor};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbaImage};
+use serde::Deserialize;
+use std::io::Cursor;
+use std::process::Command;
+
+/// JPEG quality when none is requested
+const DEFAULT_QUALITY: u8 = 70;
+
+/// Width snapshots are scaled down to when no size is requested
+const DEFAULT_MAX_WIDTH: u32 = 1280;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotFormat {
+    #[default]
+    Jpeg,
+    Png,
+}
+
+impl SnapshotFormat {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            SnapshotFormat::Jpeg => "image/jpeg",
+            SnapshotFormat::Png => "image/png",
+        }
+    }
+}
+
+/// What to capture and how to encode it
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SnapshotOptions {
+    /// Monitor name as listed by `get_monitors`; the whole desktop when unset
+    pub monitor: Option<String>,
+    pub format: SnapshotFormat,
+    /// JPEG quality from 1 to 100
+    pub quality: Option<u8>,
+    /// Largest width of the result, the image is scaled down keeping its aspect ratio
+    pub max_width: Option<u32>,
+    /// Largest height of the result
+    pub max_height: Option<u32>,
+}
+
+impl SnapshotOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(quality) = self.quality {
+            if !(1..=100).contains(&quality) {
+                return Err("Quality must be between 1 and 100".to_string());
+            }
+        }
+        if self.max_width == Some(0) || self.max_height == Some(0) {
+            return Err("Maximum size must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Capture, crop, scale and encode a snapshot
+pub fn snapshot(options: &SnapshotOptions) -> Result<Vec<u8>, String> {
+    options.validate()?;
+
+    let image = DynamicImage::ImageRgba8(capture_desktop()?);
+    let monitors = match options.monitor {
+        Some(_) => mouse::get_monitors()?,
+        None => Vec::new(),
+    };
+    render(image, &monitors, options)
+}
+
+/// Crop, scale and encode a capture of the whole desktop
+fn render(mut image: DynamicImage, monitors: &[Monitor], options: &SnapshotOptions) -> Result<Vec<u8>, String> {
+    if let Some(ref name) = options.monitor {
+        let monitor = mouse::find_monitor(monitors, Some(name))?;
+        image = crop_to_monitor(image, monitors, monitor);
+    }
+
+    let max_width = options.max_width.unwrap_or(if options.max_height.is_some() {
+        u32::MAX
+    } else {
+        DEFAULT_MAX_WIDTH
+    });
+    let max_height = options.max_height.unwrap_or(u32::MAX);
+    if image.width() > max_width || image.height() > max_height {
+        image = image.resize(max_width, max_height, FilterType::Triangle);
+    }
+
+    encode(&image, options.format, options.quality.unwrap_or(DEFAULT_QUALITY))
+}
+
+pub fn encode(image: &DynamicImage, format: SnapshotFormat, quality: u8) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    match format {
+        SnapshotFormat::Jpeg => JpegEncoder::new_with_quality(&mut data, quality)
+            .encode_image(&image.to_rgb8())
+            .map_err(|e| format!("Failed to encode snapshot: {}", e))?,
+        SnapshotFormat::Png => image
+            .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to encode snapshot: {}", e))?,
+    }
+    Ok(data)
+}
+
+/// Cut one monitor out of a capture of the whole desktop
+///
+/// Captures may be in physical pixels while monitor geometry is logical (HiDPI),
+/// so coordinates are scaled by the ratio between the two.
+fn crop_to_monitor(image: DynamicImage, monitors: &[Monitor], monitor: &Monitor) -> DynamicImage {
+    let left = monitors.iter().map(|m| m.x).min().unwrap_or(0);
+    let top = monitors.iter().map(|m| m.y).min().unwrap_or(0);
+    let right = monitors.iter().map(|m| m.x + m.width as i32).max().unwrap_or(0);
+    if right <= left {
+        return image;
+    }
+
+    let scale = image.width() as f64 / (right - left) as f64;
+    let x = ((monitor.x - left) as f64 * scale).round() as u32;
+    let y = ((monitor.y - top) as f64 * scale).round() as u32;
+    let width = (monitor.width as f64 * scale).round() as u32;
+    let height = (monitor.height as f64 * scale).round() as u32;
+    image.crop_imm(
+        x.min(image.width()),
+        y.min(image.height()),
+        width.min(image.width().saturating_sub(x)),
+        height.min(image.height().saturating_sub(y)),
+    )
+}
+
+/// Capture the whole desktop
+pub fn capture_desktop() -> Result<RgbaImage, String> {
+    #[cfg(target_os = "linux")]
+    {
+        if crate::system_commands::is_wayland_session() {
+            linux::capture_portal()
+                .or_else(|portal| linux::capture_grim().map_err(|grim| format!("{}; {}", portal, grim)))
+        } else {
+            linux::capture_x11()
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        // A region spanning every display, so all of them end up in one image
+        let monitors = mouse::get_monitors()?;
+        let left = monitors.iter().map(|m| m.x).min().unwrap_or(0);
+        let top = monitors.iter().map(|m| m.y).min().unwrap_or(0);
+        let right = monitors.iter().map(|m| m.x + m.width as i32).max().unwrap_or(0);
+        let bottom = monitors.iter().map(|m| m.y + m.height as i32).max().unwrap_or(0);
+        let region = format!("-R{},{},{},{}", left, top, right - left, bottom - top);
+
+        capture_with_file("png", |path| {
+            // -x: no sound
+            let mut command = Command::new("screencapture");
+            command.args(["-x", "-t", "png", &region]).arg(path);
+            command
+        })
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        capture_with_file("png", |path| {
+            let script = format!(
+                "Add-Type -AssemblyName System.Windows.Forms,System.Drawing; \
+                 $b = [System.Windows.Forms.SystemInformation]::VirtualScreen; \
+                 $bmp = New-Object System.Drawing.Bitmap $b.Width, $b.Height; \
+                 $g = [System.Drawing.Graphics]::FromImage($bmp); \
+                 $g.CopyFromScreen($b.Left, $b.Top, 0, 0, $bmp.Size); \
+                 $bmp.Save('{}', [System.Drawing.Imaging.ImageFormat]::Png)",
+                path.display().to_string().replace('\'', "''")
+            );
+            let mut command = Command::new("powershell");
+            command.args(["-NoProfile", "-Command", &script]);
+            command
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        Err("Screenshots are not supported on this platform".to_string())
+    }
+}
+
+/// Run a screenshot tool that writes to a file and load the result
+fn capture_with_file(extension: &str, command: impl FnOnce(&std::path::Path) -> Command) -> Result<RgbaImage, String> {
+    let path = std::env::temp_dir().join(format!(
+        "deck-snapshot-{}-{:08x}.{}",
+        std::process::id(),
+        rand::random::<u32>(),
+        extension
+    ));
+
+    let output = command(&path)
+        .output()
+        .map_err(|e| format!("Failed to take screenshot: {}", e))?;
+    let image = if output.status.success() {
+        image::open(&path)
+            .map(|image| image.to_rgba8())
+            .map_err(|e| format!("Failed to read screenshot: {}", e))
+    } else {
+        Err(format!(
+            "Screenshot failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    };
+    let _ = std::fs::remove_file(&path);
+    image
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::capture_with_file;
+    use image::RgbaImage;
+    use std::collections::HashMap;
+    use std::process::Command;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use x11rb::connection::Connection as _;
+    use x11rb::image::{Image, PixelLayout};
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::{OwnedValue, Value};
+
+    /// How long to wait for the portal, which may ask the user for permission first
+    const PORTAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Read the root window over the X protocol
+    pub fn capture_x11() -> Result<RgbaImage, String> {
+        let (conn, screen_num) =
+            x11rb::connect(None).map_err(|e| format!("Failed to connect to the X server: {}", e))?;
+        let screen = &conn.setup().roots[screen_num];
+        let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+
+        let (image, visual_id) = Image::get(&conn, screen.root, 0, 0, width, height)
+            .map_err(|e| format!("Failed to capture the screen: {}", e))?;
+        let visual = screen
+            .allowed_depths
+            .iter()
+            .flat_map(|depth| &depth.visuals)
+            .find(|visual| visual.visual_id == visual_id)
+            .ok_or_else(|| "Unknown X11 visual".to_string())?;
+        let layout = PixelLayout::from_visual_type(*visual)
+            .map_err(|e| format!("Unsupported X11 visual: {}", e))?;
+
+        let mut rgba = RgbaImage::new(width as u32, height as u32);
+        for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+            let (r, g, b) = layout.decode(image.get_pixel(x as u16, y as u16));
+            *pixel = image::Rgba([(r >> 8) as u8, (g >> 8) as u8, (b >> 8) as u8, 255]);
+        }
+        Ok(rgba)
+    }
+
+    /// Ask xdg-desktop-portal for a screenshot
+    ///
+    /// The portal answers with a `Response` signal on a request object, so the
+    /// signal is subscribed to before the call, as the portal docs recommend.
+    pub fn capture_portal() -> Result<RgbaImage, String> {
+        let conn = Connection::session().map_err(|e| format!("Failed to connect to the session bus: {}", e))?;
+        let sender = conn
+            .unique_name()
+            .map(|name| name.trim_start_matches(':').replace('.', "_"))
+            .ok_or_else(|| "No D-Bus name".to_string())?;
+        let token = format!("deck{:08x}", rand::random::<u32>());
+        let request_path = format!("/org/freedesktop/portal/desktop/request/{}/{}", sender, token);
+
+        let portal_error = |e: zbus::Error| format!("Screenshot portal: {}", e);
+        let request = Proxy::new(
+            &conn,
+            "org.freedesktop.portal.Desktop",
+            request_path,
+            "org.freedesktop.portal.Request",
+        )
+        .map_err(portal_error)?;
+        let mut responses = request.receive_signal("Response").map_err(portal_error)?;
+
+        let screenshot = Proxy::new(
+            &conn,
+            "org.freedesktop.portal.Desktop",
+            "/org/freedesktop/portal/desktop",
+            "org.freedesktop.portal.Screenshot",
+        )
+        .map_err(portal_error)?;
+        let options: HashMap<&str, Value> = HashMap::from([
+            ("handle_token", Value::from(token.as_str())),
+            ("interactive", Value::from(false)),
+        ]);
+        screenshot
+            .call_method("Screenshot", &("", options))
+            .map_err(portal_error)?;
+
+        // The signal iterator blocks without a timeout, so wait for it on a thread
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = tx.send(responses.next());
+        });
+        let message = rx
+            .recv_timeout(PORTAL_TIMEOUT)
+            .map_err(|_| "Screenshot portal did not answer".to_string())?
+            .ok_or_else(|| "Screenshot portal closed the request".to_string())?;
+
+        let (response, results): (u32, HashMap<String, OwnedValue>) = message
+            .body()
+            .deserialize()
+            .map_err(|e| format!("Screenshot portal: {}", e))?;
+        if response != 0 {
+            return Err("Screenshot was denied".to_string());
+        }
+        let uri = results
+            .get("uri")
+            .and_then(|v| v.downcast_ref::<&str>().ok())
+            .and_then(|uri| uri.strip_prefix("file://"))
+            .map(crate::media::mpris::percent_decode)
+            .ok_or_else(|| "Screenshot portal returned no file".to_string())?;
+
+        let image = image::open(&uri)
+            .map(|image| image.to_rgba8())
+            .map_err(|e| format!("Failed to read screenshot: {}", e));
+        // The portal saves into the user's pictures, don't leave copies behind
+        let _ = std::fs::remove_file(&uri);
+        image
+    }
+
+    /// Screenshot with grim (wlroots compositors)
+    pub fn capture_grim() -> Result<RgbaImage, String> {
+        capture_with_file("png", |path| {
+            let mut command = Command::new("grim");
+            command.arg(path);
+            command
+        })
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::x11::xvfb::Xvfb;
+    use image::GenericImageView;
+    use x11rb::connection::Connection as _;
+    use x11rb::image::Image;
+    use x11rb::protocol::xproto::{ConnectionExt as _, CreateGCAux};
+    use x11rb::wrapper::ConnectionExt as _;
+
+    const WIDTH: u16 = 640;
+    const HEIGHT: u16 = 480;
+
+    /// Busy enough that JPEG quality shows in the size
+    fn pattern(x: u32, y: u32) -> [u8; 3] {
+        [((x * 7) ^ (y * 3)) as u8, (x + y) as u8, (x * y / 5) as u8]
+    }
+
+    /// Paint the pattern onto the root window
+    fn paint_root() {
+        let (conn, screen) = x11rb::connect(None).unwrap();
+        let root = conn.setup().roots[screen].root;
+        let mut image = Image::allocate_native(WIDTH, HEIGHT, 24, conn.setup()).unwrap();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let [r, g, b] = pattern(x as u32, y as u32);
+                image.put_pixel(x, y, (r as u32) << 16 | (g as u32) << 8 | b as u32);
+            }
+        }
+        let gc = conn.generate_id().unwrap();
+        conn.create_gc(gc, root, &CreateGCAux::new()).unwrap();
+        image.put(&conn, root, gc, 0, 0).unwrap();
+        conn.sync().unwrap();
+    }
+
+    fn monitor(name: &str, x: i32) -> Monitor {
+        Monitor { name: name.to_string(), x, y: 0, width: 320, height: 480, primary: x == 0 }
+    }
+
+    #[test]
+    fn captures_crops_and_encodes_under_xvfb() {
+        let Some(_xvfb) = Xvfb::start(WIDTH, HEIGHT) else {
+            eprintln!("Xvfb not available, skipping");
+            return;
+        };
+        paint_root();
+
+        let capture = linux::capture_x11().unwrap();
+        assert_eq!(capture.dimensions(), (WIDTH as u32, HEIGHT as u32));
+        for (x, y, pixel) in capture.enumerate_pixels() {
+            let [r, g, b] = pattern(x, y);
+            assert_eq!(pixel.0, [r, g, b, 255], "pixel at {},{}", x, y);
+        }
+
+        let capture = DynamicImage::ImageRgba8(capture);
+        let monitors = [monitor("left", 0), monitor("right", 320)];
+
+        // Full size PNG is lossless, so the crop can be checked pixel by pixel
+        let options = SnapshotOptions {
+            monitor: Some("right".to_string()),
+            format: SnapshotFormat::Png,
+            max_width: Some(320),
+            ..Default::default()
+        };
+        let png = render(capture.clone(), &monitors, &options).unwrap();
+        assert_eq!(image::guess_format(&png).unwrap(), image::ImageFormat::Png);
+        let cropped = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(cropped.dimensions(), (320, 480));
+        for (x, y, pixel) in cropped.enumerate_pixels() {
+            assert_eq!(pixel.0, pattern(x + 320, y), "pixel at {},{}", x, y);
+        }
+
+        let scaled = SnapshotOptions { max_width: Some(160), ..options };
+        let png = render(capture.clone(), &monitors, &scaled).unwrap();
+        assert_eq!(image::load_from_memory(&png).unwrap().dimensions(), (160, 240));
+
+        let jpeg = |quality: u8| {
+            let options = SnapshotOptions {
+                monitor: Some("left".to_string()),
+                format: SnapshotFormat::Jpeg,
+                quality: Some(quality),
+                max_height: Some(240),
+                ..Default::default()
+            };
+            render(capture.clone(), &monitors, &options).unwrap()
+        };
+        let (low, high) = (jpeg(10), jpeg(95));
+        for data in [&low, &high] {
+            assert_eq!(image::guess_format(data).unwrap(), image::ImageFormat::Jpeg);
+            assert_eq!(image::load_from_memory(data).unwrap().dimensions(), (160, 240));
+        }
+        assert!(low.len() < high.len(), "quality 10: {} bytes, quality 95: {} bytes", low.len(), high.len());
+
+        let missing = SnapshotOptions { monitor: Some("HDMI-9".to_string()), ..Default::default() };
+        assert!(render(capture, &monitors, &missing).unwrap_err().contains("not found"));
+    }
+}
@@ -0,0 +1,220 @@
+//! Live stream of the desktop as MJPEG
+//!
+//! One capture loop is shared by every viewer. It sleeps until someone
+//! connects, captures at the configured frame rate while at least one viewer
+//! is watching, and only publishes a frame when the picture changed. Viewers
+//! get the latest frame right away, then every new one.
+//!
+//! Wayland sessions can't stream: the only capture that works everywhere there
+//! is the Screenshot portal, which saves a file and may flash or play a sound
+//! for every frame.
+//!
+//! Snapshot clients are tracked here too so the desktop indicator shows
+//! anyone who is looking at the screen, not just stream viewers.
+
+use crate::database::SharedDatabase;
+use crate::screen::{self, SnapshotFormat};
+use axum::body::Bytes;
+use futures::stream::{self, Stream};
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbaImage};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Frame rate when none is configured
+pub const DEFAULT_FPS: u32 = 2;
+
+/// Highest configurable frame rate; this is meant for glances, not video
+pub const MAX_FPS: u32 = 10;
+
+/// Stream width when none is configured, in pixels
+pub const DEFAULT_MAX_WIDTH: u32 = 960;
+
+pub const MIN_WIDTH: u32 = 160;
+pub const MAX_WIDTH: u32 = 3840;
+
+/// JPEG quality of stream frames
+const FRAME_QUALITY: u8 = 60;
+
+/// Pause after a failed capture before trying again
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// How long a snapshot keeps a client counted as watching
+const SNAPSHOT_WATCH_WINDOW: Duration = Duration::from_secs(10);
+
+/// Boundary between the parts of the MJPEG response
+pub const BOUNDARY: &str = "frame";
+
+/// State shared between the capture loop and the viewers
+pub struct ScreenStream {
+    viewers: watch::Sender<usize>,
+    /// Latest JPEG frame; `None` ends the streams of current viewers
+    frames: watch::Sender<Option<Bytes>>,
+    /// When each client last took a snapshot
+    snapshots: Mutex<HashMap<IpAddr, Instant>>,
+}
+
+pub type SharedScreenStream = Arc<ScreenStream>;
+
+pub fn create_screen_stream() -> SharedScreenStream {
+    Arc::new(ScreenStream {
+        viewers: watch::channel(0).0,
+        frames: watch::channel(None).0,
+        snapshots: Mutex::new(HashMap::new()),
+    })
+}
+
+impl ScreenStream {
+    /// Number of clients currently watching
+    pub fn viewers(&self) -> usize {
+        *self.viewers.borrow()
+    }
+
+    /// Number of clients looking at the screen: stream viewers plus recent snapshot takers
+    pub fn watchers(&self) -> usize {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        snapshots.retain(|_, taken| taken.elapsed() < SNAPSHOT_WATCH_WINDOW);
+        self.viewers() + snapshots.len()
+    }
+
+    /// Note that a client took a snapshot so the desktop indicator shows it
+    pub fn record_snapshot(&self, client: IpAddr) {
+        self.snapshots.lock().unwrap().insert(client, Instant::now());
+    }
+
+    /// Register a viewer and stream MJPEG parts to it until it goes away
+    pub fn watch(self: &Arc<Self>) -> impl Stream<Item = Result<Bytes, std::convert::Infallible>> {
+        let viewer = Viewer::new(self.clone());
+        let mut frames = self.frames.subscribe();
+        // Send what we have right away instead of waiting for the picture to change
+        if frames.borrow().is_some() {
+            frames.mark_changed();
+        }
+
+        stream::unfold((frames, viewer), |(mut frames, viewer)| async move {
+            frames.changed().await.ok()?;
+            let frame = frames.borrow_and_update().clone()?;
+            Some((Ok(mjpeg_part(&frame)), (frames, viewer)))
+        })
+    }
+}
+
+/// Counts as a viewer for as long as it lives
+struct Viewer(SharedScreenStream);
+
+impl Viewer {
+    fn new(stream: SharedScreenStream) -> Self {
+        stream.viewers.send_modify(|n| *n += 1);
+        println!("Screen stream viewer connected ({} watching)", stream.viewers());
+        Self(stream)
+    }
+}
+
+impl Drop for Viewer {
+    fn drop(&mut self) {
+        self.0.viewers.send_modify(|n| *n = n.saturating_sub(1));
+        println!("Screen stream viewer left ({} watching)", self.0.viewers());
+    }
+}
+
+fn mjpeg_part(frame: &Bytes) -> Bytes {
+    let mut part = format!(
+        "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        BOUNDARY,
+        frame.len()
+    )
+    .into_bytes();
+    part.extend_from_slice(frame);
+    part.extend_from_slice(b"\r\n");
+    Bytes::from(part)
+}
+
+/// Capture frames whenever someone is watching; runs for the lifetime of the server
+pub async fn run_capture_loop(stream: SharedScreenStream, database: SharedDatabase) {
+    let mut viewers = stream.viewers.subscribe();
+    let mut previous: Option<RgbaImage> = None;
+
+    loop {
+        if *viewers.borrow() == 0 {
+            // Don't show the next viewer a stale picture; clearing without
+            // notifying keeps streams that are just starting alive
+            stream.frames.send_if_modified(|frame| {
+                *frame = None;
+                false
+            });
+            previous = None;
+        }
+        if viewers.wait_for(|n| *n > 0).await.is_err() {
+            return;
+        }
+
+        // Read fresh so changes apply without restarting the server
+        let settings = database.lock().await.get_settings();
+        if !settings.allow_screen_stream.unwrap_or(false) {
+            // Permission was revoked: end current streams and wait for them to leave
+            stream.frames.send_replace(None);
+            previous = None;
+            let _ = viewers.wait_for(|n| *n == 0).await;
+            continue;
+        }
+        let interval = Duration::from_secs(1) / settings.stream_fps.unwrap_or(DEFAULT_FPS).clamp(1, MAX_FPS);
+        let max_width = settings.stream_max_width.unwrap_or(DEFAULT_MAX_WIDTH);
+        let started = Instant::now();
+
+        let last = previous.take();
+        let captured = tokio::task::spawn_blocking(move || {
+            let frame = capture_frame(max_width)?;
+            // Nothing to send when the picture didn't change
+            let jpeg = if last.as_ref() == Some(&frame) {
+                None
+            } else {
+                let image = DynamicImage::ImageRgba8(frame.clone());
+                Some(screen::encode(&image, SnapshotFormat::Jpeg, FRAME_QUALITY)?)
+            };
+            Ok::<_, String>((frame, jpeg))
+        })
+        .await;
+
+        match captured {
+            Ok(Ok((frame, jpeg))) => {
+                previous = Some(frame);
+                if let Some(jpeg) = jpeg {
+                    stream.frames.send_replace(Some(Bytes::from(jpeg)));
+                }
+            }
+            Ok(Err(e)) => {
+                eprintln!("Failed to capture stream frame: {}", e);
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+            Err(e) => eprintln!("Stream capture task failed: {}", e),
+        }
+
+        tokio::time::sleep(interval.saturating_sub(started.elapsed())).await;
+    }
+}
+
+/// Check that the desktop can be streamed in this session
+pub fn check_supported() -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    {
+        if crate::system_commands::is_wayland_session() {
+            return Err("Live screen streaming is not available on Wayland, use snapshots instead".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Capture the desktop scaled down to `max_width`
+fn capture_frame(max_width: u32) -> Result<RgbaImage, String> {
+    check_supported()?;
+    let image = screen::capture_desktop()?;
+    if image.width() > max_width {
+        return Ok(DynamicImage::ImageRgba8(image)
+            .resize(max_width, u32::MAX, FilterType::Triangle)
+            .into_rgba8());
+    }
+    Ok(image)
+}
@@ -1,29 +1,63 @@
-use crate::config::Settings;
-use crate::database::SharedDatabase;
-use crate::system_commands;
-use crate::windows_focus;
+use crate::audit::{AuditEvent, AuthFailures, AUTH_FAILURE_WINDOW};
+use crate::audio::{self, VolumeChange, VolumeState, DEFAULT_MAX_VOLUME};
+use crate::clipboard::{self, ClipboardContent};
+use crate::commands::{CommandConfig, CommandState, CommandType, WindowAction};
+use crate::config::{DirectoryInfo, Settings};
+use crate::database::{Database, SharedDatabase};
+use crate::events::{self, EventSender, ServerEvent};
+use crate::executor;
+use crate::files::{self, DirectoryListing};
+use crate::focus_watcher::{self, ActiveProfile};
+use crate::history::{HistoryFilter, HistoryPage};
+use crate::media::{self, NowPlaying};
+use crate::live_state::{self, StateCache};
+use crate::profiles::{Profile, SlotKind};
+use crate::remote_input;
+use crate::screen::{self, SnapshotOptions};
+use crate::screen_stream::{self, SharedScreenStream};
+use crate::system_commands::{self, StepOutcome};
+use crate::upload::{self, UploadedFile};
+use crate::window_manager::{self, WindowInfo};
 use axum::{
-    extract::{Query, State},
-    http::{HeaderMap, StatusCode, Uri},
-    response::{Html, IntoResponse, Json, Response},
+    body::{Body, Bytes},
+    extract::{ws::WebSocketUpgrade, ConnectInfo, DefaultBodyLimit, Multipart, Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Json, Response,
+    },
     routing::{get, post},
     Router,
 };
+use futures::stream::{self, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::{oneshot, Mutex};
-use tower_http::services::ServeDir;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{broadcast, oneshot, watch, Mutex};
+use tokio::task::JoinHandle;
+use tower::ServiceExt;
+use tower_http::services::{ServeDir, ServeFile};
 
 #[derive(Serialize, Deserialize)]
 struct ExecuteRequest {
     id: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize)]
 struct ExecuteResponse {
     success: bool,
     message: String,
+    /// Per-step outcomes, only present for macros
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    steps: Vec<StepOutcome>,
+    /// Output volume after a volume command, when the platform reports it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    volume: Option<VolumeState>,
 }
 
 #[derive(Serialize)]
@@ -36,6 +70,44 @@ struct AuthQuery {
     code: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct UploadQuery {
+    /// Id of the upload target to write into
+    target: String,
+    /// Open the target directory on the desktop once the files are written
+    #[serde(default)]
+    open: bool,
+    /// Id used in progress events; generated when unset
+    upload_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FilesQuery {
+    /// Id of the browse root
+    root: String,
+    /// Path relative to the root; the root itself when empty
+    #[serde(default)]
+    path: String,
+}
+
+#[derive(Serialize)]
+struct UploadResponse {
+    upload_id: String,
+    files: Vec<UploadedFile>,
+}
+
+#[derive(Deserialize)]
+struct VolumeRequest {
+    /// Volume in percent
+    level: u8,
+}
+
+#[derive(Deserialize, Default)]
+struct MediaQuery {
+    /// Player name, e.g. "spotify"; the active player when unset
+    player: Option<String>,
+}
+
 async fn health_handler() -> Json<HealthResponse> {
     Json(HealthResponse {
         status: "ok".to_string(),
@@ -46,19 +118,51 @@ async fn health_handler() -> Json<HealthResponse> {
 struct CommandInfo {
     id: String,
     name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subtitle: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+    /// URL of the uploaded image, takes precedence over `icon`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    background_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    foreground_color: Option<String>,
+    /// Last evaluated live state, if the command has a state query
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<CommandState>,
+}
+
+/// Build the phone-facing view of a command
+fn command_info(c: CommandConfig, db: &Database, states: &HashMap<String, CommandState>) -> CommandInfo {
+    let image_url = db
+        .has_command_image(&c.id)
+        .then(|| format!("/api/commands/{}/icon", c.id));
+    let command_state = states.get(&c.id).cloned().or_else(|| {
+        (c.command_type == CommandType::Toggle).then(|| c.toggle_state(db.get_toggle_state(&c.id)))
+    });
+    CommandInfo {
+        id: c.id,
+        name: c.name,
+        subtitle: c.subtitle,
+        icon: c.icon,
+        image_url,
+        background_color: c.background_color,
+        foreground_color: c.foreground_color,
+        state: command_state,
+    }
 }
 
 async fn commands_handler(
     State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Query(query): Query<AuthQuery>,
     headers: HeaderMap,
 ) -> Result<Json<Vec<CommandInfo>>, StatusCode> {
-    let settings = state.settings.lock().await;
-    
-    if !verify_auth(&query, &headers, &settings) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    drop(settings);
+    authorize(&state, addr, &query, &headers).await?;
+
+    let states = state.command_states.lock().await.clone();
 
     // Fetch commands fresh from the database
     let db = state.database.lock().await;
@@ -66,12 +170,700 @@ async fn commands_handler(
     
     let command_list: Vec<CommandInfo> = commands
         .into_iter()
-        .map(|c| CommandInfo { id: c.id, name: c.name })
+        .map(|c| command_info(c, &db, &states))
         .collect();
     
     Ok(Json(command_list))
 }
 
+#[derive(Serialize)]
+struct SlotInfo {
+    position: u32,
+    kind: SlotKind,
+    /// Command shown in the slot (for command slots)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<CommandInfo>,
+    /// Page opened by the slot (for folder slots)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PageLayout {
+    id: String,
+    profile_id: String,
+    name: String,
+    /// Page to go back to, if this page was opened from a folder
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_page_id: Option<String>,
+    slots: Vec<SlotInfo>,
+}
+
+async fn profiles_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Profile>>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    let db = state.database.lock().await;
+    let profiles = db.get_profiles().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(profiles))
+}
+
+#[derive(Serialize)]
+struct ActiveProfileResponse {
+    profile_id: Option<String>,
+    page_id: Option<String>,
+}
+
+/// Return the profile selected by the focused application, if any
+async fn active_profile_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+) -> Result<Json<ActiveProfileResponse>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    let profile_id = state.active_profile.lock().await.clone();
+    let page_id = match profile_id {
+        Some(ref id) => {
+            let db = state.database.lock().await;
+            db.get_profile(id).and_then(|p| p.home_page_id)
+        }
+        None => None,
+    };
+
+    Ok(Json(ActiveProfileResponse { profile_id, page_id }))
+}
+
+/// Return the layout of a page, with commands resolved and dangling slots dropped
+async fn page_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(id): Path<String>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+) -> Result<Json<PageLayout>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    let states = state.command_states.lock().await.clone();
+
+    let db = state.database.lock().await;
+    let page = db.get_page(&id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let slots = page
+        .slots
+        .into_iter()
+        .filter_map(|slot| match slot.kind {
+            SlotKind::Command => {
+                let cmd = db.get_command(slot.command_id.as_deref()?)?;
+                Some(SlotInfo {
+                    position: slot.position,
+                    kind: SlotKind::Command,
+                    command: Some(command_info(cmd, &db, &states)),
+                    page_id: None,
+                    label: None,
+                    icon: None,
+                })
+            }
+            SlotKind::Folder => {
+                let target = db.get_page(slot.target_page_id.as_deref()?)?;
+                Some(SlotInfo {
+                    position: slot.position,
+                    kind: SlotKind::Folder,
+                    command: None,
+                    page_id: Some(target.id),
+                    label: Some(slot.label.unwrap_or(target.name)),
+                    icon: slot.icon,
+                })
+            }
+        })
+        .collect();
+
+    Ok(Json(PageLayout {
+        id: page.id,
+        profile_id: page.profile_id,
+        name: page.name,
+        parent_page_id: page.parent_page_id,
+        slots,
+    }))
+}
+
+/// Query the execution history, newest first
+async fn history_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    Query(filter): Query<HistoryFilter>,
+    headers: HeaderMap,
+) -> Result<Json<HistoryPage>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    let db = state.database.lock().await;
+    let page = db
+        .query_executions(&filter)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(page))
+}
+
+/// Serve the uploaded image for a command, with ETag-based caching
+async fn command_icon_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(id): Path<String>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    let db = state.database.lock().await;
+    let image = db.get_command_image(&id).ok_or(StatusCode::NOT_FOUND)?;
+    drop(db);
+
+    let cache_headers = [
+        (header::ETAG, image.etag.clone()),
+        (header::CACHE_CONTROL, "private, max-age=86400, must-revalidate".to_string()),
+    ];
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(',').any(|tag| tag.trim() == image.etag || tag.trim() == "*"))
+        .unwrap_or(false);
+
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    Ok((
+        cache_headers,
+        [(header::CONTENT_TYPE, image.mime_type)],
+        image.data,
+    )
+        .into_response())
+}
+
+/// What the selected (or active) media player is playing; 404 when none is running
+async fn now_playing_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    Query(media_query): Query<MediaQuery>,
+    headers: HeaderMap,
+) -> Result<Json<NowPlaying>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    let now_playing = tokio::task::spawn_blocking(move || media::now_playing(media_query.player.as_deref()))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| {
+            eprintln!("Failed to read now playing: {}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        })?;
+
+    now_playing.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Level and mute state of the default output
+async fn get_volume_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+) -> Result<Json<VolumeState>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    tokio::task::spawn_blocking(audio::get_volume)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
+        .map_err(|e| {
+            eprintln!("Failed to read volume: {}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        })
+}
+
+/// Set the default output to an absolute level, e.g. from a slider
+///
+/// Levels above the `max_volume` setting are refused. The new state is returned and published to every client on `/api/events`.
+async fn set_volume_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+    Json(req): Json<VolumeRequest>,
+) -> Result<Json<VolumeState>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+    let max = state.database.lock().await.get_settings().max_volume.unwrap_or(DEFAULT_MAX_VOLUME);
+    if req.level > max {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let volume = tokio::task::spawn_blocking(move || audio::change_volume(VolumeChange::Set(req.level)))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| {
+            eprintln!("Failed to set volume: {}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        })?;
+
+    events::publish(&state.events, ServerEvent::Volume(volume));
+    Ok(Json(volume))
+}
+
+/// Cover image of the current track, for players that keep it on disk
+async fn media_art_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    Query(media_query): Query<MediaQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    let path = tokio::task::spawn_blocking(move || media::art_file(media_query.player.as_deref()))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let data = tokio::fs::read(&path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    let mime_type = image::guess_format(&data)
+        .map(|format| format.to_mime_type())
+        .map_err(|_| StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, mime_type),
+            (header::CACHE_CONTROL, "private, max-age=60"),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// Current contents of the PC clipboard
+async fn get_clipboard_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+) -> Result<Json<ClipboardContent>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    tokio::task::spawn_blocking(clipboard::get_clipboard)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
+        .map_err(|e| {
+            eprintln!("Failed to read clipboard: {}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        })
+}
+
+/// Replace the PC clipboard with the request body
+///
+/// An `image/*` content type puts an image on the clipboard, anything else is
+/// taken as UTF-8 text.
+async fn set_clipboard_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    let is_image = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("image/"))
+        .unwrap_or(false);
+
+    let result = if is_image {
+        tokio::task::spawn_blocking(move || clipboard::set_image(&body)).await
+    } else {
+        let text = String::from_utf8(body.to_vec()).map_err(|_| StatusCode::BAD_REQUEST)?;
+        tokio::task::spawn_blocking(move || clipboard::set_text(&text)).await
+    };
+
+    result
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(|_| StatusCode::NO_CONTENT)
+        .map_err(|e| {
+            eprintln!("Failed to set clipboard: {}", e);
+            if e.starts_with("Clipboard") {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                StatusCode::BAD_REQUEST
+            }
+        })
+}
+
+/// Directories the phone may upload into
+async fn upload_targets_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<DirectoryInfo>>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    let db = state.database.lock().await;
+    let targets = db.get_settings().upload_targets.unwrap_or_default();
+    Ok(Json(targets.iter().map(DirectoryInfo::from).collect()))
+}
+
+/// Write the files of a multipart request into an upload target
+///
+/// Progress is published on `/api/events` under the upload id.
+async fn upload_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    Query(upload_query): Query<UploadQuery>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Json<UploadResponse>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    // Read fresh so targets can change without restarting the server
+    let settings = state.database.lock().await.get_settings();
+    let target = settings
+        .upload_targets
+        .unwrap_or_default()
+        .into_iter()
+        .find(|t| t.id == upload_query.target)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let max_bytes = settings.max_upload_mb.unwrap_or(upload::DEFAULT_MAX_UPLOAD_MB) * 1024 * 1024;
+
+    let total_bytes = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    // Allow some room for the multipart boundaries and headers
+    if total_bytes.is_some_and(|total| total > max_bytes + 64 * 1024) {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let dir = files::resolve_root(&target).map_err(|e| {
+        eprintln!("{}", e);
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+
+    let upload_id = upload::upload_id(upload_query.upload_id);
+    let mut progress = upload::Progress::new(state.events.clone(), upload_id.clone(), total_bytes);
+
+    let files = match receive_files(&mut multipart, &dir, max_bytes, &mut progress).await {
+        Ok(files) => {
+            progress.finish(None);
+            files
+        }
+        Err((status, e)) => {
+            eprintln!("Upload {} from {} failed: {}", upload_id, addr.ip(), e);
+            progress.finish(Some(e));
+            return Err(status);
+        }
+    };
+    println!("Received {} file(s) from {} into {}", files.len(), addr.ip(), dir.display());
+
+    if upload_query.open {
+        let config = CommandConfig {
+            command_type: CommandType::OpenDirectory,
+            directory_path: Some(dir.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        match tokio::task::spawn_blocking(move || system_commands::execute_command(&config)).await {
+            Ok(result) if !result.success => {
+                eprintln!("Failed to open upload directory: {}", result.message)
+            }
+            Err(e) => eprintln!("Failed to open upload directory: {}", e),
+            Ok(_) => {}
+        }
+    }
+
+    Ok(Json(UploadResponse { upload_id, files }))
+}
+
+/// Stream every file field of a multipart request into `dir`
+///
+/// A file that fails midway is removed; files completed before it are kept.
+async fn receive_files(
+    multipart: &mut Multipart,
+    dir: &std::path::Path,
+    max_bytes: u64,
+    progress: &mut upload::Progress,
+) -> Result<Vec<UploadedFile>, (StatusCode, String)> {
+    let mut files = Vec::new();
+
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid upload: {}", e)))?
+    {
+        // Fields without a file name aren't files
+        let Some(name) = field.file_name() else { continue };
+        let name = upload::sanitize_file_name(name).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let (path, mut file) = upload::create_file(dir, &name)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or(name);
+        progress.start_file(&file_name);
+
+        let mut size = 0u64;
+        let written: Result<(), (StatusCode, String)> = async {
+            while let Some(chunk) = field
+                .chunk()
+                .await
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Upload interrupted: {}", e)))?
+            {
+                if progress.received_bytes() + chunk.len() as u64 > max_bytes {
+                    return Err((
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        format!("Upload exceeds {} MB", max_bytes / 1024 / 1024),
+                    ));
+                }
+                file.write_all(&chunk)
+                    .await
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write {}: {}", file_name, e)))?;
+                size += chunk.len() as u64;
+                progress.add(chunk.len() as u64);
+            }
+            file.flush()
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write {}: {}", file_name, e)))
+        }
+        .await;
+
+        if let Err(e) = written {
+            drop(file);
+            let _ = tokio::fs::remove_file(&path).await;
+            return Err(e);
+        }
+        files.push(UploadedFile { file_name, size });
+    }
+
+    if files.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No files in upload".to_string()));
+    }
+    Ok(files)
+}
+
+/// Directories the phone may browse
+async fn browse_roots_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<DirectoryInfo>>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    let db = state.database.lock().await;
+    let roots = db.get_settings().browse_roots.unwrap_or_default();
+    Ok(Json(roots.iter().map(DirectoryInfo::from).collect()))
+}
+
+/// List a directory inside a browse root, or download a file (with range support)
+async fn files_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    Query(files_query): Query<FilesQuery>,
+    headers: HeaderMap,
+    request: Request,
+) -> Result<Response, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    // Read fresh so roots can be removed without restarting the server
+    let root = state
+        .database
+        .lock()
+        .await
+        .get_settings()
+        .browse_roots
+        .unwrap_or_default()
+        .into_iter()
+        .find(|r| r.id == files_query.root)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    enum Target {
+        Directory(DirectoryListing),
+        File(PathBuf),
+    }
+
+    let target = tokio::task::spawn_blocking(move || {
+        let root_dir = files::resolve_root(&root).map_err(|e| {
+            eprintln!("{}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        })?;
+        let path = files::resolve(&root_dir, &files_query.path).ok_or(StatusCode::NOT_FOUND)?;
+        if path.is_dir() {
+            files::list(&root.id, &root_dir, &path)
+                .map(Target::Directory)
+                .map_err(|e| {
+                    eprintln!("{}", e);
+                    StatusCode::FORBIDDEN
+                })
+        } else if path.is_file() {
+            Ok(Target::File(path))
+        } else {
+            // FIFOs, sockets and devices would hang or stream forever
+            Err(StatusCode::NOT_FOUND)
+        }
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+
+    match target {
+        Target::Directory(listing) => Ok(Json(listing).into_response()),
+        // Handles Range, If-Modified-Since and the content type
+        Target::File(path) => ServeFile::new(path)
+            .oneshot(request)
+            .await
+            .map(IntoResponse::into_response)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Whether phones may see the desktop, through snapshots or the stream
+async fn screen_allowed(state: &ServerState) -> bool {
+    // Read fresh so the permission can be revoked without restarting the server
+    let db = state.database.lock().await;
+    db.get_settings().allow_screen_stream.unwrap_or(false)
+}
+
+/// Screenshot of the desktop or of one monitor, scaled and encoded as requested
+///
+/// Needs screen streaming to be allowed in the settings, like the live stream.
+async fn snapshot_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    Query(options): Query<SnapshotOptions>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+    if !screen_allowed(&state).await {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    options.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+    state.screen_stream.record_snapshot(addr.ip());
+
+    let mime_type = options.format.mime_type();
+    let data = tokio::task::spawn_blocking(move || screen::snapshot(&options))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| {
+            eprintln!("Failed to take snapshot: {}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, mime_type),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// Live MJPEG stream of the desktop; needs screen streaming to be allowed in the settings
+async fn screen_stream_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+    if !screen_allowed(&state).await {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if let Err(e) = screen_stream::check_supported() {
+        return Ok((StatusCode::NOT_IMPLEMENTED, e).into_response());
+    }
+
+    println!("Screen stream requested from {}", addr.ip());
+    let mut closing = state.closing.clone();
+    let frames = state.screen_stream.watch().take_until(async move {
+        let _ = closing.wait_for(|closing| *closing).await;
+    });
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                format!("multipart/x-mixed-replace; boundary={}", screen_stream::BOUNDARY),
+            ),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+        Body::from_stream(frames),
+    )
+        .into_response())
+}
+
+/// Stream live events (command state changes, ...) as Server-Sent Events
+async fn events_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    let rx = state.events.subscribe();
+    let events = stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    let data = serde_json::to_string(&event).unwrap_or_default();
+                    return Some((Ok(Event::default().data(data)), rx));
+                }
+                // A slow client missed some events, keep going with the newest ones
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    // End the stream when the server shuts down, otherwise graceful shutdown waits forever
+    let mut closing = state.closing.clone();
+    let events = events.take_until(async move {
+        let _ = closing.wait_for(|closing| *closing).await;
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Remote trackpad and keyboard; needs remote input to be allowed in the settings
+async fn remote_input_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Result<Response, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+    if !remote_input::is_allowed(&state.database).await {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    println!("Remote input connected from {}", addr.ip());
+    let database = state.database.clone();
+    let closing = state.closing.clone();
+    Ok(ws.on_upgrade(move |socket| remote_input::handle_socket(socket, database, closing)))
+}
+
 /// Fallback handler that serves index.html for SPA routing
 async fn fallback_handler(
     State(state): State<Arc<ServerState>>,
@@ -155,6 +947,75 @@ fn get_fallback_html() -> String {
 </html>"##.to_string()
 }
 
+/// Check a request's credentials, recording failures in the audit log
+async fn authorize(
+    state: &ServerState,
+    addr: SocketAddr,
+    query: &AuthQuery,
+    headers: &HeaderMap,
+) -> Result<(), StatusCode> {
+    let settings = state.settings.lock().await;
+    if verify_auth(query, headers, &settings) {
+        return Ok(());
+    }
+    drop(settings);
+
+    // Repeated failures within a minute are only counted
+    let Some(repeated) = state.auth_failures.record(addr.ip()) else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    let db = state.database.lock().await;
+    // Which kind of credential was presented, never its value
+    let credential = if query.code.is_some() {
+        "query"
+    } else if headers.contains_key("authorization") {
+        "header"
+    } else {
+        "none"
+    };
+    let mut details = json!({ "credential": credential });
+    if repeated > 0 {
+        details["repeated"] = json!(repeated);
+    }
+    if let Err(e) = db.append_audit(AuditEvent::AuthFailed, &addr.ip().to_string(), &details) {
+        eprintln!("Failed to write audit log: {}", e);
+    }
+    Err(StatusCode::UNAUTHORIZED)
+}
+
+/// Write one entry per client for failures coalesced into a finished window
+///
+/// Stops once the server starts shutting down; the server writes what is left
+/// after the last request has been handled.
+async fn flush_auth_failures(
+    database: SharedDatabase,
+    failures: Arc<AuthFailures>,
+    mut closing: watch::Receiver<bool>,
+) {
+    let mut ticker = tokio::time::interval(AUTH_FAILURE_WINDOW);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = closing.wait_for(|closing| *closing) => return,
+        }
+        write_auth_failures(&database, failures.take_expired()).await;
+    }
+}
+
+async fn write_auth_failures(database: &SharedDatabase, failures: Vec<(IpAddr, u32)>) {
+    if failures.is_empty() {
+        return;
+    }
+    let db = database.lock().await;
+    for (ip, repeated) in failures {
+        let details = json!({ "repeated": repeated });
+        if let Err(e) = db.append_audit(AuditEvent::AuthFailed, &ip.to_string(), &details) {
+            eprintln!("Failed to write audit log: {}", e);
+        }
+    }
+}
+
 /// Verify authentication - checks query parameter first, then Authorization header
 fn verify_auth(query: &AuthQuery, headers: &HeaderMap, settings: &Settings) -> bool {
     // First check query parameter
@@ -179,18 +1040,60 @@ fn verify_auth(query: &AuthQuery, headers: &HeaderMap, settings: &Settings) -> b
     false
 }
 
+/// Top-level windows on the PC
+async fn windows_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<WindowInfo>>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+
+    tokio::task::spawn_blocking(window_manager::list_windows)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
+        .map_err(|e| {
+            eprintln!("Failed to list windows: {}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        })
+}
+
+/// Run a one-off window action, like a Window command that isn't on a page
+async fn window_action_handler(
+    State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<AuthQuery>,
+    headers: HeaderMap,
+    Json(action): Json<WindowAction>,
+) -> Result<Json<ExecuteResponse>, StatusCode> {
+    authorize(&state, addr, &query, &headers).await?;
+    action.validate().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let result = tokio::task::spawn_blocking(move || window_manager::perform(&action))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (success, message) = match result {
+        Ok(message) => (true, message),
+        Err(e) => (false, e),
+    };
+    Ok(Json(ExecuteResponse {
+        success,
+        message,
+        steps: Vec::new(),
+        volume: None,
+    }))
+}
+
 async fn execute_handler(
     State(state): State<Arc<ServerState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Query(query): Query<AuthQuery>,
     headers: HeaderMap,
     Json(req): Json<ExecuteRequest>,
 ) -> Result<Json<ExecuteResponse>, StatusCode> {
-    let settings = state.settings.lock().await;
-    
-    if !verify_auth(&query, &headers, &settings) {
-        return Err(StatusCode::UNAUTHORIZED);
-    }
-    drop(settings);
+    authorize(&state, addr, &query, &headers).await?;
 
     // Fetch command fresh from database
     let db = state.database.lock().await;
@@ -198,21 +1101,26 @@ async fn execute_handler(
         .ok_or(StatusCode::NOT_FOUND)?;
     drop(db);
 
-    // Execute the command using the system_commands module
-    let result = system_commands::execute_command(&cmd_config);
+    // Execute the command, keeping toggle state in sync
+    let client = addr.ip().to_string();
+    let result = executor::run_command(&cmd_config, &state.database, &state.events, &client).await;
 
-    // Handle legacy focus_app field for backward compatibility
-    if result.success {
-        if let Some(ref app_title) = cmd_config.focus_app {
-            if let Err(e) = windows_focus::focus_window_by_title(app_title) {
-                eprintln!("Failed to focus window '{}': {}", app_title, e);
-            }
-        }
+    // Refresh the button state now that the command may have changed it
+    if cmd_config.state_query.is_some() {
+        let cmd = cmd_config.clone();
+        let database = state.database.clone();
+        let cache = state.command_states.clone();
+        let events = state.events.clone();
+        tokio::spawn(async move {
+            live_state::refresh_command_state(&cmd, &database, &cache, &events).await;
+        });
     }
 
     Ok(Json(ExecuteResponse {
         success: result.success,
         message: result.message,
+        steps: result.steps,
+        volume: result.volume,
     }))
 }
 
@@ -220,16 +1128,34 @@ pub struct ServerState {
     pub settings: Arc<Mutex<Settings>>,
     pub database: SharedDatabase,
     pub mobile_dist_path: Option<String>,
+    pub events: EventSender,
+    pub command_states: StateCache,
+    pub active_profile: ActiveProfile,
+    pub screen_stream: SharedScreenStream,
+    /// Failed logins per client, so each adds at most one audit row a minute
+    pub auth_failures: Arc<AuthFailures>,
+    /// Flips to true when the server starts shutting down
+    pub closing: watch::Receiver<bool>,
 }
 
 /// Handle for controlling the running server
 pub struct ServerHandle {
     shutdown_tx: oneshot::Sender<()>,
+    background_tasks: Vec<JoinHandle<()>>,
+    screen_stream: SharedScreenStream,
 }
 
 impl ServerHandle {
+    /// Number of phones currently watching the desktop, by stream or snapshots
+    pub fn screen_stream_viewers(&self) -> usize {
+        self.screen_stream.watchers()
+    }
+
     /// Gracefully shutdown the server
     pub fn shutdown(self) {
+        for task in self.background_tasks {
+            task.abort();
+        }
         let _ = self.shutdown_tx.send(());
     }
 }
@@ -237,14 +1163,26 @@ impl ServerHandle {
 pub async fn start_server(
     settings: Settings,
     database: SharedDatabase,
+    events: EventSender,
     mobile_dist_path: Option<String>,
 ) -> Result<ServerHandle, String> {
     let port = settings.port;
+    let command_states = live_state::create_state_cache();
+    let active_profile = focus_watcher::create_active_profile();
+    let screen_stream = screen_stream::create_screen_stream();
+    let (closing_tx, closing_rx) = watch::channel(false);
+    let auth_failures = Arc::new(AuthFailures::default());
     
     let state = ServerState {
         settings: Arc::new(Mutex::new(settings)),
-        database,
+        database: database.clone(),
         mobile_dist_path: mobile_dist_path.clone(),
+        events: events.clone(),
+        command_states: command_states.clone(),
+        active_profile: active_profile.clone(),
+        screen_stream: screen_stream.clone(),
+        auth_failures: auth_failures.clone(),
+        closing: closing_rx.clone(),
     };
 
     let shared_state = Arc::new(state);
@@ -253,7 +1191,35 @@ pub async fn start_server(
     let mut router = Router::new()
         .route("/health", get(health_handler))
         .route("/execute", post(execute_handler))
-        .route("/api/commands", get(commands_handler));
+        .route("/api/commands", get(commands_handler))
+        .route("/api/commands/:id/icon", get(command_icon_handler))
+        .route("/api/events", get(events_handler))
+        .route("/api/profiles", get(profiles_handler))
+        .route("/api/profiles/active", get(active_profile_handler))
+        .route("/api/pages/:id", get(page_handler))
+        .route("/api/history", get(history_handler))
+        .route("/api/input", get(remote_input_handler))
+        .route("/api/media/now-playing", get(now_playing_handler))
+        .route("/api/media/art", get(media_art_handler))
+        .route("/api/volume", get(get_volume_handler).put(set_volume_handler))
+        .route(
+            "/api/clipboard",
+            get(get_clipboard_handler)
+                .post(set_clipboard_handler)
+                .layer(DefaultBodyLimit::max(clipboard::MAX_CLIPBOARD_BYTES)),
+        )
+        .route("/api/upload/targets", get(upload_targets_handler))
+        .route("/api/files/roots", get(browse_roots_handler))
+        .route("/api/files", get(files_handler))
+        .route("/api/screen/snapshot", get(snapshot_handler))
+        .route("/api/screen/stream", get(screen_stream_handler))
+        .route("/api/windows", get(windows_handler))
+        .route("/api/windows/action", post(window_action_handler))
+        .route(
+            "/api/upload",
+            // The configured limit is checked while streaming, this is a hard ceiling
+            post(upload_handler).layer(DefaultBodyLimit::max((upload::MAX_UPLOAD_MB * 1024 * 1024) as usize)),
+        );
     
     // If mobile dist path exists, serve static files from it
     if let Some(ref dist_path) = mobile_dist_path {
@@ -284,15 +1250,42 @@ pub async fn start_server(
     let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
 
     // Spawn the server task
+    let pending_failures = auth_failures.clone();
+    let audit_database = database.clone();
     tokio::spawn(async move {
-        axum::serve(listener, router)
-            .with_graceful_shutdown(async {
+        // Connection info gives handlers the client address (for history)
+        axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(async move {
                 let _ = shutdown_rx.await;
+                let _ = closing_tx.send(true);
                 println!("Server shutting down gracefully...");
             })
             .await
             .ok();
+
+        // Failures still being coalesced would otherwise never be logged
+        write_auth_failures(&audit_database, pending_failures.take_all()).await;
     });
 
-    Ok(ServerHandle { shutdown_tx })
+    // Keep button states up to date while the server is running
+    let state_poller = tokio::spawn(live_state::run_state_poller(
+        database.clone(),
+        command_states,
+        events.clone(),
+    ));
+
+    // Capture the desktop only while someone watches the stream
+    let stream_capture = tokio::spawn(screen_stream::run_capture_loop(screen_stream.clone(), database.clone()));
+
+    // Report repeated failed logins once their window has passed
+    tokio::spawn(flush_auth_failures(database.clone(), auth_failures, closing_rx));
+
+    // Switch profiles as the focused application changes
+    let focus_watcher = tokio::spawn(focus_watcher::run_focus_watcher(database, active_profile, events));
+
+    Ok(ServerHandle {
+        shutdown_tx,
+        background_tasks: vec![state_poller, stream_capture, focus_watcher],
+        screen_stream,
+    })
 }
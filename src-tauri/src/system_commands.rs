@@ -1,12 +1,23 @@
 //! System command handlers for built-in command types
 //! 
 //! This module provides platform-specific implementations for:
-//! - Volume control
+//! - Volume control (through `audio`)
 //! - Opening directories
 //! - Focusing applications
 //! - Sending keyboard shortcuts
-
-use crate::commands::{CommandConfig, CommandType, VolumeDirection};
+//! - Typing text
+//! - Mouse actions (pointer primitives live in `mouse`)
+//! - Window actions (through `window_manager`)
+//! - Media player control (through `media`)
+//! - Querying live state (mute, volume level, running apps)
+
+use crate::audio::{self, VolumeChange, VolumeState, DEFAULT_MAX_VOLUME};
+use crate::commands::{
+    CommandConfig, CommandState, CommandType, MouseAction, MouseActionKind, StateProbe, StateQuery,
+    StepType, VolumeDirection, WindowAction,
+};
+use crate::mouse::{self, MouseButton};
+use serde::Serialize;
 use std::process::Command;
 
 /// Result of executing a system command
@@ -14,6 +25,39 @@ use std::process::Command;
 pub struct CommandResult {
     pub success: bool,
     pub message: String,
+    /// Exit code of the process, when the command ran one (for Shell commands)
+    pub exit_code: Option<i32>,
+    /// Per-step outcomes (for Macro commands)
+    pub steps: Vec<StepOutcome>,
+    /// Output volume after the command, when it is known (for Volume commands)
+    pub volume: Option<VolumeState>,
+}
+
+/// Outcome of a single macro step
+#[derive(Debug, Clone, Serialize)]
+pub struct StepOutcome {
+    /// Position of the step in the macro, starting at 0
+    pub index: usize,
+    pub step_type: StepType,
+    pub success: bool,
+    pub message: String,
+    /// Number of times the step was run
+    pub attempts: u32,
+    /// Enclosing branch for steps nested in an If step, e.g. "2.then" or "2.then.0.else"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
+
+impl CommandResult {
+    fn with_exit_code(mut self, exit_code: Option<i32>) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
+
+    fn with_volume(mut self, volume: VolumeState) -> Self {
+        self.volume = Some(volume);
+        self
+    }
 }
 
 impl CommandResult {
@@ -21,6 +65,9 @@ impl CommandResult {
         Self {
             success: true,
             message: message.into(),
+            exit_code: None,
+            steps: Vec::new(),
+            volume: None,
         }
     }
 
@@ -28,6 +75,9 @@ impl CommandResult {
         Self {
             success: false,
             message: message.into(),
+            exit_code: None,
+            steps: Vec::new(),
+            volume: None,
         }
     }
 }
@@ -36,10 +86,34 @@ impl CommandResult {
 pub fn execute_command(config: &CommandConfig) -> CommandResult {
     match config.command_type {
         CommandType::Shell => execute_shell(&config.command),
-        CommandType::Volume => execute_volume(&config.volume_direction, config.volume_step),
+        CommandType::Volume => execute_volume(config),
         CommandType::OpenDirectory => execute_open_directory(&config.directory_path),
         CommandType::FocusApp => execute_focus_app(&config.app_name),
         CommandType::Keybind => execute_keybind(&config.keybind),
+        CommandType::Toggle => {
+            CommandResult::err("Toggle commands must be executed with their current state")
+        }
+        CommandType::Macro => crate::macros::execute_macro(&config.steps),
+        CommandType::TypeText => execute_type_text(&config.text, config.key_delay_ms),
+        CommandType::Mouse => execute_mouse(&config.mouse),
+        CommandType::Media => execute_media(config),
+        CommandType::Clipboard => execute_clipboard(&config.text),
+        CommandType::Window => execute_window(&config.window),
+    }
+}
+
+/// Execute the action of a toggle that moves it away from `active`
+///
+/// Runs the "off" action when the toggle is currently on, the "on" action otherwise.
+pub fn execute_toggle(config: &CommandConfig, active: bool) -> CommandResult {
+    let action = if active { &config.off_action } else { &config.on_action };
+    match action {
+        Some(action) if action.command_type != CommandType::Toggle => execute_command(action),
+        Some(_) => CommandResult::err("Toggle actions cannot be toggles"),
+        None => CommandResult::err(format!(
+            "No {} action specified",
+            if active { "off" } else { "on" }
+        )),
     }
 }
 
@@ -50,144 +124,81 @@ fn execute_shell(command: &Option<String>) -> CommandResult {
         _ => return CommandResult::err("No command specified"),
     };
 
-    let output = if cfg!(target_os = "windows") {
+    // With /S and the command wrapped in quotes, cmd.exe runs it exactly as written.
+    // Passed as a normal argument it would get `\"` escapes, which cmd.exe doesn't know.
+    #[cfg(target_os = "windows")]
+    let output = {
+        use std::os::windows::process::CommandExt;
         Command::new("cmd")
-            .args(["/C", command_str])
-            .output()
-    } else {
-        Command::new("sh")
-            .args(["-c", command_str])
+            .args(["/S", "/C"])
+            .raw_arg(format!("\"{}\"", command_str))
             .output()
     };
 
+    #[cfg(not(target_os = "windows"))]
+    let output = Command::new("sh")
+        .args(["-c", command_str])
+        .output();
+
     match output {
         Ok(output) => {
-            if output.status.success() {
+            let result = if output.status.success() {
                 CommandResult::ok(String::from_utf8_lossy(&output.stdout).to_string())
             } else {
                 CommandResult::err(String::from_utf8_lossy(&output.stderr).to_string())
-            }
+            };
+            result.with_exit_code(output.status.code())
         }
         Err(e) => CommandResult::err(format!("Failed to execute command: {}", e)),
     }
 }
 
 /// Control system volume
-fn execute_volume(direction: &Option<VolumeDirection>, step: Option<u8>) -> CommandResult {
-    let direction = match direction {
-        Some(dir) => dir,
+fn execute_volume(config: &CommandConfig) -> CommandResult {
+    let direction = match config.volume_direction {
+        Some(ref dir) => dir,
         None => return CommandResult::err("Volume direction not specified"),
     };
-    
-    let step = step.unwrap_or(5);
-
-    #[cfg(target_os = "macos")]
-    {
-        let script = match direction {
-            VolumeDirection::Up => {
-                format!(
-                    r#"set currentVolume to output volume of (get volume settings)
-                    set volume output volume (currentVolume + {})"#,
-                    step
-                )
-            }
-            VolumeDirection::Down => {
-                format!(
-                    r#"set currentVolume to output volume of (get volume settings)
-                    set volume output volume (currentVolume - {})"#,
-                    step
-                )
-            }
-            VolumeDirection::Mute => {
-                r#"set isMuted to output muted of (get volume settings)
-                set volume output muted (not isMuted)"#.to_string()
-            }
-        };
 
-        let output = Command::new("osascript")
-            .args(["-e", &script])
-            .output();
+    let step = config.volume_step.unwrap_or(5) as i16;
+    let change = match direction {
+        VolumeDirection::Up => VolumeChange::Step {
+            delta: step,
+            max: config.volume_max.unwrap_or(DEFAULT_MAX_VOLUME),
+        },
+        VolumeDirection::Down => VolumeChange::Step { delta: -step, max: 0 },
+        VolumeDirection::Mute => VolumeChange::Mute(None),
+        VolumeDirection::MuteOn => VolumeChange::Mute(Some(true)),
+        VolumeDirection::MuteOff => VolumeChange::Mute(Some(false)),
+        VolumeDirection::Set => match config.volume_level {
+            Some(level) => VolumeChange::Set(level),
+            None => return CommandResult::err("Volume level not specified"),
+        },
+    };
 
-        match output {
-            Ok(output) if output.status.success() => {
-                CommandResult::ok(format!("Volume {:?}", direction))
-            }
-            Ok(output) => {
-                CommandResult::err(String::from_utf8_lossy(&output.stderr).to_string())
-            }
-            Err(e) => CommandResult::err(format!("Failed to control volume: {}", e)),
-        }
+    match audio::change_volume(change) {
+        Ok(state) => CommandResult::ok(volume_message(&state)).with_volume(state),
+        Err(e) => CommandResult::err(format!("Failed to control volume: {}", e)),
     }
+}
 
-    #[cfg(target_os = "windows")]
-    {
-        let script = match direction {
-            VolumeDirection::Up => {
-                format!(
-                    r#"$wshell = New-Object -ComObject wscript.shell; for($i=0; $i -lt {}; $i++) {{ $wshell.SendKeys([char]175) }}"#,
-                    step / 2 // Each key press is ~2% volume
-                )
-            }
-            VolumeDirection::Down => {
-                format!(
-                    r#"$wshell = New-Object -ComObject wscript.shell; for($i=0; $i -lt {}; $i++) {{ $wshell.SendKeys([char]174) }}"#,
-                    step / 2
-                )
-            }
-            VolumeDirection::Mute => {
-                r#"$wshell = New-Object -ComObject wscript.shell; $wshell.SendKeys([char]173)"#.to_string()
-            }
-        };
-
-        let output = Command::new("powershell")
-            .args(["-Command", &script])
-            .output();
-
-        match output {
-            Ok(output) if output.status.success() => {
-                CommandResult::ok(format!("Volume {:?}", direction))
-            }
-            Ok(output) => {
-                CommandResult::err(String::from_utf8_lossy(&output.stderr).to_string())
-            }
-            Err(e) => CommandResult::err(format!("Failed to control volume: {}", e)),
-        }
+/// Describe a volume state, e.g. "Volume 40%" or "Volume 40% (muted)"
+fn volume_message(state: &VolumeState) -> String {
+    if state.muted {
+        format!("Volume {}% (muted)", state.level)
+    } else {
+        format!("Volume {}%", state.level)
     }
+}
 
-    #[cfg(target_os = "linux")]
-    {
-        let args = match direction {
-            VolumeDirection::Up => format!("{}%+", step),
-            VolumeDirection::Down => format!("{}%-", step),
-            VolumeDirection::Mute => "toggle".to_string(),
-        };
-
-        // Try pactl first (PulseAudio/PipeWire), then amixer
-        let output = Command::new("pactl")
-            .args(["set-sink-volume", "@DEFAULT_SINK@", &args])
-            .output()
-            .or_else(|_| {
-                Command::new("amixer")
-                    .args(["set", "Master", &format!("{}%{}", step, 
-                        if matches!(direction, VolumeDirection::Up) { "+" } else { "-" })])
-                    .output()
-            });
-
-        match output {
-            Ok(output) if output.status.success() => {
-                CommandResult::ok(format!("Volume {:?}", direction))
-            }
-            Ok(output) => {
-                CommandResult::err(String::from_utf8_lossy(&output.stderr).to_string())
-            }
-            Err(e) => CommandResult::err(format!("Failed to control volume: {}", e)),
+/// Expand a leading `~` to the home directory
+pub fn expand_home(path: &str) -> String {
+    if path.starts_with('~') {
+        if let Some(home) = dirs::home_dir() {
+            return path.replacen('~', &home.to_string_lossy(), 1);
         }
     }
-
-    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
-    {
-        CommandResult::err("Volume control not supported on this platform")
-    }
+    path.to_string()
 }
 
 /// Open a directory in the file manager
@@ -197,16 +208,7 @@ fn execute_open_directory(path: &Option<String>) -> CommandResult {
         _ => return CommandResult::err("No directory path specified"),
     };
 
-    // Expand ~ to home directory
-    let expanded_path = if path.starts_with('~') {
-        if let Some(home) = dirs::home_dir() {
-            path.replacen('~', &home.to_string_lossy(), 1)
-        } else {
-            path.clone()
-        }
-    } else {
-        path.clone()
-    };
+    let expanded_path = expand_home(path);
 
     #[cfg(target_os = "macos")]
     let output = Command::new("open").arg(&expanded_path).output();
@@ -292,24 +294,31 @@ fn execute_focus_app(app_name: &Option<String>) -> CommandResult {
 
     #[cfg(target_os = "linux")]
     {
-        // Try wmctrl first, then xdotool
-        let output = Command::new("wmctrl")
-            .args(["-a", app_name])
-            .output()
-            .or_else(|_| {
-                Command::new("xdotool")
-                    .args(["search", "--name", app_name, "windowactivate"])
-                    .output()
-            });
+        // Native EWMH first, then wmctrl, then xdotool
+        let x11 = || {
+            crate::x11::with_fallback(
+                || crate::x11::activate_by_title(app_name).map(|_| ()),
+                || {
+                    mouse::run_tool("wmctrl", &["-a", app_name]).map(|_| ()).or_else(|wmctrl_err| {
+                        mouse::run_tool("xdotool", &["search", "--name", app_name, "windowactivate"])
+                            .map(|_| ())
+                            .map_err(|e| format!("{}; {}", wmctrl_err, e))
+                    })
+                },
+            )
+        };
+        // On Wayland, X11 only reaches XWayland windows, so ask the compositor first
+        let result = if is_wayland_session() {
+            crate::wayland::activate_by_title(app_name)
+                .map(|_| ())
+                .or_else(|wayland_err| x11().map_err(|e| format!("{}; {}", wayland_err, e)))
+        } else {
+            x11()
+        };
 
-        match output {
-            Ok(output) if output.status.success() => {
-                CommandResult::ok(format!("Focused: {}", app_name))
-            }
-            Ok(output) => {
-                CommandResult::err(String::from_utf8_lossy(&output.stderr).to_string())
-            }
-            Err(e) => CommandResult::err(format!("Failed to focus app: {}", e)),
+        match result {
+            Ok(()) => CommandResult::ok(format!("Focused: {}", app_name)),
+            Err(e) => CommandResult::err(format!("Failed to focus {}: {}", app_name, e)),
         }
     }
 
@@ -372,20 +381,21 @@ fn execute_keybind(keybind: &Option<String>) -> CommandResult {
 
     #[cfg(target_os = "linux")]
     {
-        // Use xdotool
-        let xdotool_keys = convert_keybind_to_xdotool(keybind);
-        
-        let output = Command::new("xdotool")
-            .args(["key", &xdotool_keys])
-            .output();
+        let result = if is_wayland_session() {
+            send_keys_wayland(keybind)
+        } else {
+            // XTest first; xdotool knows every keysym name and remaps missing keys
+            crate::x11::with_fallback(
+                || crate::x11::send_keys(keybind),
+                || {
+                    let xdotool_keys = convert_keybind_to_xdotool(keybind);
+                    mouse::run_tool("xdotool", &["key", &xdotool_keys]).map(|_| ())
+                },
+            )
+        };
 
-        match output {
-            Ok(output) if output.status.success() => {
-                CommandResult::ok(format!("Sent: {}", keybind))
-            }
-            Ok(output) => {
-                CommandResult::err(String::from_utf8_lossy(&output.stderr).to_string())
-            }
+        match result {
+            Ok(()) => CommandResult::ok(format!("Sent: {}", keybind)),
             Err(e) => CommandResult::err(format!("Failed to send keybind: {}", e)),
         }
     }
@@ -542,6 +552,375 @@ fn convert_keybind_to_xdotool(keybind: &str) -> String {
     result.join("+")
 }
 
+/// Send a keybind on Wayland: wtype, then ydotool, then our own uinput keyboard
+///
+/// wtype needs the virtual keyboard protocol (wlroots compositors), ydotool
+/// needs its daemon running, and uinput needs access to `/dev/uinput`.
+#[cfg(target_os = "linux")]
+fn send_keys_wayland(keybind: &str) -> Result<(), String> {
+    const MODIFIERS: [(&str, &str); 4] = [("ctrl", "ctrl"), ("shift", "shift"), ("alt", "alt"), ("super", "logo")];
+
+    let wtype = || {
+        let keys = convert_keybind_to_xdotool(keybind);
+        let (modifiers, keys): (Vec<&str>, Vec<&str>) = keys
+            .split('+')
+            .partition(|k| MODIFIERS.iter().any(|(name, _)| name == k));
+        let modifiers: Vec<&str> = modifiers
+            .iter()
+            .filter_map(|m| MODIFIERS.iter().find(|(name, _)| name == m).map(|(_, w)| *w))
+            .collect();
+
+        let mut args = Vec::new();
+        for m in &modifiers {
+            args.extend(["-M", m]);
+        }
+        for k in &keys {
+            args.extend(["-k", k]);
+        }
+        for m in modifiers.iter().rev() {
+            args.extend(["-m", m]);
+        }
+        mouse::run_tool("wtype", &args).map(|_| ())
+    };
+
+    let ydotool = || {
+        // "<code>:1" presses and "<code>:0" releases an evdev key
+        let codes = crate::uinput::keycodes(keybind).map_err(|e| format!("ydotool: {}", e))?;
+        let mut args = vec!["key".to_string()];
+        args.extend(codes.iter().map(|c| format!("{}:1", c)));
+        args.extend(codes.iter().rev().map(|c| format!("{}:0", c)));
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        mouse::run_tool("ydotool", &args).map(|_| ())
+    };
+
+    wtype()
+        .or_else(|wtype_err| ydotool().map_err(|e| format!("{}; {}", wtype_err, e)))
+        .or_else(|errors| {
+            crate::uinput::send_keys(keybind).map_err(|e| format!("{}; uinput: {}", errors, e))
+        })
+}
+
+/// Pause between typed keys when the command doesn't set one
+const DEFAULT_KEY_DELAY_MS: u64 = 12;
+
+/// Type a piece of text into the focused window
+fn execute_type_text(text: &Option<String>, key_delay_ms: Option<u64>) -> CommandResult {
+    let text = match text {
+        Some(t) if !t.is_empty() => t.replace("\r\n", "\n"),
+        _ => return CommandResult::err("No text specified"),
+    };
+    let delay = key_delay_ms.unwrap_or(DEFAULT_KEY_DELAY_MS);
+    let chars = text.chars().count();
+
+    #[cfg(target_os = "macos")]
+    let output = Command::new("osascript")
+        .args(["-e", &text_to_applescript(&text, delay)])
+        .output();
+
+    #[cfg(target_os = "windows")]
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-Command", &sendkeys_script(delay)])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            use std::io::Write;
+            // Closing stdin after writing ends the script's input
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(text_to_sendkeys(&text).as_bytes())?;
+            }
+            child.wait_with_output()
+        });
+
+    #[cfg(target_os = "linux")]
+    {
+        let result = if is_wayland_session() {
+            type_text_wayland(&text, delay)
+        } else {
+            // xdotool types '\n' as Return and '\t' as Tab, and remaps keys for other characters
+            mouse::run_tool("xdotool", &["type", "--delay", &delay.to_string(), "--", &text]).map(|_| ())
+        };
+
+        match result {
+            Ok(()) => CommandResult::ok(format!("Typed {} characters", chars)),
+            Err(e) => CommandResult::err(format!("Failed to type text: {}", e)),
+        }
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    {
+        match output {
+            Ok(output) if output.status.success() => {
+                CommandResult::ok(format!("Typed {} characters", chars))
+            }
+            Ok(output) => {
+                CommandResult::err(String::from_utf8_lossy(&output.stderr).to_string())
+            }
+            Err(e) => CommandResult::err(format!("Failed to type text: {}", e)),
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        let _ = (delay, chars);
+        CommandResult::err("Typing text not supported on this platform")
+    }
+}
+
+/// Control a media player
+fn execute_media(config: &CommandConfig) -> CommandResult {
+    let action = match config.media_action {
+        Some(ref action) => action,
+        None => return CommandResult::err("No media action specified"),
+    };
+
+    match crate::media::control(action, config.media_player.as_deref(), config.seek_offset_secs) {
+        Ok(message) => CommandResult::ok(message),
+        Err(e) => CommandResult::err(e),
+    }
+}
+
+/// Act on an application window
+fn execute_window(action: &Option<WindowAction>) -> CommandResult {
+    let action = match action {
+        Some(a) => a,
+        None => return CommandResult::err("No window action specified"),
+    };
+
+    match crate::window_manager::perform(action) {
+        Ok(message) => CommandResult::ok(message),
+        Err(e) => CommandResult::err(e),
+    }
+}
+
+/// Put templated text on the clipboard
+fn execute_clipboard(text: &Option<String>) -> CommandResult {
+    let template = match text {
+        Some(t) if !t.is_empty() => t,
+        _ => return CommandResult::err("No text specified"),
+    };
+
+    let vars = crate::clipboard::template_variables(template);
+    let text = crate::macros::substitute(template, &vars, str::to_string);
+    let result = crate::clipboard::set_text(&text).map(|_| text);
+    match result {
+        Ok(text) => CommandResult::ok(format!("Copied {} characters to the clipboard", text.chars().count())),
+        Err(e) => CommandResult::err(e),
+    }
+}
+
+/// Scroll steps used when a mouse command doesn't set an amount
+const DEFAULT_SCROLL_AMOUNT: u32 = 3;
+
+/// Move the pointer and click or scroll
+fn execute_mouse(action: &Option<MouseAction>) -> CommandResult {
+    let action = match action {
+        Some(a) => a,
+        None => return CommandResult::err("No mouse action specified"),
+    };
+    if let Err(e) = action.validate() {
+        return CommandResult::err(e);
+    }
+
+    if let Err(e) = move_pointer(action) {
+        return CommandResult::err(e);
+    }
+
+    let (result, done) = match action.action {
+        MouseActionKind::Move => (Ok(()), "Moved pointer"),
+        MouseActionKind::Click => (mouse::click(MouseButton::Left, 1), "Clicked"),
+        MouseActionKind::DoubleClick => (mouse::click(MouseButton::Left, 2), "Double-clicked"),
+        MouseActionKind::RightClick => (mouse::click(MouseButton::Right, 1), "Right-clicked"),
+        MouseActionKind::MiddleClick => (mouse::click(MouseButton::Middle, 1), "Middle-clicked"),
+        MouseActionKind::Scroll => match action.scroll_direction {
+            Some(ref direction) => (
+                mouse::scroll(direction, action.scroll_amount.unwrap_or(DEFAULT_SCROLL_AMOUNT)),
+                "Scrolled",
+            ),
+            None => (Err("Scroll needs a direction".to_string()), ""),
+        },
+    };
+
+    match result {
+        Ok(()) => CommandResult::ok(done),
+        Err(e) => CommandResult::err(e),
+    }
+}
+
+/// Move the pointer to the target of a mouse action, if it has one
+///
+/// Targets are checked against the monitor layout so a typo can't send the
+/// pointer off screen.
+fn move_pointer(action: &MouseAction) -> Result<(), String> {
+    let target = match (action.x, action.y, &action.corner) {
+        (_, _, Some(corner)) => {
+            let monitors = mouse::get_monitors()?;
+            let (x, y) = mouse::find_monitor(&monitors, action.monitor.as_deref())?.corner(corner);
+            return mouse::move_to(x, y);
+        }
+        (Some(x), Some(y), None) if action.relative => match mouse::pointer_position() {
+            Ok((px, py)) => (px.saturating_add(x), py.saturating_add(y)),
+            // Without a readable position (Wayland) the move can't be checked
+            Err(_) => return mouse::move_by(x, y),
+        },
+        (Some(x), Some(y), None) => (x, y),
+        _ => return Ok(()),
+    };
+
+    let monitors = mouse::get_monitors()
+        .map_err(|e| format!("Couldn't read screen geometry to check the position: {}", e))?;
+    if !monitors.iter().any(|m| m.contains(target.0, target.1)) {
+        return Err(format!(
+            "Position {},{} is outside every monitor ({})",
+            target.0,
+            target.1,
+            monitors
+                .iter()
+                .map(|m| format!("{} {}x{}+{}+{}", m.name, m.width, m.height, m.x, m.y))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    mouse::move_to(target.0, target.1)
+}
+
+/// Type text on Wayland with wtype, or ydotool where the compositor lacks the
+/// virtual keyboard protocol wtype needs
+#[cfg(target_os = "linux")]
+fn type_text_wayland(text: &str, delay: u64) -> Result<(), String> {
+    // wtype maps '\n' to Linefeed, which most apps ignore, so press Return explicitly
+    let mut args = vec!["-d".to_string(), delay.to_string()];
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            args.extend(["-k".to_string(), "Return".to_string()]);
+        }
+        // A leading '-' would be read as an option, so send it as a key instead
+        let mut rest = line;
+        while let Some(after) = rest.strip_prefix('-') {
+            args.extend(["-k".to_string(), "minus".to_string()]);
+            rest = after;
+        }
+        if !rest.is_empty() {
+            args.push(rest.to_string());
+        }
+    }
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    mouse::run_tool("wtype", &args).map(|_| ()).or_else(|wtype_err| {
+        mouse::run_tool("ydotool", &["type", "--key-delay", &delay.to_string(), "--", text])
+            .map(|_| ())
+            .map_err(|e| format!("{}; {}", wtype_err, e))
+    })
+}
+
+/// Whether the desktop session runs on Wayland rather than X11
+#[cfg(target_os = "linux")]
+pub fn is_wayland_session() -> bool {
+    std::env::var("WAYLAND_DISPLAY").map(|v| !v.is_empty()).unwrap_or(false)
+        || std::env::var("XDG_SESSION_TYPE").map(|v| v == "wayland").unwrap_or(false)
+}
+
+/// Build an AppleScript that types `text`, pressing Return and Tab by key code
+///
+/// `keystroke` goes through the keyboard layout and mistypes characters that
+/// aren't on it, so anything outside ASCII is pasted through the clipboard
+/// instead, restoring its previous text afterwards.
+#[cfg(target_os = "macos")]
+fn text_to_applescript(text: &str, delay_ms: u64) -> String {
+    enum Part {
+        Type(String),
+        Paste(String),
+        KeyCode(u8),
+    }
+
+    let mut parts: Vec<Part> = Vec::new();
+    for c in text.chars() {
+        let part = match c {
+            '\n' => Part::KeyCode(36),
+            '\t' => Part::KeyCode(48),
+            c if c.is_ascii() => Part::Type(c.to_string()),
+            c => Part::Paste(c.to_string()),
+        };
+        // With a delay, every typed character gets its own keystroke
+        let merged = match (parts.last_mut(), &part) {
+            (Some(Part::Type(run)), Part::Type(s)) if delay_ms == 0 => {
+                run.push_str(s);
+                true
+            }
+            (Some(Part::Paste(run)), Part::Paste(s)) => {
+                run.push_str(s);
+                true
+            }
+            _ => false,
+        };
+        if !merged {
+            parts.push(part);
+        }
+    }
+
+    let pause = if delay_ms > 0 {
+        format!("\n  delay {}", delay_ms as f64 / 1000.0)
+    } else {
+        String::new()
+    };
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let pastes = parts.iter().any(|part| matches!(part, Part::Paste(_)));
+
+    let mut lines = vec![r#"tell application "System Events""#.to_string()];
+    if pastes {
+        lines.push("  try\n    set savedClipboard to the clipboard\n  end try".to_string());
+    }
+    for part in &parts {
+        lines.push(match part {
+            Part::Type(s) => format!("  keystroke {}{}", quote(s), pause),
+            Part::KeyCode(code) => format!("  key code {}{}", code, pause),
+            // The short wait lets the app read the clipboard before it changes again
+            Part::Paste(s) => format!(
+                "  set the clipboard to {}\n  keystroke \"v\" using command down\n  delay 0.1{}",
+                quote(s),
+                pause
+            ),
+        });
+    }
+    if pastes {
+        lines.push("  try\n    set the clipboard to savedClipboard\n  end try".to_string());
+    }
+
+    lines.push("end tell".to_string());
+    lines.join("\n")
+}
+
+/// PowerShell script that sends the keys on its standard input, one per line,
+/// through SendKeys
+///
+/// Reading the keys from stdin keeps long texts clear of the command line limit.
+#[cfg(target_os = "windows")]
+fn sendkeys_script(delay_ms: u64) -> String {
+    format!(
+        "Add-Type -AssemblyName System.Windows.Forms; [Console]::InputEncoding = [System.Text.Encoding]::UTF8; \
+         foreach ($k in [Console]::In.ReadToEnd() -split \"`n\") {{ if ($k) {{ [System.Windows.Forms.SendKeys]::SendWait($k); Start-Sleep -Milliseconds {} }} }}",
+        delay_ms
+    )
+}
+
+/// SendKeys codes typing `text`, one key per line
+#[cfg(target_os = "windows")]
+fn text_to_sendkeys(text: &str) -> String {
+    let keys: Vec<String> = text
+        .chars()
+        .map(|c| match c {
+            '\n' => "{ENTER}".to_string(),
+            '\t' => "{TAB}".to_string(),
+            // Characters with a meaning in SendKeys are sent literally by bracing them
+            '+' | '^' | '%' | '~' | '(' | ')' | '{' | '}' | '[' | ']' => format!("{{{}}}", c),
+            c => c.to_string(),
+        })
+        .collect();
+    keys.join("\n")
+}
+
 /// Check if accessibility permissions are granted (required for keybinds on macOS)
 pub fn check_accessibility_permission() -> bool {
     #[cfg(target_os = "macos")]
@@ -632,19 +1011,16 @@ pub fn get_running_applications() -> Vec<String> {
 
     #[cfg(target_os = "linux")]
     {
-        // Try wmctrl first
-        if let Ok(output) = Command::new("wmctrl").args(["-l"]).output() {
-            if output.status.success() {
-                let output_str = String::from_utf8_lossy(&output.stdout);
-                apps = output_str
-                    .lines()
-                    .filter_map(|line| {
-                        let parts: Vec<&str> = line.splitn(4, ' ').collect();
-                        parts.get(3).map(|s| s.to_string())
-                    })
+        // Window titles, natively or through wmctrl
+        match crate::window_manager::list_windows() {
+            Ok(windows) => {
+                apps = windows
+                    .into_iter()
+                    .map(|w| w.title)
                     .filter(|s| !s.is_empty())
                     .collect();
             }
+            Err(e) => eprintln!("Failed to list windows: {}", e),
         }
     }
 
@@ -653,3 +1029,164 @@ pub fn get_running_applications() -> Vec<String> {
     apps
 }
 
+
+/// The application owning the foreground window
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FocusedApp {
+    /// Process or application name, e.g. "Figma" or "gnome-terminal-server"
+    pub app_name: String,
+    /// Title of the focused window
+    pub title: String,
+}
+
+/// Get the application owning the foreground window
+pub fn get_focused_application() -> Option<FocusedApp> {
+    #[cfg(target_os = "macos")]
+    {
+        let script = r#"tell application "System Events"
+            set frontApp to first application process whose frontmost is true
+            set winTitle to ""
+            try
+                set winTitle to name of front window of frontApp
+            end try
+            return (name of frontApp) & linefeed & winTitle
+        end tell"#;
+
+        let output = Command::new("osascript").args(["-e", script]).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let output_str = String::from_utf8_lossy(&output.stdout);
+        let mut lines = output_str.trim_end().splitn(2, '\n');
+        Some(FocusedApp {
+            app_name: lines.next()?.to_string(),
+            title: lines.next().unwrap_or_default().to_string(),
+        })
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        crate::windows_focus::get_foreground_window()
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        // Goes through the compositor on Wayland, EWMH on X11
+        let window = crate::window_manager::active_window().ok()?;
+        let app_name = window
+            .pid
+            .and_then(|pid| std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok())
+            .map(|comm| comm.trim().to_string())
+            .or(window.class)
+            .unwrap_or_default();
+
+        Some(FocusedApp { app_name, title: window.title })
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+    {
+        None
+    }
+}
+
+/// Evaluate a command's state query
+pub fn query_state(query: &StateQuery) -> Result<CommandState, String> {
+    match query.probe {
+        StateProbe::Shell => {
+            let command = match query.command {
+                Some(ref cmd) if !cmd.is_empty() => cmd,
+                _ => return Err("No state command specified".to_string()),
+            };
+
+            let output = if cfg!(target_os = "windows") {
+                Command::new("cmd").args(["/C", command]).output()
+            } else {
+                Command::new("sh").args(["-c", command]).output()
+            }
+            .map_err(|e| format!("Failed to run state command: {}", e))?;
+
+            let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            let value = if stdout.is_empty() { None } else { Some(stdout) };
+            Ok(query.to_state(output.status.success(), value))
+        }
+        StateProbe::Muted => {
+            let muted = get_mute_state()?;
+            Ok(query.to_state(muted, None))
+        }
+        StateProbe::AppRunning => {
+            let app_name = match query.app_name {
+                Some(ref name) if !name.is_empty() => name,
+                _ => return Err("No application name specified".to_string()),
+            };
+            let running = is_app_running(app_name)?;
+            Ok(query.to_state(running, None))
+        }
+        StateProbe::VolumeLevel => {
+            let level = get_volume_level()?;
+            Ok(query.to_state(level > 0, Some(format!("{}%", level))))
+        }
+    }
+}
+
+/// Check whether the default output device is muted
+pub fn get_mute_state() -> Result<bool, String> {
+    audio::get_volume().map(|state| state.muted)
+}
+
+/// Read the current output volume in percent
+pub fn get_volume_level() -> Result<u8, String> {
+    audio::get_volume().map(|state| state.level)
+}
+
+/// Check whether a process with the given name is running
+pub fn is_app_running(app_name: &str) -> Result<bool, String> {
+    #[cfg(target_os = "windows")]
+    {
+        let script = format!(
+            "if (Get-Process -Name '{}' -ErrorAction SilentlyContinue) {{ 'yes' }} else {{ 'no' }}",
+            app_name.replace('\'', "''")
+        );
+        let output = Command::new("powershell")
+            .args(["-Command", &script])
+            .output()
+            .map_err(|e| format!("Failed to query processes: {}", e))?;
+        Ok(String::from_utf8_lossy(&output.stdout).trim() == "yes")
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        // Match the whole process name rather than command lines, which would also
+        // match shells whose script merely mentions the app
+        let mut name = app_name;
+        if cfg!(target_os = "linux") {
+            // The kernel keeps only the first 15 bytes of a process name
+            let mut end = name.len().min(15);
+            while !name.is_char_boundary(end) {
+                end -= 1;
+            }
+            name = &name[..end];
+        }
+        let pattern: String = name
+            .chars()
+            .flat_map(|c| {
+                // Escaped so pgrep's extended regex takes the name literally
+                let special = matches!(
+                    c,
+                    '.' | '[' | ']' | '{' | '}' | '(' | ')' | '\\' | '*' | '+' | '?' | '^' | '$' | '|'
+                );
+                special.then_some('\\').into_iter().chain([c])
+            })
+            .collect();
+
+        // pgrep exits with 1 when nothing matches, anything above means an error
+        let output = Command::new("pgrep")
+            .args(["-i", "-x", &pattern])
+            .output()
+            .map_err(|e| format!("Failed to query processes: {}", e))?;
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(1) => Ok(false),
+            _ => Err(String::from_utf8_lossy(&output.stderr).to_string()),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Key presses through a virtual uinput keyboard
+//!
+//! Works under any compositor because the kernel delivers the events like a
+//! real keyboard's, but needs write access to `/dev/uinput` (usually through
+//! the `input` group or a udev rule). Keys are evdev codes, so they follow the
+//! keyboard layout of the session like physical keys would. The same codes
+//! are used for `ydotool key`.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::Duration;
+
+// ioctl requests from linux/uinput.h
+const UI_SET_EVBIT: libc::c_ulong = 0x4004_5564;
+const UI_SET_KEYBIT: libc::c_ulong = 0x4004_5565;
+const UI_DEV_SETUP: libc::c_ulong = 0x405c_5503;
+const UI_DEV_CREATE: libc::c_ulong = 0x5501;
+const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const SYN_REPORT: u16 = 0;
+const BUS_VIRTUAL: u16 = 0x06;
+
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_LEFTALT: u16 = 56;
+const KEY_LEFTMETA: u16 = 125;
+
+/// Time for the compositor to pick up a new device before it gets events
+const DEVICE_SETTLE: Duration = Duration::from_millis(200);
+
+/// Evdev key code for a key name as used in keybinds
+pub fn keycode(name: &str) -> Option<u16> {
+    const LETTERS: [u16; 26] = [
+        30, 48, 46, 32, 18, 33, 34, 35, 23, 36, 37, 38, 50, 49, 24, 25, 16, 19, 31, 20, 22, 47, 17, 45, 21, 44,
+    ];
+
+    let lower = name.to_lowercase();
+    let code = match lower.as_str() {
+        "ctrl" | "control" => KEY_LEFTCTRL,
+        "shift" => KEY_LEFTSHIFT,
+        "alt" => KEY_LEFTALT,
+        "cmd" | "command" | "super" | "win" => KEY_LEFTMETA,
+        "escape" | "esc" => 1,
+        "minus" | "-" => 12,
+        "equal" | "=" => 13,
+        "backspace" => 14,
+        "tab" => 15,
+        "[" => 26,
+        "]" => 27,
+        "enter" | "return" => 28,
+        ";" => 39,
+        "'" => 40,
+        "`" => 41,
+        "\\" => 43,
+        "," => 51,
+        "." => 52,
+        "/" => 53,
+        "space" | " " => 57,
+        "capslock" => 58,
+        "print" => 99,
+        "home" => 102,
+        "up" => 103,
+        "pageup" => 104,
+        "left" => 105,
+        "right" => 106,
+        "end" => 107,
+        "down" => 108,
+        "pagedown" => 109,
+        "insert" => 110,
+        "delete" => 111,
+        "menu" => 127,
+        "xf86audiomute" => 113,
+        "xf86audiolowervolume" => 114,
+        "xf86audioraisevolume" => 115,
+        "xf86audionext" => 163,
+        "xf86audioplay" => 164,
+        "xf86audioprev" => 165,
+        "xf86audiostop" => 166,
+        _ => {
+            if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u16>().ok()) {
+                return match n {
+                    1..=10 => Some(58 + n),
+                    11 | 12 => Some(76 + n),
+                    13..=24 => Some(170 + n),
+                    _ => None,
+                };
+            }
+            let mut chars = lower.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            return match c {
+                'a'..='z' => Some(LETTERS[c as usize - 'a' as usize]),
+                '1'..='9' => Some(c as u16 - '1' as u16 + 2),
+                '0' => Some(11),
+                _ => None,
+            };
+        }
+    };
+    Some(code)
+}
+
+/// Key codes of a keybind like "ctrl+shift+t", in press order
+pub fn keycodes(keybind: &str) -> Result<Vec<u16>, String> {
+    keybind
+        .split('+')
+        .map(str::trim)
+        .map(|part| keycode(part).ok_or_else(|| format!("Unknown key '{}'", part)))
+        .collect()
+}
+
+/// A virtual keyboard that exists until dropped
+struct Keyboard(File);
+
+impl Keyboard {
+    fn create(keys: &[u16]) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/uinput")
+            .map_err(|e| format!("Can't open /dev/uinput: {}", e))?;
+        let fd = file.as_raw_fd();
+
+        let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+        setup.id.bustype = BUS_VIRTUAL;
+        for (dst, src) in setup.name.iter_mut().zip(b"deck virtual keyboard") {
+            *dst = *src as libc::c_char;
+        }
+
+        let ioctl = |request: libc::c_ulong, arg: libc::c_ulong| {
+            // SAFETY: fd is an open uinput device and every request gets the argument type it expects
+            if unsafe { libc::ioctl(fd, request as _, arg) } < 0 {
+                Err(format!("uinput setup failed: {}", std::io::Error::last_os_error()))
+            } else {
+                Ok(())
+            }
+        };
+        ioctl(UI_SET_EVBIT, EV_KEY as libc::c_ulong)?;
+        for &key in keys {
+            ioctl(UI_SET_KEYBIT, key as libc::c_ulong)?;
+        }
+        ioctl(UI_DEV_SETUP, &setup as *const _ as libc::c_ulong)?;
+        ioctl(UI_DEV_CREATE, 0)?;
+
+        std::thread::sleep(DEVICE_SETTLE);
+        Ok(Self(file))
+    }
+
+    fn emit(&mut self, kind: u16, code: u16, value: i32) -> Result<(), String> {
+        let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+        event.type_ = kind;
+        event.code = code;
+        event.value = value;
+        // SAFETY: input_event is plain old data
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const _ as *const u8,
+                std::mem::size_of::<libc::input_event>(),
+            )
+        };
+        self.0
+            .write_all(bytes)
+            .map_err(|e| format!("Failed to write key event: {}", e))
+    }
+
+    fn key(&mut self, code: u16, pressed: bool) -> Result<(), String> {
+        self.emit(EV_KEY, code, pressed as i32)?;
+        self.emit(EV_SYN, SYN_REPORT, 0)
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        // Let the last events through before the device disappears
+        std::thread::sleep(DEVICE_SETTLE);
+        unsafe {
+            libc::ioctl(self.0.as_raw_fd(), UI_DEV_DESTROY as _);
+        }
+    }
+}
+
+/// Press a key combination and release it in reverse order
+pub fn send_keys(keybind: &str) -> Result<(), String> {
+    let codes = keycodes(keybind)?;
+    let mut keyboard = Keyboard::create(&codes)?;
+    for &code in &codes {
+        keyboard.key(code, true)?;
+    }
+    for &code in codes.iter().rev() {
+        keyboard.key(code, false)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,285 @@
+//! Receiving files from the phone into configured directories
+//!
+//! Files may only land directly inside one of the upload targets from the
+//! settings. Client-supplied names are reduced to a plain file name, and an
+//! existing file is never overwritten: a numbered name is picked instead.
+
+use crate::events::{self, EventSender, ServerEvent};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Upload size limit when none is configured, in megabytes
+pub const DEFAULT_MAX_UPLOAD_MB: u64 = 100;
+
+/// Highest configurable upload size limit, in megabytes
+pub const MAX_UPLOAD_MB: u64 = 4096;
+
+/// Longest file name kept, in bytes
+const MAX_FILE_NAME_LEN: usize = 200;
+
+/// Bytes received between two progress events
+const PROGRESS_STEP: u64 = 256 * 1024;
+
+/// Numbered names tried before giving up on a free one
+const MAX_NAME_ATTEMPTS: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadStatus {
+    Uploading,
+    Done,
+    Failed,
+}
+
+/// A file written by an upload
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadedFile {
+    /// Name on disk, which may differ from the name sent by the phone
+    pub file_name: String,
+    pub size: u64,
+}
+
+/// Reduce a client-supplied name to a safe file name
+///
+/// Drops any directory part, control characters and characters Windows
+/// doesn't allow, and refuses names that would still point elsewhere.
+pub fn sanitize_file_name(name: &str) -> Result<String, String> {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+        .collect();
+    // Windows strips trailing dots and spaces, leading dots would hide the file
+    let cleaned = cleaned.trim_matches(|c: char| c == '.' || c.is_whitespace());
+
+    if cleaned.is_empty() {
+        return Err(format!("Invalid file name '{}'", name));
+    }
+
+    let stem = cleaned.split('.').next().unwrap_or_default().to_uppercase();
+    let reserved = matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || ((stem.starts_with("COM") || stem.starts_with("LPT"))
+            && stem.len() == 4
+            && stem.ends_with(|c: char| c.is_ascii_digit()));
+    let mut cleaned = if reserved {
+        format!("_{}", cleaned)
+    } else {
+        cleaned.to_string()
+    };
+
+    if cleaned.len() > MAX_FILE_NAME_LEN {
+        // Keep a short extension so the file still opens with the right app
+        let (stem, ext) = match split_extension(&cleaned) {
+            (stem, ext) if ext.len() <= 16 => (stem, ext),
+            _ => (cleaned.as_str(), ""),
+        };
+        let mut end = MAX_FILE_NAME_LEN - ext.len();
+        while !stem.is_char_boundary(end) {
+            end -= 1;
+        }
+        cleaned = format!("{}{}", &stem[..end], ext);
+    }
+    Ok(cleaned)
+}
+
+/// Split "name.ext" into "name" and ".ext"
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    }
+}
+
+/// Create a new file for `name` in `dir`, numbering the name if it is taken
+///
+/// The file is created exclusively, so an existing file or symlink is never
+/// written through.
+pub async fn create_file(dir: &Path, name: &str) -> Result<(PathBuf, tokio::fs::File), String> {
+    let (stem, ext) = split_extension(name);
+
+    for attempt in 0..MAX_NAME_ATTEMPTS {
+        let candidate = if attempt == 0 {
+            name.to_string()
+        } else {
+            format!("{} ({}){}", stem, attempt, ext)
+        };
+        let path = dir.join(&candidate);
+        if path.parent() != Some(dir) {
+            return Err(format!("Invalid file name '{}'", name));
+        }
+
+        match tokio::fs::OpenOptions::new().write(true).create_new(true).open(&path).await {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Failed to create {}: {}", path.display(), e)),
+        }
+    }
+    Err(format!("No free file name for '{}'", name))
+}
+
+/// Reports the progress of one upload request on the live channel
+pub struct Progress {
+    events: EventSender,
+    upload_id: String,
+    /// Request size, which slightly overestimates the file data
+    total_bytes: Option<u64>,
+    file_name: String,
+    received_bytes: u64,
+    reported_bytes: u64,
+}
+
+impl Progress {
+    pub fn new(events: EventSender, upload_id: String, total_bytes: Option<u64>) -> Self {
+        Self {
+            events,
+            upload_id,
+            total_bytes,
+            file_name: String::new(),
+            received_bytes: 0,
+            reported_bytes: 0,
+        }
+    }
+
+    pub fn received_bytes(&self) -> u64 {
+        self.received_bytes
+    }
+
+    /// Start reporting a new file of the request
+    pub fn start_file(&mut self, file_name: &str) {
+        self.file_name = file_name.to_string();
+        self.report(UploadStatus::Uploading, None);
+    }
+
+    pub fn add(&mut self, bytes: u64) {
+        self.received_bytes += bytes;
+        if self.received_bytes - self.reported_bytes >= PROGRESS_STEP {
+            self.report(UploadStatus::Uploading, None);
+        }
+    }
+
+    pub fn finish(&mut self, error: Option<String>) {
+        let status = if error.is_some() { UploadStatus::Failed } else { UploadStatus::Done };
+        self.report(status, error);
+    }
+
+    fn report(&mut self, status: UploadStatus, error: Option<String>) {
+        self.reported_bytes = self.received_bytes;
+        events::publish(
+            &self.events,
+            ServerEvent::Upload {
+                upload_id: self.upload_id.clone(),
+                file_name: self.file_name.clone(),
+                received_bytes: self.received_bytes,
+                total_bytes: self.total_bytes,
+                status,
+                error,
+            },
+        );
+    }
+}
+
+/// Id for an upload, keeping the phone's own if it is reasonable
+pub fn upload_id(requested: Option<String>) -> String {
+    requested
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= 64
+                && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .unwrap_or_else(|| format!("{:016x}", rand::random::<u64>()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_directory_parts() {
+        assert_eq!(sanitize_file_name("../../x").unwrap(), "x");
+        assert_eq!(sanitize_file_name("a\\..\\b").unwrap(), "b");
+        assert_eq!(sanitize_file_name("/etc/passwd").unwrap(), "passwd");
+        assert_eq!(sanitize_file_name("C:\\Windows\\win.ini").unwrap(), "win.ini");
+        assert!(sanitize_file_name("..").is_err());
+        assert!(sanitize_file_name("dir/..").is_err());
+        assert!(sanitize_file_name("dir/").is_err());
+        assert!(sanitize_file_name("").is_err());
+    }
+
+    #[test]
+    fn trims_dots_and_spaces() {
+        assert_eq!(sanitize_file_name(".hidden").unwrap(), "hidden");
+        assert_eq!(sanitize_file_name("report.pdf. . ").unwrap(), "report.pdf");
+        assert!(sanitize_file_name(" . ").is_err());
+    }
+
+    #[test]
+    fn prefixes_reserved_windows_names() {
+        assert_eq!(sanitize_file_name("CON.txt").unwrap(), "_CON.txt");
+        assert_eq!(sanitize_file_name("com1").unwrap(), "_com1");
+        assert_eq!(sanitize_file_name("lpt9.tar.gz").unwrap(), "_lpt9.tar.gz");
+        assert_eq!(sanitize_file_name("console.txt").unwrap(), "console.txt");
+        assert_eq!(sanitize_file_name("com10").unwrap(), "com10");
+    }
+
+    #[test]
+    fn removes_control_and_forbidden_characters() {
+        assert_eq!(sanitize_file_name("a\u{0}b\nc\u{7f}.txt").unwrap(), "abc.txt");
+        assert_eq!(sanitize_file_name("what?<is>:this*|\".txt").unwrap(), "whatisthis.txt");
+        assert!(sanitize_file_name("\u{1b}\u{7}").is_err());
+    }
+
+    #[test]
+    fn shortens_long_names_on_a_character_boundary() {
+        // Three-byte characters, so the cut falls inside one
+        let name = format!("{}.txt", "€".repeat(100));
+        let short = sanitize_file_name(&name).unwrap();
+        assert!(short.len() <= MAX_FILE_NAME_LEN, "{}", short.len());
+        assert!(short.ends_with(".txt"));
+        assert_eq!(short.trim_end_matches(".txt").chars().filter(|&c| c != '€').count(), 0);
+
+        // An extension too long to keep is cut with the rest
+        let name = format!("a.{}", "é".repeat(150));
+        let short = sanitize_file_name(&name).unwrap();
+        assert!(short.len() <= MAX_FILE_NAME_LEN);
+        assert!(short.starts_with("a.é"));
+    }
+
+    #[tokio::test]
+    async fn numbers_names_that_are_taken() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut names = Vec::new();
+        for _ in 0..3 {
+            let (path, _) = create_file(dir.path(), "photo.jpg").await.unwrap();
+            names.push(path.file_name().unwrap().to_string_lossy().into_owned());
+        }
+        assert_eq!(names, ["photo.jpg", "photo (1).jpg", "photo (2).jpg"]);
+
+        let (path, _) = create_file(dir.path(), "notes").await.unwrap();
+        assert_eq!(path, dir.path().join("notes"));
+        let (path, _) = create_file(dir.path(), "notes").await.unwrap();
+        assert_eq!(path, dir.path().join("notes (1)"));
+    }
+
+    #[tokio::test]
+    async fn refuses_names_leaving_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(create_file(dir.path(), "../escape.txt").await.is_err());
+        assert!(create_file(dir.path(), "sub/file.txt").await.is_err());
+        assert!(!dir.path().parent().unwrap().join("escape.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn never_writes_through_a_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let target = outside.path().join("target.txt");
+        std::fs::write(&target, "keep").unwrap();
+        std::os::unix::fs::symlink(&target, dir.path().join("link.txt")).unwrap();
+
+        let (path, _) = create_file(dir.path(), "link.txt").await.unwrap();
+        assert_eq!(path, dir.path().join("link (1).txt"));
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "keep");
+    }
+}
@@ -0,0 +1,441 @@
+//! Window listing and control through the Wayland compositor
+//!
+//! Wayland has no protocol for one client to see or focus another's windows,
+//! so each compositor is asked through its own IPC: `swaymsg` for Sway,
+//! `hyprctl` for Hyprland, and a KWin script for KDE Plasma, which reports
+//! back over D-Bus. GNOME Shell offers no such interface. Errors name the
+//! compositor so a failed fallback chain shows what was tried.
+
+use crate::mouse::run_tool;
+use crate::window_manager::{WindowBackend, WindowInfo};
+use serde_json::Value;
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long to wait for a KWin script to report back
+const KWIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the active window found by a KWin script is reused; every lookup
+/// loads a script, and the focus watcher asks several times a second
+const KWIN_ACTIVE_TTL: Duration = Duration::from_secs(2);
+
+/// Last active window reported by KWin and when
+static KWIN_ACTIVE: Mutex<Option<(Instant, WindowInfo)>> = Mutex::new(None);
+
+/// Object path our KWin scripts call back on
+const REPORT_PATH: &str = "/com/deckdot/WindowReport";
+const REPORT_INTERFACE: &str = "com.deckdot.WindowReport";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compositor {
+    Sway,
+    Hyprland,
+    Kde,
+    Gnome,
+    Unknown,
+}
+
+impl Compositor {
+    pub fn name(self) -> &'static str {
+        match self {
+            Compositor::Sway => "sway",
+            Compositor::Hyprland => "Hyprland",
+            Compositor::Kde => "KWin",
+            Compositor::Gnome => "GNOME Shell",
+            Compositor::Unknown => "Wayland compositor",
+        }
+    }
+}
+
+/// The compositor of the current session, from the variables it sets
+pub fn compositor() -> Compositor {
+    let env = |key: &str| std::env::var(key).unwrap_or_default();
+    let desktop = env("XDG_CURRENT_DESKTOP").to_lowercase();
+
+    if !env("SWAYSOCK").is_empty() {
+        Compositor::Sway
+    } else if !env("HYPRLAND_INSTANCE_SIGNATURE").is_empty() {
+        Compositor::Hyprland
+    } else if desktop.contains("kde") {
+        Compositor::Kde
+    } else if desktop.contains("gnome") {
+        Compositor::Gnome
+    } else {
+        Compositor::Unknown
+    }
+}
+
+/// A listed window and whether it has focus
+struct Listed {
+    window: WindowInfo,
+    focused: bool,
+}
+
+fn listed(compositor: Compositor) -> Result<Vec<Listed>, String> {
+    match compositor {
+        Compositor::Sway => sway_windows(),
+        Compositor::Hyprland => hyprland_windows(),
+        Compositor::Kde => kwin_windows(),
+        Compositor::Gnome | Compositor::Unknown => Err("no window IPC available".to_string()),
+    }
+}
+
+/// Prefix errors with the compositor they came from
+fn tagged<T>(compositor: Compositor, result: Result<T, String>) -> Result<T, String> {
+    result.map_err(|e| format!("{}: {}", compositor.name(), e))
+}
+
+pub fn list_windows() -> Result<Vec<WindowInfo>, String> {
+    let compositor = compositor();
+    tagged(compositor, listed(compositor)).map(|list| list.into_iter().map(|l| l.window).collect())
+}
+
+/// The focused window, from a single listing
+pub fn active_window() -> Result<WindowInfo, String> {
+    let compositor = compositor();
+    if compositor == Compositor::Kde {
+        if let Some((at, ref window)) = *KWIN_ACTIVE.lock().unwrap() {
+            if at.elapsed() < KWIN_ACTIVE_TTL {
+                return Ok(window.clone());
+            }
+        }
+    }
+
+    let active = listed(compositor).and_then(|list| {
+        list.into_iter()
+            .find(|l| l.focused)
+            .map(|l| l.window)
+            .ok_or_else(|| "no focused window".to_string())
+    });
+    let active = tagged(compositor, active)?;
+    if compositor == Compositor::Kde {
+        *KWIN_ACTIVE.lock().unwrap() = Some((Instant::now(), active.clone()));
+    }
+    Ok(active)
+}
+
+pub fn activate(id: &str) -> Result<(), String> {
+    let compositor = compositor();
+    // Focus is about to change, don't report the old window
+    KWIN_ACTIVE.lock().unwrap().take();
+    let result = match compositor {
+        Compositor::Sway => swaymsg(&format!("[con_id={}] focus", id)),
+        Compositor::Hyprland => hyprctl_dispatch(&["focuswindow", &format!("address:{}", id)]),
+        Compositor::Kde => kwin_window_action(
+            id,
+            "if (workspace.activeWindow !== undefined) { workspace.activeWindow = w; } else { workspace.activeClient = w; }",
+        ),
+        Compositor::Gnome | Compositor::Unknown => Err("no window IPC available".to_string()),
+    };
+    tagged(compositor, result)
+}
+
+pub fn close(id: &str) -> Result<(), String> {
+    let compositor = compositor();
+    let result = match compositor {
+        Compositor::Sway => swaymsg(&format!("[con_id={}] kill", id)),
+        Compositor::Hyprland => hyprctl_dispatch(&["closewindow", &format!("address:{}", id)]),
+        Compositor::Kde => kwin_window_action(id, "w.closeWindow();"),
+        Compositor::Gnome | Compositor::Unknown => Err("no window IPC available".to_string()),
+    };
+    tagged(compositor, result)
+}
+
+pub fn move_to_workspace(id: &str, workspace: u32) -> Result<(), String> {
+    let compositor = compositor();
+    // Both number workspaces from 1
+    let result = match compositor {
+        Compositor::Sway => swaymsg(&format!("[con_id={}] move container to workspace number {}", id, workspace + 1)),
+        Compositor::Hyprland => hyprctl_dispatch(&[
+            "movetoworkspacesilent",
+            &format!("{},address:{}", workspace + 1, id),
+        ]),
+        _ => Err("moving windows between workspaces is not supported".to_string()),
+    };
+    tagged(compositor, result)
+}
+
+pub fn minimize(id: &str) -> Result<(), String> {
+    let compositor = compositor();
+    let result = match compositor {
+        Compositor::Kde => kwin_window_action(id, "w.minimized = true;"),
+        _ => Err("minimizing is not supported".to_string()),
+    };
+    tagged(compositor, result)
+}
+
+pub fn maximize(id: &str) -> Result<(), String> {
+    let compositor = compositor();
+    let result = match compositor {
+        Compositor::Kde => kwin_window_action(id, "w.setMaximize(true, true);"),
+        _ => Err("maximizing is not supported".to_string()),
+    };
+    tagged(compositor, result)
+}
+
+/// Move and resize a window; tiled windows are made floating first
+pub fn set_geometry(id: &str, x: i32, y: i32, width: u32, height: u32) -> Result<(), String> {
+    let compositor = compositor();
+    let result = match compositor {
+        Compositor::Sway => swaymsg(&format!(
+            "[con_id={}] floating enable, resize set width {} px height {} px, move absolute position {} px {} px",
+            id, width, height, x, y
+        )),
+        Compositor::Hyprland => {
+            let window = format!("address:{}", id);
+            hyprctl_dispatch(&["setfloating", &window])
+                .and_then(|_| hyprctl_dispatch(&["resizewindowpixel", &format!("exact {} {},{}", width, height, window)]))
+                .and_then(|_| hyprctl_dispatch(&["movewindowpixel", &format!("exact {} {},{}", x, y, window)]))
+        }
+        Compositor::Kde => kwin_window_action(
+            id,
+            &format!(
+                "w.setMaximize(false, false); w.frameGeometry = {{x: {}, y: {}, width: {}, height: {}}};",
+                x, y, width, height
+            ),
+        ),
+        Compositor::Gnome | Compositor::Unknown => Err("no window IPC available".to_string()),
+    };
+    tagged(compositor, result)
+}
+
+/// Activate the first window whose title contains `name` (case-insensitive)
+pub fn activate_by_title(name: &str) -> Result<String, String> {
+    let needle = name.to_lowercase();
+    let window = list_windows()?
+        .into_iter()
+        .find(|w| w.title.to_lowercase().contains(&needle))
+        .ok_or_else(|| format!("{}: no window matching '{}'", compositor().name(), name))?;
+    activate(&window.id)?;
+    Ok(window.title)
+}
+
+fn number(value: &Value, key: &str) -> i64 {
+    value.get(key).and_then(Value::as_i64).unwrap_or(0)
+}
+
+fn string(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+fn swaymsg(command: &str) -> Result<(), String> {
+    let output = run_tool("swaymsg", &["--raw", command])?;
+    // Replies look like [{"success": false, "error": "..."}]
+    let replies: Vec<Value> = serde_json::from_str(&output).unwrap_or_default();
+    match replies.iter().find(|r| r.get("success") == Some(&Value::Bool(false))) {
+        Some(reply) => Err(string(reply, "error").unwrap_or_else(|| "command failed".to_string())),
+        None => Ok(()),
+    }
+}
+
+fn sway_windows() -> Result<Vec<Listed>, String> {
+    let output = run_tool("swaymsg", &["-t", "get_tree", "--raw"])?;
+    let tree: Value = serde_json::from_str(&output).map_err(|e| format!("unexpected tree: {}", e))?;
+    let mut windows = Vec::new();
+    collect_sway(&tree, None, &mut windows);
+    Ok(windows)
+}
+
+/// Walk the tree, remembering the workspace each window sits on
+fn collect_sway(node: &Value, workspace: Option<i64>, windows: &mut Vec<Listed>) {
+    let workspace = match node.get("type").and_then(Value::as_str) {
+        // Workspaces are numbered from 1; named ones without a number have none
+        Some("workspace") => node.get("num").and_then(Value::as_i64).filter(|&n| n > 0).map(|n| n - 1),
+        _ => workspace,
+    };
+
+    // Windows are the containers that belong to a client
+    if node.get("pid").and_then(Value::as_u64).is_some() {
+        let class = string(node, "app_id").or_else(|| node.get("window_properties").and_then(|p| string(p, "class")));
+        let rect = node.get("rect").cloned().unwrap_or_default();
+        windows.push(Listed {
+            window: WindowInfo {
+                id: number(node, "id").to_string(),
+                title: string(node, "name").unwrap_or_default(),
+                class,
+                pid: node.get("pid").and_then(Value::as_u64).map(|p| p as u32),
+                workspace,
+                x: number(&rect, "x") as i32,
+                y: number(&rect, "y") as i32,
+                width: number(&rect, "width") as u32,
+                height: number(&rect, "height") as u32,
+                backend: WindowBackend::Wayland,
+            },
+            focused: node.get("focused").and_then(Value::as_bool).unwrap_or(false),
+        });
+    }
+
+    for key in ["nodes", "floating_nodes"] {
+        if let Some(children) = node.get(key).and_then(Value::as_array) {
+            for child in children {
+                collect_sway(child, workspace, windows);
+            }
+        }
+    }
+}
+
+fn hyprctl_dispatch(args: &[&str]) -> Result<(), String> {
+    let mut all = vec!["dispatch"];
+    all.extend_from_slice(args);
+    // hyprctl exits successfully on errors too, only "ok" means it worked
+    let output = run_tool("hyprctl", &all)?;
+    match output.trim() {
+        "ok" => Ok(()),
+        error => Err(error.to_string()),
+    }
+}
+
+fn hyprland_windows() -> Result<Vec<Listed>, String> {
+    let output = run_tool("hyprctl", &["clients", "-j"])?;
+    let clients: Vec<Value> = serde_json::from_str(&output).map_err(|e| format!("unexpected client list: {}", e))?;
+    let pair = |client: &Value, key: &str| {
+        let values = client.get(key).and_then(Value::as_array);
+        let at = |i: usize| values.and_then(|v| v.get(i)).and_then(Value::as_i64).unwrap_or(0);
+        (at(0), at(1))
+    };
+
+    Ok(clients
+        .iter()
+        .filter(|c| c.get("mapped").and_then(Value::as_bool).unwrap_or(true))
+        .filter_map(|client| {
+            let (x, y) = pair(client, "at");
+            let (width, height) = pair(client, "size");
+            // Special workspaces (scratchpads) have negative ids
+            let workspace = client.get("workspace").map(|w| number(w, "id")).filter(|&id| id > 0);
+            Some(Listed {
+                window: WindowInfo {
+                    id: string(client, "address")?,
+                    title: string(client, "title").unwrap_or_default(),
+                    class: string(client, "class"),
+                    pid: client.get("pid").and_then(Value::as_i64).filter(|&p| p > 0).map(|p| p as u32),
+                    workspace: workspace.map(|id| id - 1),
+                    x: x as i32,
+                    y: y as i32,
+                    width: width as u32,
+                    height: height as u32,
+                    backend: WindowBackend::Wayland,
+                },
+                // The focus history starts with the focused window
+                focused: number(client, "focusHistoryID") == 0,
+            })
+        })
+        .collect())
+}
+
+/// Receives what our KWin scripts report
+struct Reporter(mpsc::Sender<String>);
+
+#[zbus::interface(name = "com.deckdot.WindowReport")]
+impl Reporter {
+    fn report(&self, json: String) {
+        let _ = self.0.send(json);
+    }
+}
+
+/// Run a KWin script that calls `report(string)` once, and return what it reported
+///
+/// KWin scripts can't return values, so they call back into a D-Bus object we
+/// serve for the duration of the script. Handles Plasma 5 and 6, which differ
+/// in the script object paths and parts of the scripting API.
+fn run_kwin_script(body: &str) -> Result<String, String> {
+    use zbus::blocking::{connection, Proxy};
+
+    let (tx, rx) = mpsc::channel();
+    let conn = connection::Builder::session()
+        .and_then(|b| b.serve_at(REPORT_PATH, Reporter(tx)))
+        .and_then(|b| b.build())
+        .map_err(|e| format!("Failed to connect to the session bus: {}", e))?;
+    let name = conn
+        .unique_name()
+        .map(|n| n.to_string())
+        .ok_or_else(|| "No D-Bus name".to_string())?;
+
+    let script = format!(
+        "function report(s) {{ callDBus('{}', '{}', '{}', 'report', s); }}\n\
+         const windows = workspace.windowList ? workspace.windowList() : workspace.clientList();\n{}",
+        name, REPORT_PATH, REPORT_INTERFACE, body
+    );
+    let plugin = format!("deck-{:08x}", rand::random::<u32>());
+    let path = std::env::temp_dir().join(format!("{}.js", plugin));
+    std::fs::write(&path, script).map_err(|e| format!("Failed to write KWin script: {}", e))?;
+
+    let kwin_error = |e: zbus::Error| format!("scripting failed: {}", e);
+    let scripting = Proxy::new(&conn, "org.kde.KWin", "/Scripting", "org.kde.kwin.Scripting").map_err(kwin_error);
+    let result = scripting.and_then(|scripting| {
+        let id: i32 = scripting
+            .call("loadScript", &(path.to_string_lossy().as_ref(), plugin.as_str()))
+            .map_err(kwin_error)?;
+        // Plasma 6 puts scripts under /Scripting, Plasma 5 at the root
+        let ran = [format!("/Scripting/Script{}", id), format!("/{}", id)]
+            .iter()
+            .any(|object| {
+                Proxy::new(&conn, "org.kde.KWin", object.as_str(), "org.kde.kwin.Script")
+                    .and_then(|script| script.call_method("run", &()))
+                    .is_ok()
+            });
+        if !ran {
+            return Err("failed to run script".to_string());
+        }
+        let reported = rx
+            .recv_timeout(KWIN_TIMEOUT)
+            .map_err(|_| "script did not report back".to_string());
+        let _ = scripting.call_method("unloadScript", &(plugin.as_str(),));
+        reported
+    });
+
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+fn kwin_windows() -> Result<Vec<Listed>, String> {
+    let script = r#"const active = workspace.activeWindow !== undefined ? workspace.activeWindow : workspace.activeClient;
+report(JSON.stringify(windows.filter(w => w.normalWindow).map(w => {
+  const g = w.frameGeometry || w.geometry;
+  const desktop = w.desktops ? (w.desktops.length === 1 ? w.desktops[0].x11DesktopNumber : 0) : w.desktop;
+  return {id: String(w.internalId), title: w.caption, class: w.resourceClass, pid: w.pid,
+    desktop: desktop, focused: w === active, x: Math.round(g.x), y: Math.round(g.y),
+    width: Math.round(g.width), height: Math.round(g.height)};
+})));"#;
+    let output = run_kwin_script(script)?;
+    let windows: Vec<Value> = serde_json::from_str(&output).map_err(|e| format!("unexpected window list: {}", e))?;
+
+    Ok(windows
+        .iter()
+        .filter_map(|w| {
+            Some(Listed {
+                window: WindowInfo {
+                    id: string(w, "id")?,
+                    title: string(w, "title").unwrap_or_default(),
+                    class: string(w, "class"),
+                    pid: w.get("pid").and_then(Value::as_i64).filter(|&p| p > 0).map(|p| p as u32),
+                    // Desktops are numbered from 1, and -1 or 0 mean all of them
+                    workspace: w.get("desktop").and_then(Value::as_i64).filter(|&d| d > 0).map(|d| d - 1),
+                    x: number(w, "x") as i32,
+                    y: number(w, "y") as i32,
+                    width: number(w, "width") as u32,
+                    height: number(w, "height") as u32,
+                    backend: WindowBackend::Wayland,
+                },
+                focused: w.get("focused").and_then(Value::as_bool).unwrap_or(false),
+            })
+        })
+        .collect())
+}
+
+/// Run `action` on the window with `id`, which is available to it as `w`
+fn kwin_window_action(id: &str, action: &str) -> Result<(), String> {
+    let id = serde_json::to_string(id).map_err(|e| e.to_string())?;
+    let script = format!(
+        "const w = windows.find(w => String(w.internalId) === {});\n\
+         if (w) {{ {} report('ok'); }} else {{ report('missing'); }}",
+        id, action
+    );
+    match run_kwin_script(&script)?.as_str() {
+        "ok" => Ok(()),
+        _ => Err("window not found".to_string()),
+    }
+}
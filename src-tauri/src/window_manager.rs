@@ -0,0 +1,745 @@
+//! Listing and arranging application windows
+//!
+//! Linux goes through the EWMH hints of the window manager, natively with
+//! `wmctrl` as a fallback (both only see X11 and XWayland windows); Wayland
+//! sessions ask the compositor first, see `wayland`. Each window remembers
+//! which of the two listed it and is only controlled through that one. macOS
+//! scripts System Events through JavaScript for Automation, and Windows calls
+//! user32 directly.
+//! Layout geometry is computed here from the monitors in `mouse`, so every
+//! backend only has to move and resize.
+
+use crate::commands::{WindowAction, WindowLayout, WindowOperation};
+use crate::mouse::{self, Monitor};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// What a window was listed through, and so what its id means
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowBackend {
+    /// The platform window system: X11 (including XWayland), macOS or Windows
+    #[default]
+    Native,
+    /// The IPC of a Wayland compositor; the id is only valid there
+    Wayland,
+}
+
+/// A top-level window and its position in desktop coordinates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowInfo {
+    /// Backend-specific id, stable for the lifetime of the window
+    pub id: String,
+    pub title: String,
+    /// Window class (Linux, Windows) or application name (macOS)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Workspace number starting at 0; `None` when on all workspaces or unknown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<i64>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub backend: WindowBackend,
+}
+
+impl WindowInfo {
+    fn center(&self) -> (i32, i32) {
+        (self.x + self.width as i32 / 2, self.y + self.height as i32 / 2)
+    }
+}
+
+/// What a window target or `focus_app` value matches against titles and classes
+///
+/// A value wrapped in slashes like `/^Slack \|/` is a regular expression,
+/// anything else matches as a case-insensitive substring.
+pub enum TitlePattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl TitlePattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+            Some(expr) if !expr.is_empty() => Regex::new(expr)
+                .map(TitlePattern::Regex)
+                .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e)),
+            _ if pattern.trim().is_empty() => Err("Empty window title".to_string()),
+            _ => Ok(TitlePattern::Substring(pattern.to_lowercase())),
+        }
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            TitlePattern::Substring(needle) => text.to_lowercase().contains(needle.as_str()),
+            TitlePattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+
+    /// Whether this pattern is exactly the given title (for substrings)
+    fn is_exactly(&self, text: &str) -> bool {
+        matches!(self, TitlePattern::Substring(needle) if text.to_lowercase() == *needle)
+    }
+}
+
+/// Pick the window for a pattern: an exact title first, then a title match, then a class match
+pub fn find_window<'a>(windows: &'a [WindowInfo], pattern: &TitlePattern) -> Option<&'a WindowInfo> {
+    windows
+        .iter()
+        .find(|w| pattern.is_exactly(&w.title))
+        .or_else(|| windows.iter().find(|w| pattern.matches(&w.title)))
+        .or_else(|| {
+            windows
+                .iter()
+                .find(|w| w.class.as_deref().is_some_and(|c| pattern.matches(c)))
+        })
+}
+
+/// List the top-level windows, in the window manager's stacking or creation order
+pub fn list_windows() -> Result<Vec<WindowInfo>, String> {
+    backend::list()
+}
+
+/// The focused window
+pub fn active_window() -> Result<WindowInfo, String> {
+    backend::active()
+}
+
+/// A listed window by id, for backends that can only name the active window
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn listed(windows: Vec<WindowInfo>, id: &str) -> Result<WindowInfo, String> {
+    windows
+        .into_iter()
+        .find(|w| w.id == id)
+        .ok_or_else(|| "The active window is not a listed window".to_string())
+}
+
+/// Raise and focus a listed window
+pub fn focus(window: &WindowInfo) -> Result<(), String> {
+    backend::focus(window)
+}
+
+/// Run a window action and describe what was done
+pub fn perform(action: &WindowAction) -> Result<String, String> {
+    action.validate()?;
+    let window = select(action)?;
+
+    match action.action {
+        WindowOperation::Focus => {
+            backend::focus(&window)?;
+            Ok(format!("Focused: {}", window.title))
+        }
+        WindowOperation::Minimize => {
+            backend::minimize(&window)?;
+            Ok(format!("Minimized: {}", window.title))
+        }
+        WindowOperation::Maximize => {
+            backend::maximize(&window)?;
+            Ok(format!("Maximized: {}", window.title))
+        }
+        WindowOperation::Close => {
+            backend::close(&window)?;
+            Ok(format!("Closed: {}", window.title))
+        }
+        WindowOperation::Layout => {
+            let layout = action.layout.ok_or("No layout specified")?;
+            let monitors = mouse::get_monitors()?;
+            let monitor = match action.monitor {
+                Some(ref name) => mouse::find_monitor(&monitors, Some(name))?,
+                None => monitor_of(&window, &monitors)?,
+            };
+            let (x, y, width, height) = layout_geometry(layout, monitor);
+            backend::set_geometry(&window, x, y, width, height)?;
+            Ok(format!("Arranged {} on {}", window.title, monitor.name))
+        }
+        WindowOperation::MoveToWorkspace => {
+            let workspace = action.workspace.ok_or("No workspace specified")?;
+            backend::move_to_workspace(&window, workspace)?;
+            Ok(format!("Moved {} to workspace {}", window.title, workspace))
+        }
+        WindowOperation::MoveToMonitor => {
+            let monitors = mouse::get_monitors()?;
+            let from = monitor_of(&window, &monitors)?;
+            let to = mouse::find_monitor(&monitors, action.monitor.as_deref())?;
+            let (x, y, width, height) = move_geometry(&window, from, to);
+            backend::set_geometry(&window, x, y, width, height)?;
+            Ok(format!("Moved {} to {}", window.title, to.name))
+        }
+    }
+}
+
+/// Pick the window by id, then by target, falling back to the active window
+fn select(action: &WindowAction) -> Result<WindowInfo, String> {
+    match (&action.window_id, &action.target) {
+        (Some(id), _) => list_windows()?
+            .into_iter()
+            .find(|w| w.id.eq_ignore_ascii_case(id))
+            .ok_or_else(|| format!("No window with id '{}'", id)),
+        (None, Some(target)) => {
+            let pattern = TitlePattern::parse(target)?;
+            find_window(&list_windows()?, &pattern)
+                .cloned()
+                .ok_or_else(|| format!("No window matching '{}'", target))
+        }
+        (None, None) => active_window(),
+    }
+}
+
+/// Monitor that holds the center of the window, or the nearest one
+fn monitor_of<'a>(window: &WindowInfo, monitors: &'a [Monitor]) -> Result<&'a Monitor, String> {
+    let (cx, cy) = window.center();
+    monitors
+        .iter()
+        .find(|m| m.contains(cx, cy))
+        .or_else(|| {
+            monitors.iter().min_by_key(|m| {
+                let dx = (m.x as i64 + m.width as i64 / 2) - cx as i64;
+                let dy = (m.y as i64 + m.height as i64 / 2) - cy as i64;
+                dx * dx + dy * dy
+            })
+        })
+        .ok_or_else(|| "No monitors found".to_string())
+}
+
+/// Position and size of a layout on a monitor
+fn layout_geometry(layout: WindowLayout, monitor: &Monitor) -> (i32, i32, u32, u32) {
+    let (x, y, w, h) = (monitor.x, monitor.y, monitor.width, monitor.height);
+    let (half_w, half_h) = (w / 2, h / 2);
+    let (right, bottom) = (x + half_w as i32, y + half_h as i32);
+
+    match layout {
+        WindowLayout::LeftHalf => (x, y, half_w, h),
+        WindowLayout::RightHalf => (right, y, w - half_w, h),
+        WindowLayout::TopHalf => (x, y, w, half_h),
+        WindowLayout::BottomHalf => (x, bottom, w, h - half_h),
+        WindowLayout::TopLeft => (x, y, half_w, half_h),
+        WindowLayout::TopRight => (right, y, w - half_w, half_h),
+        WindowLayout::BottomLeft => (x, bottom, half_w, h - half_h),
+        WindowLayout::BottomRight => (right, bottom, w - half_w, h - half_h),
+        WindowLayout::Center => {
+            let (cw, ch) = (w * 2 / 3, h * 2 / 3);
+            (x + ((w - cw) / 2) as i32, y + ((h - ch) / 2) as i32, cw, ch)
+        }
+        WindowLayout::Fill => (x, y, w, h),
+    }
+}
+
+/// Keep the window's offset on its monitor, shrinking it to fit the new one
+fn move_geometry(window: &WindowInfo, from: &Monitor, to: &Monitor) -> (i32, i32, u32, u32) {
+    let width = window.width.min(to.width);
+    let height = window.height.min(to.height);
+    let max_x = (to.width - width) as i32;
+    let max_y = (to.height - height) as i32;
+    let x = to.x + (window.x - from.x).clamp(0, max_x);
+    let y = to.y + (window.y - from.y).clamp(0, max_y);
+    (x, y, width, height)
+}
+
+#[cfg(target_os = "linux")]
+mod backend {
+    use super::{WindowBackend, WindowInfo};
+    use crate::mouse::run_tool;
+    use crate::system_commands::is_wayland_session;
+    use crate::wayland;
+    use crate::x11::{self, with_fallback};
+
+    /// On Wayland the compositor is asked first; X11 then only reaches XWayland windows
+    fn wayland_first<T>(
+        native: impl FnOnce() -> Result<T, String>,
+        x11: impl FnOnce() -> Result<T, String>,
+    ) -> Result<T, String> {
+        if !is_wayland_session() {
+            return x11();
+        }
+        native().or_else(|wayland_err| x11().map_err(|x11_err| format!("{}; {}", wayland_err, x11_err)))
+    }
+
+    /// Control a window through whatever listed it, as its id means nothing to the other
+    fn by_backend<T>(
+        window: &WindowInfo,
+        wayland: impl FnOnce() -> Result<T, String>,
+        x11: impl FnOnce() -> Result<T, String>,
+    ) -> Result<T, String> {
+        match window.backend {
+            WindowBackend::Wayland => wayland(),
+            WindowBackend::Native => x11(),
+        }
+    }
+
+    pub fn list() -> Result<Vec<WindowInfo>, String> {
+        wayland_first(wayland::list_windows, list_x11)
+    }
+
+    fn list_x11() -> Result<Vec<WindowInfo>, String> {
+        with_fallback(x11::list_windows, || {
+            let output = run_tool("wmctrl", &["-l", "-p", "-G", "-x"])?;
+            Ok(output.lines().filter_map(parse_wmctrl_line).collect())
+        })
+    }
+
+    /// Parse a line like "0x03a00007  0 4242  10 40 800 600  navigator.Firefox  host Title"
+    fn parse_wmctrl_line(line: &str) -> Option<WindowInfo> {
+        let (fields, title) = split_fields(line, 9)?;
+        let desktop: i64 = fields[1].parse().ok()?;
+        let pid: u32 = fields[2].parse().ok()?;
+
+        Some(WindowInfo {
+            id: normalize_id(fields[0])?,
+            title: title.to_string(),
+            class: wm_class(fields[7]),
+            pid: (pid != 0).then_some(pid),
+            // -1 means the window is shown on every desktop
+            workspace: (desktop >= 0).then_some(desktop),
+            x: fields[3].parse().ok()?,
+            y: fields[4].parse().ok()?,
+            width: fields[5].parse().ok()?,
+            height: fields[6].parse().ok()?,
+            backend: WindowBackend::Native,
+        })
+    }
+
+    /// Split off `count` whitespace-separated fields, keeping the rest as is
+    fn split_fields(line: &str, count: usize) -> Option<(Vec<&str>, &str)> {
+        let mut fields = Vec::with_capacity(count);
+        let mut rest = line.trim_start();
+        while fields.len() < count {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if end == 0 {
+                return None;
+            }
+            fields.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+        Some((fields, rest))
+    }
+
+    /// Class part of "instance.Class"; both parts may contain dots themselves
+    fn wm_class(field: &str) -> Option<String> {
+        if field == "N/A" {
+            return None;
+        }
+        let class = field
+            .match_indices('.')
+            .map(|(i, _)| (&field[..i], &field[i + 1..]))
+            .find(|(instance, class)| instance.eq_ignore_ascii_case(class))
+            .or_else(|| field.split_once('.'))
+            .map_or(field, |(_, class)| class);
+        Some(class.to_string())
+    }
+
+    /// Window ids are printed in hex or decimal depending on the tool
+    fn normalize_id(id: &str) -> Option<String> {
+        let value = match id.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+            None => id.parse().ok()?,
+        };
+        Some(format!("0x{:08x}", value))
+    }
+
+    pub fn active() -> Result<WindowInfo, String> {
+        wayland_first(wayland::active_window, || {
+            let id = with_fallback(x11::active_window, || {
+                // "_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007"
+                let output = run_tool("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?;
+                output
+                    .rsplit_once('#')
+                    .and_then(|(_, id)| id.split(',').next())
+                    .and_then(|id| normalize_id(id.trim()))
+                    .filter(|id| id != "0x00000000")
+                    .ok_or_else(|| "No active window".to_string())
+            })?;
+            super::listed(list_x11()?, &id)
+        })
+    }
+
+    fn wmctrl(window: &WindowInfo, args: &[&str]) -> Result<(), String> {
+        let mut all = vec!["-i", "-r", window.id.as_str()];
+        all.extend_from_slice(args);
+        run_tool("wmctrl", &all).map(|_| ())
+    }
+
+    pub fn focus(window: &WindowInfo) -> Result<(), String> {
+        by_backend(
+            window,
+            || wayland::activate(&window.id),
+            || {
+                with_fallback(
+                    || x11::activate(&window.id),
+                    // Switches to the window's desktop, raises and focuses it
+                    || run_tool("wmctrl", &["-i", "-a", &window.id]).map(|_| ()),
+                )
+            },
+        )
+    }
+
+    pub fn minimize(window: &WindowInfo) -> Result<(), String> {
+        by_backend(
+            window,
+            || wayland::minimize(&window.id),
+            || {
+                with_fallback(
+                    || x11::minimize(&window.id),
+                    // EWMH has no request to iconify, xdotool sends the ICCCM one
+                    || run_tool("xdotool", &["windowminimize", &window.id]).map(|_| ()),
+                )
+            },
+        )
+    }
+
+    pub fn maximize(window: &WindowInfo) -> Result<(), String> {
+        by_backend(
+            window,
+            || wayland::maximize(&window.id),
+            || {
+                with_fallback(
+                    || x11::maximize(&window.id),
+                    || wmctrl(window, &["-b", "add,maximized_vert,maximized_horz"]),
+                )
+            },
+        )
+    }
+
+    pub fn close(window: &WindowInfo) -> Result<(), String> {
+        by_backend(
+            window,
+            || wayland::close(&window.id),
+            || {
+                with_fallback(
+                    || x11::close(&window.id),
+                    || run_tool("wmctrl", &["-i", "-c", &window.id]).map(|_| ()),
+                )
+            },
+        )
+    }
+
+    pub fn set_geometry(window: &WindowInfo, x: i32, y: i32, width: u32, height: u32) -> Result<(), String> {
+        by_backend(
+            window,
+            || wayland::set_geometry(&window.id, x, y, width, height),
+            || {
+                with_fallback(
+                    || x11::set_geometry(&window.id, x, y, width, height),
+                    || {
+                        // Maximized windows ignore move requests
+                        wmctrl(window, &["-b", "remove,maximized_vert,maximized_horz"])?;
+                        wmctrl(window, &["-e", &format!("0,{},{},{},{}", x, y, width, height)])
+                    },
+                )
+            },
+        )
+    }
+
+    pub fn move_to_workspace(window: &WindowInfo, workspace: u32) -> Result<(), String> {
+        by_backend(
+            window,
+            || wayland::move_to_workspace(&window.id, workspace),
+            || {
+                with_fallback(
+                    || x11::move_to_desktop(&window.id, workspace),
+                    || wmctrl(window, &["-t", &workspace.to_string()]),
+                )
+            },
+        )
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod backend {
+    use super::WindowInfo;
+    use crate::mouse::run_jxa;
+
+    /// Helpers for the CoreGraphics window list, whose window numbers are
+    /// stable; System Events has no ids, so its windows are matched to those
+    /// by owner and frame
+    const CG_PRELUDE: &str = r#"ObjC.import('CoreGraphics');
+function cgWindows(option, relativeTo) {
+  return ObjC.deepUnwrap(ObjC.castRefToObject($.CGWindowListCopyWindowInfo(option, relativeTo))) || [];
+}
+function sameFrame(c, pos, size) {
+  const b = c.kCGWindowBounds;
+  return Math.round(b.X) === pos[0] && Math.round(b.Y) === pos[1]
+    && Math.round(b.Width) === size[0] && Math.round(b.Height) === size[1];
+}
+"#;
+
+    /// Windows are identified by their CoreGraphics window number
+    pub fn list() -> Result<Vec<WindowInfo>, String> {
+        let script = format!(
+            r#"{}const cg = cgWindows($.kCGWindowListOptionAll | $.kCGWindowListExcludeDesktopElements, $.kCGNullWindowID)
+  .filter(c => c.kCGWindowLayer === 0);
+const used = {{}};
+const out = [];
+Application('System Events').applicationProcesses.whose({{backgroundOnly: false}})().forEach(p => {{
+  const pid = p.unixId();
+  const name = p.name();
+  p.windows().forEach(w => {{
+    const pos = w.position();
+    const size = w.size();
+    const c = cg.find(c => c.kCGWindowOwnerPID === pid && !used[c.kCGWindowNumber] && sameFrame(c, pos, size));
+    if (!c) return;
+    used[c.kCGWindowNumber] = true;
+    out.push({{id: String(c.kCGWindowNumber), title: w.name() || '', class: name, pid: pid,
+      x: pos[0], y: pos[1], width: size[0], height: size[1]}});
+  }});
+}});
+JSON.stringify(out);"#,
+            CG_PRELUDE
+        );
+        let output = run_jxa(&script)?;
+        serde_json::from_str(&output).map_err(|e| format!("Failed to parse window list: {}", e))
+    }
+
+    pub fn active() -> Result<WindowInfo, String> {
+        // On-screen windows come front to back, so the first one of the frontmost app is active
+        let script = format!(
+            r#"{}const pid = Application('System Events').applicationProcesses.whose({{frontmost: true}})[0].unixId();
+const c = cgWindows($.kCGWindowListOptionOnScreenOnly, $.kCGNullWindowID)
+  .find(c => c.kCGWindowOwnerPID === pid && c.kCGWindowLayer === 0);
+c ? String(c.kCGWindowNumber) : '';"#,
+            CG_PRELUDE
+        );
+        super::listed(list()?, &run_jxa(&script)?)
+    }
+
+    /// Run `body` with `p` (the process) and `w` (the window) defined
+    fn with_window(window: &WindowInfo, body: &str) -> Result<(), String> {
+        let number: u32 = window
+            .id
+            .parse()
+            .map_err(|_| format!("Invalid window id '{}'", window.id))?;
+        let script = format!(
+            r#"{}const c = cgWindows($.kCGWindowListOptionIncludingWindow, {})[0];
+if (!c) throw new Error('The window is gone');
+const p = Application('System Events').applicationProcesses.whose({{unixId: c.kCGWindowOwnerPID}})[0];
+const w = p.windows().find(w => sameFrame(c, w.position(), w.size()));
+if (!w) throw new Error('The window is gone');
+{}"#,
+            CG_PRELUDE, number, body
+        );
+        run_jxa(&script).map(|_| ())
+    }
+
+    pub fn focus(window: &WindowInfo) -> Result<(), String> {
+        with_window(window, "w.actions['AXRaise'].perform();\np.frontmost = true;")
+    }
+
+    pub fn minimize(window: &WindowInfo) -> Result<(), String> {
+        with_window(window, "w.attributes['AXMinimized'].value = true;")
+    }
+
+    pub fn maximize(window: &WindowInfo) -> Result<(), String> {
+        // macOS has no maximized state; zooming to full size is the closest
+        let monitors = crate::mouse::get_monitors()?;
+        let monitor = super::monitor_of(window, &monitors)?;
+        set_geometry(window, monitor.x, monitor.y, monitor.width, monitor.height)
+    }
+
+    pub fn close(window: &WindowInfo) -> Result<(), String> {
+        with_window(window, "w.buttons.whose({subrole: 'AXCloseButton'})[0].click();")
+    }
+
+    pub fn set_geometry(window: &WindowInfo, x: i32, y: i32, width: u32, height: u32) -> Result<(), String> {
+        with_window(
+            window,
+            &format!("w.position = [{}, {}];\nw.size = [{}, {}];", x, y, width, height),
+        )
+    }
+
+    pub fn move_to_workspace(_window: &WindowInfo, _workspace: u32) -> Result<(), String> {
+        Err("Moving windows between Spaces is not supported on macOS".to_string())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod backend {
+    use super::{WindowBackend, WindowInfo};
+    use windows::core::BOOL;
+    use windows::Win32::Foundation::{HWND, LPARAM, RECT, WPARAM};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        EnumWindows, GetClassNameW, GetForegroundWindow, GetWindow, GetWindowLongW, GetWindowRect,
+        GetWindowTextW, GetWindowThreadProcessId, IsIconic, IsWindowVisible, IsZoomed, PostMessageW,
+        SetForegroundWindow, SetWindowPos, ShowWindow, GWL_EXSTYLE, GW_OWNER, SWP_NOACTIVATE,
+        SWP_NOZORDER, SW_MAXIMIZE, SW_MINIMIZE, SW_RESTORE, WM_CLOSE, WS_EX_TOOLWINDOW,
+    };
+
+    fn format_id(hwnd: HWND) -> String {
+        format!("0x{:08x}", hwnd.0 as usize)
+    }
+
+    fn parse_id(id: &str) -> Result<HWND, String> {
+        let value = id
+            .strip_prefix("0x")
+            .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("Invalid window id '{}'", id))?;
+        Ok(HWND(value as *mut _))
+    }
+
+    fn utf16(buffer: &[u16], len: i32) -> String {
+        String::from_utf16_lossy(&buffer[..len.max(0) as usize])
+    }
+
+    /// Describe a window if it is one the taskbar would show
+    unsafe fn describe(hwnd: HWND) -> Option<WindowInfo> {
+        if !IsWindowVisible(hwnd).as_bool() || GetWindow(hwnd, GW_OWNER).is_ok_and(|owner| !owner.is_invalid()) {
+            return None;
+        }
+        if GetWindowLongW(hwnd, GWL_EXSTYLE) as u32 & WS_EX_TOOLWINDOW.0 != 0 {
+            return None;
+        }
+
+        let mut title = [0u16; 512];
+        let len = GetWindowTextW(hwnd, &mut title);
+        if len <= 0 {
+            return None;
+        }
+        let mut class = [0u16; 256];
+        let class_len = GetClassNameW(hwnd, &mut class);
+        let mut pid = 0u32;
+        GetWindowThreadProcessId(hwnd, Some(&mut pid));
+        let mut rect = RECT::default();
+        GetWindowRect(hwnd, &mut rect).ok()?;
+
+        Some(WindowInfo {
+            id: format_id(hwnd),
+            title: utf16(&title, len),
+            class: (class_len > 0).then(|| utf16(&class, class_len)),
+            pid: (pid != 0).then_some(pid),
+            workspace: None,
+            x: rect.left,
+            y: rect.top,
+            width: (rect.right - rect.left).max(0) as u32,
+            height: (rect.bottom - rect.top).max(0) as u32,
+            backend: WindowBackend::Native,
+        })
+    }
+
+    pub fn list() -> Result<Vec<WindowInfo>, String> {
+        unsafe extern "system" fn collect(hwnd: HWND, data: LPARAM) -> BOOL {
+            let windows = &mut *(data.0 as *mut Vec<WindowInfo>);
+            if let Some(window) = describe(hwnd) {
+                windows.push(window);
+            }
+            BOOL::from(true)
+        }
+
+        let mut windows: Vec<WindowInfo> = Vec::new();
+        unsafe {
+            EnumWindows(Some(collect), LPARAM(&mut windows as *mut _ as isize))
+                .map_err(|e| format!("Failed to list windows: {:?}", e))?;
+        }
+        Ok(windows)
+    }
+
+    pub fn active() -> Result<WindowInfo, String> {
+        let hwnd = unsafe { GetForegroundWindow() };
+        if hwnd.is_invalid() {
+            return Err("No active window".to_string());
+        }
+        unsafe { describe(hwnd) }.ok_or_else(|| "The active window is not a listed window".to_string())
+    }
+
+    pub fn focus(window: &WindowInfo) -> Result<(), String> {
+        let hwnd = parse_id(&window.id)?;
+        unsafe {
+            if IsIconic(hwnd).as_bool() {
+                let _ = ShowWindow(hwnd, SW_RESTORE);
+            }
+            if !SetForegroundWindow(hwnd).as_bool() {
+                return Err(format!("Windows refused to focus '{}'", window.title));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn minimize(window: &WindowInfo) -> Result<(), String> {
+        let hwnd = parse_id(&window.id)?;
+        unsafe {
+            let _ = ShowWindow(hwnd, SW_MINIMIZE);
+        }
+        Ok(())
+    }
+
+    pub fn maximize(window: &WindowInfo) -> Result<(), String> {
+        let hwnd = parse_id(&window.id)?;
+        unsafe {
+            let _ = ShowWindow(hwnd, SW_MAXIMIZE);
+        }
+        Ok(())
+    }
+
+    pub fn close(window: &WindowInfo) -> Result<(), String> {
+        let hwnd = parse_id(&window.id)?;
+        // Ask politely, like the close button; the app may still prompt
+        unsafe {
+            PostMessageW(Some(hwnd), WM_CLOSE, WPARAM(0), LPARAM(0))
+                .map_err(|e| format!("Failed to close '{}': {:?}", window.title, e))
+        }
+    }
+
+    pub fn set_geometry(window: &WindowInfo, x: i32, y: i32, width: u32, height: u32) -> Result<(), String> {
+        let hwnd = parse_id(&window.id)?;
+        unsafe {
+            // Maximized and minimized windows would snap back on restore
+            if IsZoomed(hwnd).as_bool() || IsIconic(hwnd).as_bool() {
+                let _ = ShowWindow(hwnd, SW_RESTORE);
+            }
+            SetWindowPos(
+                hwnd,
+                None,
+                x,
+                y,
+                width as i32,
+                height as i32,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            )
+            .map_err(|e| format!("Failed to move '{}': {:?}", window.title, e))
+        }
+    }
+
+    pub fn move_to_workspace(_window: &WindowInfo, _workspace: u32) -> Result<(), String> {
+        Err("Moving windows between virtual desktops is not supported on Windows".to_string())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+mod backend {
+    use super::WindowInfo;
+
+    const UNSUPPORTED: &str = "Window control not supported on this platform";
+
+    pub fn list() -> Result<Vec<WindowInfo>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn active() -> Result<WindowInfo, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn focus(_: &WindowInfo) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn minimize(_: &WindowInfo) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn maximize(_: &WindowInfo) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn close(_: &WindowInfo) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn set_geometry(_: &WindowInfo, _: i32, _: i32, _: u32, _: u32) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn move_to_workspace(_: &WindowInfo, _: u32) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+}
@@ -1,33 +1,57 @@
-#[cfg(target_os = "windows")]
+//! Focusing a window by title, for the legacy `focus_app` field, and reading
+//! the foreground window on Windows
+
+use crate::window_manager::{self, TitlePattern};
+
+/// Bring the window matching `title` to the front
+///
+/// Goes through `window_manager`, so it uses EWMH (or the Wayland compositor)
+/// on Linux, System Events on macOS and user32 on Windows.
 pub fn focus_window_by_title(title: &str) -> Result<(), String> {
-    use windows::Win32::UI::WindowsAndMessaging::{FindWindowA, SetForegroundWindow, ShowWindow, SW_RESTORE};
-    use windows::core::PCSTR;
-    use std::ffi::CString;
+    let pattern = TitlePattern::parse(title)?;
+    let windows = window_manager::list_windows()?;
+    let window = window_manager::find_window(&windows, &pattern).ok_or_else(|| format!("No window matching '{}'", title))?;
+    window_manager::focus(window)
+}
 
-    let title_cstr = CString::new(title)
-        .map_err(|e| format!("Invalid window title: {}", e))?;
+/// Get the title and process name of the foreground window
+#[cfg(target_os = "windows")]
+pub fn get_foreground_window() -> Option<crate::system_commands::FocusedApp> {
+    use windows::Win32::Foundation::CloseHandle;
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowTextW, GetWindowThreadProcessId};
+    use windows::core::PWSTR;
 
     unsafe {
-        let hwnd = FindWindowA(PCSTR::null(), PCSTR(title_cstr.as_ptr() as *const u8))
-            .map_err(|e| format!("Failed to find window: {:?}", e))?;
-        
+        let hwnd = GetForegroundWindow();
         if hwnd.0.is_null() {
-            return Err(format!("Window with title '{}' not found", title));
+            return None;
         }
 
-        let _ = ShowWindow(hwnd, SW_RESTORE);
-        SetForegroundWindow(hwnd)
-            .ok()
-            .map_err(|e| format!("Failed to set foreground window: {:?}", e))?;
+        let mut title_buf = [0u16; 512];
+        let len = GetWindowTextW(hwnd, &mut title_buf);
+        let title = String::from_utf16_lossy(&title_buf[..len.max(0) as usize]);
 
-        Ok(())
-    }
-}
+        let mut pid = 0u32;
+        GetWindowThreadProcessId(hwnd, Some(&mut pid as *mut u32));
 
-#[cfg(not(target_os = "windows"))]
-pub fn focus_window_by_title(_title: &str) -> Result<(), String> {
-    // Focus logic for other platforms can be implemented here
-    // For now, just return Ok() as a no-op
-    Ok(())
-}
+        let mut app_name = String::new();
+        if let Ok(process) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) {
+            let mut path_buf = [0u16; 1024];
+            let mut size = path_buf.len() as u32;
+            if QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(path_buf.as_mut_ptr()), &mut size).is_ok() {
+                let path = String::from_utf16_lossy(&path_buf[..size as usize]);
+                // Keep just the executable name without extension, e.g. "Figma"
+                app_name = std::path::Path::new(&path)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or(path);
+            }
+            let _ = CloseHandle(process);
+        }
 
+        Some(crate::system_commands::FocusedApp { app_name, title })
+    }
+}
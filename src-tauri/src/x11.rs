@@ -0,0 +1,639 @@
+//! Native X11 access: EWMH window management and XTest key and pointer events
+//!
+//! Talks to the display in `DISPLAY` directly instead of running `wmctrl` or
+//! `xdotool`, so it also works against a virtual display like Xvfb (given a
+//! window manager for the EWMH parts). Callers keep the command-line tools as
+//! a fallback, see [`with_fallback`].
+
+use crate::window_manager::{WindowBackend, WindowInfo};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask, Window, KEY_PRESS_EVENT,
+    KEY_RELEASE_EVENT, MOTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::xtest::ConnectionExt as _;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+/// `_NET_WM_DESKTOP` value of windows shown on every desktop
+const ALL_DESKTOPS: u32 = 0xFFFF_FFFF;
+
+/// Source indication telling the window manager a pager asked, which it obeys
+/// more readily than requests from applications
+const SOURCE_PAGER: u32 = 2;
+
+/// ICCCM `IconicState`
+const ICONIC_STATE: u32 = 3;
+
+/// `_NET_WM_STATE` actions
+const STATE_REMOVE: u32 = 0;
+const STATE_ADD: u32 = 1;
+
+/// Display kept open for pointer motion, which arrives many times a second
+static POINTER_DISPLAY: Mutex<Option<Display>> = Mutex::new(None);
+
+/// Run the native implementation, then the tool if it failed, reporting both errors
+pub fn with_fallback<T>(
+    native: impl FnOnce() -> Result<T, String>,
+    tool: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    native().or_else(|native_err| tool().map_err(|tool_err| format!("X11: {}; {}", native_err, tool_err)))
+}
+
+/// An open display with the atoms we use
+struct Display {
+    conn: RustConnection,
+    root: Window,
+    /// Atoms interned so far, so reading every window doesn't repeat the round trips
+    atoms: RefCell<HashMap<&'static str, Atom>>,
+}
+
+impl Display {
+    fn open() -> Result<Self, String> {
+        let (conn, screen) = x11rb::connect(None).map_err(|e| format!("Can't open display: {}", e))?;
+        let root = conn.setup().roots[screen].root;
+        Ok(Self { conn, root, atoms: RefCell::default() })
+    }
+
+    fn atom(&self, name: &'static str) -> Result<Atom, String> {
+        if let Some(&atom) = self.atoms.borrow().get(name) {
+            return Ok(atom);
+        }
+        let atom = self
+            .conn
+            .intern_atom(false, name.as_bytes())
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map(|r| r.atom)
+            .map_err(|e| format!("Failed to look up {}: {}", name, e))?;
+        self.atoms.borrow_mut().insert(name, atom);
+        Ok(atom)
+    }
+
+    fn property(&self, window: Window, name: &'static str, kind: impl Into<Atom>) -> Result<Vec<u8>, String> {
+        let property = self.atom(name)?;
+        self.conn
+            .get_property(false, window, property, kind, 0, u32::MAX)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map(|r| r.value)
+            .map_err(|e| format!("Failed to read {}: {}", name, e))
+    }
+
+    fn property32(&self, window: Window, name: &'static str, kind: impl Into<Atom>) -> Result<Vec<u32>, String> {
+        let property = self.atom(name)?;
+        let reply = self
+            .conn
+            .get_property(false, window, property, kind, 0, u32::MAX)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        Ok(reply.value32().map(|values| values.collect()).unwrap_or_default())
+    }
+
+    /// Ask the window manager for something through a client message to the root window
+    fn request(&self, window: Window, name: &'static str, data: [u32; 5]) -> Result<(), String> {
+        let event = ClientMessageEvent::new(32, window, self.atom(name)?, data);
+        self.conn
+            .send_event(
+                false,
+                self.root,
+                EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+                event,
+            )
+            .map_err(|e| e.to_string())?;
+        self.conn.flush().map_err(|e| e.to_string())
+    }
+
+    /// Windows managed by the window manager, oldest first
+    fn client_list(&self) -> Result<Vec<Window>, String> {
+        let windows = self.property32(self.root, "_NET_CLIENT_LIST", AtomEnum::WINDOW)?;
+        if windows.is_empty() && self.property32(self.root, "_NET_SUPPORTED", AtomEnum::ATOM)?.is_empty() {
+            return Err("No EWMH-compliant window manager is running".to_string());
+        }
+        Ok(windows)
+    }
+
+    fn title(&self, window: Window) -> Result<String, String> {
+        let utf8 = self.atom("UTF8_STRING")?;
+        let mut title = self.property(window, "_NET_WM_NAME", utf8)?;
+        if title.is_empty() {
+            title = self.property(window, "WM_NAME", AtomEnum::ANY)?;
+        }
+        Ok(String::from_utf8_lossy(&title).into_owned())
+    }
+
+    fn describe(&self, window: Window) -> Result<WindowInfo, String> {
+        // WM_CLASS holds "instance\0Class\0"
+        let class = self.property(window, "WM_CLASS", AtomEnum::STRING)?;
+        let class = class
+            .split(|&b| b == 0)
+            .filter(|part| !part.is_empty())
+            .nth(1)
+            .map(|part| String::from_utf8_lossy(part).into_owned());
+        let pid = self.property32(window, "_NET_WM_PID", AtomEnum::CARDINAL)?.first().copied();
+        let desktop = self.property32(window, "_NET_WM_DESKTOP", AtomEnum::CARDINAL)?.first().copied();
+
+        let geometry = self
+            .conn
+            .get_geometry(window)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| format!("Failed to read window geometry: {}", e))?;
+        let position = self
+            .conn
+            .translate_coordinates(window, self.root, 0, 0)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| format!("Failed to read window position: {}", e))?;
+
+        Ok(WindowInfo {
+            id: format_id(window),
+            title: self.title(window)?,
+            class,
+            pid: pid.filter(|&pid| pid != 0),
+            workspace: desktop.filter(|&d| d != ALL_DESKTOPS).map(i64::from),
+            x: position.dst_x as i32,
+            y: position.dst_y as i32,
+            width: geometry.width as u32,
+            height: geometry.height as u32,
+            backend: WindowBackend::Native,
+        })
+    }
+
+    fn set_state(&self, window: Window, action: u32, first: &'static str, second: &'static str) -> Result<(), String> {
+        let data = [action, self.atom(first)?, self.atom(second)?, SOURCE_PAGER, 0];
+        self.request(window, "_NET_WM_STATE", data)
+    }
+}
+
+/// Window ids are shown like wmctrl does, so both backends agree
+fn format_id(window: Window) -> String {
+    format!("0x{:08x}", window)
+}
+
+fn parse_id(id: &str) -> Result<Window, String> {
+    id.strip_prefix("0x")
+        .and_then(|hex| Window::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| format!("Invalid window id '{}'", id))
+}
+
+/// List the managed windows, skipping any that close while being read
+pub fn list_windows() -> Result<Vec<WindowInfo>, String> {
+    let display = Display::open()?;
+    Ok(display
+        .client_list()?
+        .into_iter()
+        .filter_map(|window| display.describe(window).ok())
+        .collect())
+}
+
+/// Id of the focused window
+pub fn active_window() -> Result<String, String> {
+    let display = Display::open()?;
+    display
+        .property32(display.root, "_NET_ACTIVE_WINDOW", AtomEnum::WINDOW)?
+        .first()
+        .filter(|&&window| window != 0)
+        .map(|&window| format_id(window))
+        .ok_or_else(|| "No active window".to_string())
+}
+
+/// Switch to the window's desktop, raise and focus it
+pub fn activate(id: &str) -> Result<(), String> {
+    let display = Display::open()?;
+    let window = parse_id(id)?;
+
+    let desktop = display.property32(window, "_NET_WM_DESKTOP", AtomEnum::CARDINAL)?;
+    if let Some(&desktop) = desktop.first().filter(|&&d| d != ALL_DESKTOPS) {
+        display.request(display.root, "_NET_CURRENT_DESKTOP", [desktop, 0, 0, 0, 0])?;
+    }
+    display.request(window, "_NET_ACTIVE_WINDOW", [SOURCE_PAGER, 0, 0, 0, 0])
+}
+
+/// Activate the first window whose title contains `name` (case-insensitive), like `wmctrl -a`
+pub fn activate_by_title(name: &str) -> Result<String, String> {
+    let needle = name.to_lowercase();
+    let window = list_windows()?
+        .into_iter()
+        .find(|w| w.title.to_lowercase().contains(&needle))
+        .ok_or_else(|| format!("No window matching '{}'", name))?;
+    activate(&window.id)?;
+    Ok(window.title)
+}
+
+pub fn minimize(id: &str) -> Result<(), String> {
+    let display = Display::open()?;
+    display.request(parse_id(id)?, "WM_CHANGE_STATE", [ICONIC_STATE, 0, 0, 0, 0])
+}
+
+pub fn maximize(id: &str) -> Result<(), String> {
+    let display = Display::open()?;
+    display.set_state(parse_id(id)?, STATE_ADD, "_NET_WM_STATE_MAXIMIZED_VERT", "_NET_WM_STATE_MAXIMIZED_HORZ")
+}
+
+pub fn close(id: &str) -> Result<(), String> {
+    let display = Display::open()?;
+    display.request(parse_id(id)?, "_NET_CLOSE_WINDOW", [0, SOURCE_PAGER, 0, 0, 0])
+}
+
+pub fn set_geometry(id: &str, x: i32, y: i32, width: u32, height: u32) -> Result<(), String> {
+    let display = Display::open()?;
+    let window = parse_id(id)?;
+    // Maximized windows ignore move requests
+    display.set_state(window, STATE_REMOVE, "_NET_WM_STATE_MAXIMIZED_VERT", "_NET_WM_STATE_MAXIMIZED_HORZ")?;
+
+    // Default gravity, with x, y, width and height all present
+    let flags = (1 << 8) | (1 << 9) | (1 << 10) | (1 << 11) | (SOURCE_PAGER << 12);
+    display.request(
+        window,
+        "_NET_MOVERESIZE_WINDOW",
+        [flags, x as u32, y as u32, width, height],
+    )
+}
+
+pub fn move_to_desktop(id: &str, desktop: u32) -> Result<(), String> {
+    let display = Display::open()?;
+    display.request(parse_id(id)?, "_NET_WM_DESKTOP", [desktop, SOURCE_PAGER, 0, 0, 0])
+}
+
+/// Press a key combination like "ctrl+shift+t" through XTest
+///
+/// Fails for keys that aren't on the current keyboard layout, so the caller
+/// can fall back to xdotool, which remaps a spare keycode for them.
+pub fn send_keys(keybind: &str) -> Result<(), String> {
+    let display = Display::open()?;
+    let conn = &display.conn;
+    conn.xtest_get_version(2, 2)
+        .map_err(|e| e.to_string())?
+        .reply()
+        .map_err(|e| format!("XTest extension unavailable: {}", e))?;
+
+    let setup = conn.setup();
+    let (min, max) = (setup.min_keycode, setup.max_keycode);
+    let mapping = conn
+        .get_keyboard_mapping(min, max - min + 1)
+        .map_err(|e| e.to_string())?
+        .reply()
+        .map_err(|e| format!("Failed to read keyboard mapping: {}", e))?;
+    let per_keycode = mapping.keysyms_per_keycode.max(1) as usize;
+
+    // Keycode of a keysym, and whether it needs Shift on that key
+    let lookup = |keysym: u32| {
+        mapping
+            .keysyms
+            .chunks(per_keycode)
+            .enumerate()
+            .find_map(|(i, syms)| {
+                let column = syms.iter().take(2).position(|&s| s == keysym)?;
+                Some((min + i as u8, column == 1))
+            })
+    };
+    let shift = lookup(keysym("shift").unwrap_or_default());
+
+    let mut keycodes = Vec::new();
+    for part in keybind.split('+').map(str::trim) {
+        let sym = keysym(part).ok_or_else(|| format!("Unknown key '{}'", part))?;
+        let (keycode, shifted) = lookup(sym).ok_or_else(|| format!("Key '{}' is not on the keyboard layout", part))?;
+        if shifted {
+            if let Some((shift_code, _)) = shift {
+                if !keycodes.contains(&shift_code) {
+                    keycodes.push(shift_code);
+                }
+            }
+        }
+        keycodes.push(keycode);
+    }
+
+    let fake = |kind: u8, keycode: u8| {
+        conn.xtest_fake_input(kind, keycode, 0, display.root, 0, 0, 0)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to send key event: {}", e))
+    };
+    for &keycode in &keycodes {
+        fake(KEY_PRESS_EVENT, keycode)?;
+    }
+    for &keycode in keycodes.iter().rev() {
+        fake(KEY_RELEASE_EVENT, keycode)?;
+    }
+    conn.sync().map_err(|e| e.to_string())
+}
+
+/// Move the pointer by an offset through XTest, reusing one connection
+pub fn move_pointer_by(dx: i32, dy: i32) -> Result<(), String> {
+    let mut slot = POINTER_DISPLAY.lock().unwrap();
+    let display = match slot.take() {
+        Some(display) => display,
+        None => Display::open()?,
+    };
+    let clamp = |d: i32| d.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+
+    // A detail of 1 makes the motion relative
+    display
+        .conn
+        .xtest_fake_input(MOTION_NOTIFY_EVENT, 1, 0, x11rb::NONE, clamp(dx), clamp(dy), 0)
+        .map_err(|e| format!("Failed to move the pointer: {}", e))?;
+    display.conn.flush().map_err(|e| e.to_string())?;
+    // Only a working connection is kept, a broken one is reopened next time
+    *slot = Some(display);
+    Ok(())
+}
+
+/// Keysym for a key name as used in keybinds
+fn keysym(name: &str) -> Option<u32> {
+    let lower = name.to_lowercase();
+    let sym = match lower.as_str() {
+        "ctrl" | "control" => 0xffe3,
+        "shift" => 0xffe1,
+        "alt" => 0xffe9,
+        "cmd" | "command" | "super" | "win" => 0xffeb,
+        "enter" | "return" => 0xff0d,
+        "tab" => 0xff09,
+        "escape" | "esc" => 0xff1b,
+        "space" => 0x0020,
+        "backspace" => 0xff08,
+        "delete" => 0xffff,
+        "insert" => 0xff63,
+        "home" => 0xff50,
+        "end" => 0xff57,
+        "pageup" => 0xff55,
+        "pagedown" => 0xff56,
+        "left" => 0xff51,
+        "up" => 0xff52,
+        "right" => 0xff53,
+        "down" => 0xff54,
+        "print" => 0xff61,
+        "menu" => 0xff67,
+        "plus" => 0x002b,
+        "minus" => 0x002d,
+        "xf86audioplay" => 0x1008ff14,
+        "xf86audiostop" => 0x1008ff15,
+        "xf86audioprev" => 0x1008ff16,
+        "xf86audionext" => 0x1008ff17,
+        "xf86audiomute" => 0x1008ff12,
+        "xf86audiolowervolume" => 0x1008ff11,
+        "xf86audioraisevolume" => 0x1008ff13,
+        _ => {
+            if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u32>().ok()) {
+                return (1..=24).contains(&n).then(|| 0xffbe + n - 1);
+            }
+            let mut chars = lower.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            // Latin-1 keysyms equal the code point, the rest use the Unicode range
+            return Some(match c as u32 {
+                cp @ (0x20..=0x7e | 0xa0..=0xff) => cp,
+                cp => 0x0100_0000 + cp,
+            });
+        }
+    };
+    Some(sym)
+}
+
+/// Private Xvfb servers for tests
+#[cfg(test)]
+pub mod xvfb {
+    use std::path::Path;
+    use std::process::{Child, Command, Stdio};
+    use std::sync::{Mutex, MutexGuard};
+    use std::time::{Duration, Instant};
+
+    /// `DISPLAY` is process-wide, so tests take turns
+    static DISPLAY_LOCK: Mutex<()> = Mutex::new(());
+
+    /// A running server that `DISPLAY` points at, stopped when dropped
+    pub struct Xvfb {
+        server: Child,
+        _turn: MutexGuard<'static, ()>,
+    }
+
+    impl Xvfb {
+        /// Start a server with a single screen, `None` when Xvfb isn't installed
+        pub fn start(width: u16, height: u16) -> Option<Self> {
+            let turn = DISPLAY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let number = (90..200).find(|n| {
+                !Path::new(&format!("/tmp/.X{}-lock", n)).exists()
+                    && !Path::new(&format!("/tmp/.X11-unix/X{}", n)).exists()
+            })?;
+            let mut server = Command::new("Xvfb")
+                .arg(format!(":{}", number))
+                .args(["-screen", "0", &format!("{}x{}x24", width, height), "-nolisten", "tcp"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+
+            let socket = format!("/tmp/.X11-unix/X{}", number);
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !Path::new(&socket).exists() {
+                if Instant::now() > deadline || matches!(server.try_wait(), Ok(Some(_))) {
+                    let _ = server.kill();
+                    let _ = server.wait();
+                    return None;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+
+            std::env::set_var("DISPLAY", format!(":{}", number));
+            std::env::remove_var("WAYLAND_DISPLAY");
+            std::env::remove_var("XDG_SESSION_TYPE");
+            Some(Self { server, _turn: turn })
+        }
+    }
+
+    impl Drop for Xvfb {
+        fn drop(&mut self) {
+            let _ = self.server.kill();
+            let _ = self.server.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::xvfb::Xvfb;
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+    use x11rb::protocol::xproto::{
+        ChangeWindowAttributesAux, ConfigureWindowAux, CreateWindowAux, InputFocus, PropMode, StackMode, WindowClass,
+    };
+    use x11rb::protocol::Event;
+    use x11rb::{COPY_DEPTH_FROM_PARENT, CURRENT_TIME};
+
+    /// Poll until `check` gives a value, for things the server does asynchronously
+    fn wait_for<T>(mut check: impl FnMut() -> Option<T>) -> T {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(value) = check() {
+                return value;
+            }
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn intern(conn: &RustConnection, name: &str) -> Atom {
+        conn.intern_atom(false, name.as_bytes()).unwrap().reply().unwrap().atom
+    }
+
+    /// Just enough of a window manager for EWMH: maps windows, keeps
+    /// `_NET_CLIENT_LIST` and follows `_NET_ACTIVE_WINDOW` requests
+    fn run_window_manager(ready: mpsc::Sender<()>) {
+        let (conn, screen) = x11rb::connect(None).unwrap();
+        let root = conn.setup().roots[screen].root;
+        let supported = intern(&conn, "_NET_SUPPORTED");
+        let client_list = intern(&conn, "_NET_CLIENT_LIST");
+        let active = intern(&conn, "_NET_ACTIVE_WINDOW");
+
+        let mask = EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY;
+        conn.change_window_attributes(root, &ChangeWindowAttributesAux::new().event_mask(mask))
+            .unwrap()
+            .check()
+            .unwrap();
+        conn.change_property32(PropMode::REPLACE, root, supported, AtomEnum::ATOM, &[client_list, active])
+            .unwrap();
+        conn.flush().unwrap();
+        ready.send(()).unwrap();
+
+        // Ends when the server goes away
+        let mut clients: Vec<Window> = Vec::new();
+        while let Ok(event) = conn.wait_for_event() {
+            match event {
+                Event::MapRequest(e) => {
+                    let _ = conn.map_window(e.window);
+                    clients.push(e.window);
+                }
+                Event::DestroyNotify(e) => clients.retain(|&w| w != e.window),
+                Event::ClientMessage(e) if e.type_ == active => {
+                    let _ = conn.configure_window(e.window, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE));
+                    let _ = conn.set_input_focus(InputFocus::PARENT, e.window, CURRENT_TIME);
+                    let _ = conn.change_property32(PropMode::REPLACE, root, active, AtomEnum::WINDOW, &[e.window]);
+                }
+                _ => continue,
+            }
+            let _ = conn.change_property32(PropMode::REPLACE, root, client_list, AtomEnum::WINDOW, &clients);
+            let _ = conn.flush();
+        }
+    }
+
+    /// Map a top-level window with the properties applications usually set
+    fn open_window(conn: &RustConnection, title: &str, class: &str, x: i16, y: i16) -> Window {
+        let root = conn.setup().roots[0].root;
+        let window = conn.generate_id().unwrap();
+        let aux = CreateWindowAux::new().event_mask(EventMask::KEY_PRESS | EventMask::KEY_RELEASE);
+        conn.create_window(COPY_DEPTH_FROM_PARENT, window, root, x, y, 200, 100, 0, WindowClass::INPUT_OUTPUT, 0, &aux)
+            .unwrap();
+
+        let utf8 = intern(conn, "UTF8_STRING");
+        let net_wm_name = intern(conn, "_NET_WM_NAME");
+        let net_wm_pid = intern(conn, "_NET_WM_PID");
+        let wm_class = format!("{}\0{}\0", class.to_lowercase(), class);
+        conn.change_property8(PropMode::REPLACE, window, net_wm_name, utf8, title.as_bytes()).unwrap();
+        conn.change_property8(PropMode::REPLACE, window, AtomEnum::WM_CLASS, AtomEnum::STRING, wm_class.as_bytes())
+            .unwrap();
+        conn.change_property32(PropMode::REPLACE, window, net_wm_pid, AtomEnum::CARDINAL, &[4242]).unwrap();
+        conn.map_window(window).unwrap();
+        conn.sync().unwrap();
+        window
+    }
+
+    #[test]
+    fn send_keys_through_xtest() {
+        let Some(_xvfb) = Xvfb::start(320, 240) else {
+            eprintln!("Xvfb not available, skipping");
+            return;
+        };
+        let (conn, _) = x11rb::connect(None).unwrap();
+        let window = open_window(&conn, "Keys", "Keys", 0, 0);
+        conn.set_input_focus(InputFocus::PARENT, window, CURRENT_TIME).unwrap();
+        conn.sync().unwrap();
+
+        send_keys("ctrl+shift+t").unwrap();
+
+        // First keysym of every keycode, to tell which keys were pressed
+        let setup = conn.setup();
+        let (min, max) = (setup.min_keycode, setup.max_keycode);
+        let mapping = conn.get_keyboard_mapping(min, max - min + 1).unwrap().reply().unwrap();
+        let per_keycode = mapping.keysyms_per_keycode as usize;
+        let keysym_of = |keycode: u8| mapping.keysyms[(keycode - min) as usize * per_keycode];
+
+        let mut events = Vec::new();
+        wait_for(|| {
+            while let Some(event) = conn.poll_for_event().unwrap() {
+                match event {
+                    Event::KeyPress(e) => events.push((true, keysym_of(e.detail))),
+                    Event::KeyRelease(e) => events.push((false, keysym_of(e.detail))),
+                    _ => {}
+                }
+            }
+            (events.len() >= 6).then_some(())
+        });
+        assert_eq!(
+            events,
+            [(true, 0xffe3), (true, 0xffe1), (true, 0x74), (false, 0x74), (false, 0xffe1), (false, 0xffe3)]
+        );
+
+        assert!(send_keys("ctrl+nosuchkey").unwrap_err().contains("Unknown key 'nosuchkey'"));
+        assert!(send_keys("ctrl+é").unwrap_err().contains("not on the keyboard layout"));
+    }
+
+    #[test]
+    fn list_and_activate_with_window_manager() {
+        let Some(_xvfb) = Xvfb::start(640, 480) else {
+            eprintln!("Xvfb not available, skipping");
+            return;
+        };
+        let (ready_tx, ready) = mpsc::channel();
+        std::thread::spawn(move || run_window_manager(ready_tx));
+        ready.recv().unwrap();
+
+        let (conn, _) = x11rb::connect(None).unwrap();
+        let editor = open_window(&conn, "notes.txt - Editor", "Editor", 10, 20);
+        let terminal = open_window(&conn, "Terminal", "Terminal", 300, 200);
+
+        let windows = wait_for(|| list_windows().ok().filter(|w| w.len() == 2));
+        let info = &windows[0];
+        assert_eq!(info.id, format_id(editor));
+        assert_eq!(info.title, "notes.txt - Editor");
+        assert_eq!(info.class.as_deref(), Some("Editor"));
+        assert_eq!(info.pid, Some(4242));
+        assert_eq!(info.workspace, None);
+        assert_eq!((info.x, info.y, info.width, info.height), (10, 20, 200, 100));
+        assert_eq!(info.backend, WindowBackend::Native);
+        assert_eq!(windows[1].id, format_id(terminal));
+
+        activate(&format_id(terminal)).unwrap();
+        wait_for(|| (active_window().ok()? == format_id(terminal)).then_some(()));
+
+        assert_eq!(activate_by_title("EDITOR").unwrap(), "notes.txt - Editor");
+        wait_for(|| (active_window().ok()? == format_id(editor)).then_some(()));
+
+        assert!(activate("editor").unwrap_err().contains("Invalid window id"));
+        assert!(activate_by_title("browser").unwrap_err().contains("No window matching"));
+    }
+
+    #[test]
+    fn fallback_reports_both_errors() {
+        let Some(_xvfb) = Xvfb::start(320, 240) else {
+            eprintln!("Xvfb not available, skipping");
+            return;
+        };
+
+        // Without a window manager the native listing fails
+        let err = with_fallback(list_windows, || Err::<Vec<WindowInfo>, _>("wmctrl: not found".to_string()))
+            .unwrap_err();
+        assert_eq!(err, "X11: No EWMH-compliant window manager is running; wmctrl: not found");
+
+        let windows = with_fallback(list_windows, || Ok(Vec::new())).unwrap();
+        assert!(windows.is_empty());
+
+        let keys = with_fallback(|| send_keys("ctrl+c"), || panic!("tool used although XTest worked"));
+        assert_eq!(keys, Ok(()));
+    }
+}